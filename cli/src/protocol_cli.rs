@@ -23,6 +23,7 @@ use spl_associated_token_account::{
     get_associated_token_address,
 };
 use std::path::Path;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 
 const USAGE: &str = "
 MEGASWAP TRADING TERMINAL:
@@ -35,6 +36,10 @@ Summary and usage of available commands.
     megaswap-cli withdraw --token <TOKEN TO WITHDRAW> --amount <AMOUNT TO WITHDRAW>
 3. Swaping or Trading.
     megaswap-cli swap --deposit --token <TOKEN TO DEPOSIT> --amount <AMOUNT TO SWAP>
+4. Valuing LP tokens.
+    megaswap-cli lp-value
+5. Inspecting the invariant solver.
+    megaswap-cli solver-diagnostics [--amount <AMOUNT TO SWAP>] [--y-to-x]
 ";
 
 const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
@@ -76,6 +81,10 @@ enum Commands {
     Swap(SwapArgs),
     // Argument for initializing trades.
     Trade(TradeInitArgs),
+    // Displaying the value of one LP token.
+    LpValue,
+    // Displaying the Newton solver's convergence for the pool, optionally for a swap.
+    SolverDiagnostics(SolverDiagnosticsArgs),
 }
 
 #[derive(Args, Debug)]
@@ -112,6 +121,16 @@ pub struct SwapArgs {
     amount: u64,
 }
 
+#[derive(Args, Debug)]
+pub struct SolverDiagnosticsArgs {
+    // Swap amount to diagnose, 0 for the invariant D only.
+    #[arg(short, long, default_value_t = 0)]
+    amount: u64,
+    // Swapping token y for token x instead of x for y.
+    #[arg(long)]
+    y_to_x: bool,
+}
+
 fn load_or_create_wallet(path: &str) -> anyhow::Result<Keypair> {
     let path_obj = Path::new(path);
 
@@ -148,6 +167,9 @@ pub fn ensure_protocol_ready(seed: u64, fee: u16) -> anyhow::Result<()> {
     let (mint_lp, lp_bump) = Pubkey::find_program_address(
         &[b"lp_mint", config_pda.as_ref()], &program_id
     );
+    let (observations, _) = Pubkey::find_program_address(
+        &[b"observations", config_pda.as_ref()], &program_id
+    );
     let rpc_client = RpcClient::new(config.rpc_url);
     let account = rpc_client.get_account_with_commitment(
         &config_pda, CommitmentConfig::confirmed(),
@@ -216,6 +238,7 @@ pub fn ensure_protocol_ready(seed: u64, fee: u16) -> anyhow::Result<()> {
                 AccountMeta::new_readonly(spl_associated_token_account::id(), false),
                 AccountMeta::new_readonly(Pubkey::from_str(SYSTEM_PROGRAM_ID)?, false),
                 AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(observations, false),
             ];
             let init_ix = Instruction {
                 program_id: program_id,
@@ -242,6 +265,123 @@ pub fn ensure_protocol_ready(seed: u64, fee: u16) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Simulates GetVirtualPrice and prints the value of one LP token in units of D.
+pub fn show_lp_value() -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let program_id = Pubkey::from_str(&config.megaswap_protocol_program_id)?;
+    let (config_pda, _) = Pubkey::find_program_address(
+        &[b"config"], &program_id,
+    );
+    let (mint_lp, _) = Pubkey::find_program_address(
+        &[b"lp_mint", config_pda.as_ref()], &program_id
+    );
+    let mint_x = read_keypair_file("wallets/mint_x.json").map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mint_y = read_keypair_file("wallets/mint_y.json").map_err(|e| anyhow::anyhow!("{}", e))?;
+    let payer = read_keypair_file(
+        shellexpand::tilde("~/.config/solana/id.json").to_string()
+    ).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let rpc_client = RpcClient::new(config.rpc_url);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(config_pda, false),
+        AccountMeta::new_readonly(mint_lp, false),
+        AccountMeta::new_readonly(get_associated_token_address(&config_pda, &mint_x.pubkey()), false),
+        AccountMeta::new_readonly(get_associated_token_address(&config_pda, &mint_y.pubkey()), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    let ix = Instruction {
+        program_id: program_id,
+        accounts: accounts,
+        data: vec![7u8], // GetVirtualPrice
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix], Some(&payer.pubkey()), &[&payer], rpc_client.get_latest_blockhash()?,
+    );
+    let result = rpc_client.simulate_transaction(&tx)?.value;
+    if let Some(err) = result.err {
+        return Err(anyhow::anyhow!("Virtual price query failed: {:?}", err));
+    }
+    let return_data = result.return_data
+        .ok_or_else(|| anyhow::anyhow!("Virtual price query returned no data"))?;
+    let bytes: [u8; 16] = BASE64.decode(&return_data.data.0)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Unexpected virtual price length"))?;
+    let virtual_price = u128::from_le_bytes(bytes);
+    println!(
+        "One LP token is worth {}.{:018} units of D",
+        virtual_price / 1_000_000_000_000_000_000,
+        virtual_price % 1_000_000_000_000_000_000,
+    );
+    Ok(())
+}
+
+// Prints one solve of the GetSolverDiagnostics return data,
+// value u128, iterations u16, residual u128, converged u8.
+fn print_convergence(label: &str, bytes: &[u8]) {
+    let value = u128::from_le_bytes(bytes[0..16].try_into().unwrap());
+    let iterations = u16::from_le_bytes(bytes[16..18].try_into().unwrap());
+    let residual = u128::from_le_bytes(bytes[18..34].try_into().unwrap());
+    let converged = bytes[34] == 1;
+    println!(
+        "{}: value {}, {} iterations, residual {}, {}",
+        label, value, iterations, residual, if converged { "converged" } else { "NOT converged" },
+    );
+}
+
+// Simulates GetSolverDiagnostics and prints the solver outcome for D and, with an amount, the swap.
+pub fn show_solver_diagnostics(args: &SolverDiagnosticsArgs) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let program_id = Pubkey::from_str(&config.megaswap_protocol_program_id)?;
+    let (config_pda, _) = Pubkey::find_program_address(
+        &[b"config"], &program_id,
+    );
+    let (mint_lp, _) = Pubkey::find_program_address(
+        &[b"lp_mint", config_pda.as_ref()], &program_id
+    );
+    let mint_x = read_keypair_file("wallets/mint_x.json").map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mint_y = read_keypair_file("wallets/mint_y.json").map_err(|e| anyhow::anyhow!("{}", e))?;
+    let payer = read_keypair_file(
+        shellexpand::tilde("~/.config/solana/id.json").to_string()
+    ).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let rpc_client = RpcClient::new(config.rpc_url);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(config_pda, false),
+        AccountMeta::new_readonly(mint_lp, false),
+        AccountMeta::new_readonly(get_associated_token_address(&config_pda, &mint_x.pubkey()), false),
+        AccountMeta::new_readonly(get_associated_token_address(&config_pda, &mint_y.pubkey()), false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    let mut data = vec![11u8]; // GetSolverDiagnostics
+    if args.amount > 0 {
+        data.extend_from_slice(&args.amount.to_le_bytes());
+        data.push(!args.y_to_x as u8);
+    }
+    let ix = Instruction {
+        program_id: program_id,
+        accounts: accounts,
+        data: data,
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix], Some(&payer.pubkey()), &[&payer], rpc_client.get_latest_blockhash()?,
+    );
+    let result = rpc_client.simulate_transaction(&tx)?.value;
+    if let Some(err) = result.err {
+        return Err(anyhow::anyhow!("Solver diagnostics query failed: {:?}", err));
+    }
+    let return_data = result.return_data
+        .ok_or_else(|| anyhow::anyhow!("Solver diagnostics query returned no data"))?;
+    let bytes = BASE64.decode(&return_data.data.0)?;
+    if bytes.len() != 71 {
+        return Err(anyhow::anyhow!("Unexpected solver diagnostics length"));
+    }
+    print_convergence("D", &bytes[0..35]);
+    if bytes[35] == 1 {
+        print_convergence("y", &bytes[36..71]);
+    }
+    Ok(())
+}
+
 pub fn process_cli() {
     let cli = Cli::parse();
 
@@ -268,6 +408,16 @@ pub fn process_cli() {
         },
         Commands::Swap(args) => {
             println!("Swapping or trading liquidity {:?}", args);
+        },
+        Commands::LpValue => {
+            if let Err(e) = show_lp_value() {
+                eprintln!("Error reading the LP value: {}", e);
+            }
+        },
+        Commands::SolverDiagnostics(args) => {
+            if let Err(e) = show_solver_diagnostics(args) {
+                eprintln!("Error reading the solver diagnostics: {}", e);
+            }
         }
     }
 }
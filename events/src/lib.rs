@@ -0,0 +1,527 @@
+//! Events emitted by the MegaSwap protocol through `sol_log_data`.
+//! The program encodes them, indexers decode the base64 payload of `Program data:` log lines.
+//!
+//! Every event starts with a two byte header, the event discriminator followed by the
+//! layout version. All integers are little endian, addresses are raw 32 byte keys.
+#![no_std]
+
+// Current layout version. Bumped whenever a field is added or changes meaning.
+pub const EVENT_VERSION: u8 = 2;
+pub const HEADER_LEN: usize = 2;
+
+pub type Key = [u8; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // No bytes to decode.
+    Empty,
+    // Discriminator not emitted by this protocol.
+    UnknownEvent(u8),
+    // Layout version this decoder does not read.
+    UnsupportedVersion(u8),
+    // Payload shorter or longer than the event layout.
+    InvalidLength,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    PoolInitialized = 0,
+    Deposit = 1,
+    Swap = 2,
+    Withdraw = 3,
+    ConfigChanged = 4,
+    Skim = 5,
+    Sync = 6,
+    Donate = 7,
+    FlashLoan = 8,
+}
+
+impl TryFrom<u8> for EventKind {
+    type Error = DecodeError;
+    fn try_from(discriminator: u8) -> Result<Self, Self::Error> {
+        match discriminator {
+            0 => Ok(EventKind::PoolInitialized),
+            1 => Ok(EventKind::Deposit),
+            2 => Ok(EventKind::Swap),
+            3 => Ok(EventKind::Withdraw),
+            4 => Ok(EventKind::ConfigChanged),
+            5 => Ok(EventKind::Skim),
+            6 => Ok(EventKind::Sync),
+            7 => Ok(EventKind::Donate),
+            8 => Ok(EventKind::FlashLoan),
+            other => Err(DecodeError::UnknownEvent(other)),
+        }
+    }
+}
+
+// Sequential writer over a fixed size event buffer.
+struct Writer<'a> {
+    data: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(data: &'a mut [u8], kind: EventKind) -> Self {
+        data[0] = kind as u8;
+        data[1] = EVENT_VERSION;
+        Self { data, offset: HEADER_LEN }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.data[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+        self
+    }
+
+    fn u8(&mut self, value: u8) -> &mut Self { self.bytes(&[value]) }
+    fn u16(&mut self, value: u16) -> &mut Self { self.bytes(&value.to_le_bytes()) }
+    fn u64(&mut self, value: u64) -> &mut Self { self.bytes(&value.to_le_bytes()) }
+    fn u128(&mut self, value: u128) -> &mut Self { self.bytes(&value.to_le_bytes()) }
+}
+
+// Sequential reader over an event payload. Length is checked once up front.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], kind: EventKind, len: usize) -> Result<Self, DecodeError> {
+        let (discriminator, version) = header(data)?;
+        if discriminator != kind as u8 {
+            return Err(DecodeError::UnknownEvent(discriminator));
+        }
+        if version != EVENT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        if data.len() != len {
+            return Err(DecodeError::InvalidLength);
+        }
+        Ok(Self { data, offset: HEADER_LEN })
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut out = [0u8; N];
+        out.copy_from_slice(&self.data[self.offset..self.offset + N]);
+        self.offset += N;
+        out
+    }
+
+    fn key(&mut self) -> Key { self.array::<32>() }
+    fn u8(&mut self) -> u8 { self.array::<1>()[0] }
+    fn u16(&mut self) -> u16 { u16::from_le_bytes(self.array::<2>()) }
+    fn u64(&mut self) -> u64 { u64::from_le_bytes(self.array::<8>()) }
+    fn u128(&mut self) -> u128 { u128::from_le_bytes(self.array::<16>()) }
+}
+
+fn header(data: &[u8]) -> Result<(u8, u8), DecodeError> {
+    match data {
+        [] => Err(DecodeError::Empty),
+        [_] => Err(DecodeError::InvalidLength),
+        [discriminator, version, ..] => Ok((*discriminator, *version)),
+    }
+}
+
+// Emitted once by Initialize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolInitialized {
+    pub pool: Key,
+    pub authority: Key,
+    pub mint_x: Key,
+    pub mint_y: Key,
+    pub mint_lp: Key,
+    pub fee_bps: u16,
+}
+
+impl PoolInitialized {
+    pub const LEN: usize = HEADER_LEN + 32 * 5 + 2;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::PoolInitialized)
+            .bytes(&self.pool).bytes(&self.authority)
+            .bytes(&self.mint_x).bytes(&self.mint_y).bytes(&self.mint_lp)
+            .u16(self.fee_bps);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::PoolInitialized, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), authority: r.key(),
+            mint_x: r.key(), mint_y: r.key(), mint_lp: r.key(),
+            fee_bps: r.u16(),
+        })
+    }
+}
+
+// Emitted by Deposit. Reserves, LP supply and D are read after the deposit settles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositEvent {
+    pub pool: Key,
+    pub user: Key,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub lp_minted: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+    pub d: u128,
+}
+
+impl DepositEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 8 * 6 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Deposit)
+            .bytes(&self.pool).bytes(&self.user)
+            .u64(self.amount_x).u64(self.amount_y).u64(self.lp_minted)
+            .u64(self.reserve_x).u64(self.reserve_y).u64(self.lp_supply).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Deposit, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), user: r.key(),
+            amount_x: r.u64(), amount_y: r.u64(), lp_minted: r.u64(),
+            reserve_x: r.u64(), reserve_y: r.u64(), lp_supply: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Emitted by Swap. `is_x` is 1 when x was sold for y. The fee is in the output token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapEvent {
+    pub pool: Key,
+    pub user: Key,
+    pub is_x: u8,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub d: u128,
+}
+
+impl SwapEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 1 + 8 * 5 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Swap)
+            .bytes(&self.pool).bytes(&self.user).u8(self.is_x)
+            .u64(self.amount_in).u64(self.amount_out).u64(self.fee)
+            .u64(self.reserve_x).u64(self.reserve_y).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Swap, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), user: r.key(), is_x: r.u8(),
+            amount_in: r.u64(), amount_out: r.u64(), fee: r.u64(),
+            reserve_x: r.u64(), reserve_y: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Emitted by Withdraw. `withdraw_mode` is 0 for balanced exits, 1 for single sided ones,
+// which pay `fee` in the withdrawn token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithdrawEvent {
+    pub pool: Key,
+    pub user: Key,
+    pub withdraw_mode: u8,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub lp_burned: u64,
+    pub fee: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+    pub d: u128,
+}
+
+impl WithdrawEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 1 + 8 * 7 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Withdraw)
+            .bytes(&self.pool).bytes(&self.user).u8(self.withdraw_mode)
+            .u64(self.amount_x).u64(self.amount_y).u64(self.lp_burned).u64(self.fee)
+            .u64(self.reserve_x).u64(self.reserve_y).u64(self.lp_supply).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Withdraw, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), user: r.key(), withdraw_mode: r.u8(),
+            amount_x: r.u64(), amount_y: r.u64(), lp_burned: r.u64(), fee: r.u64(),
+            reserve_x: r.u64(), reserve_y: r.u64(), lp_supply: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Emitted by UpdateConfig. `param` is the program's ConfigParam discriminator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChanged {
+    pub pool: Key,
+    pub authority: Key,
+    pub param: u8,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
+impl ConfigChanged {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 1 + 8 * 2;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::ConfigChanged)
+            .bytes(&self.pool).bytes(&self.authority).u8(self.param)
+            .u64(self.old_value).u64(self.new_value);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::ConfigChanged, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), authority: r.key(), param: r.u8(),
+            old_value: r.u64(), new_value: r.u64(),
+        })
+    }
+}
+
+// Emitted by Skim. Amounts of x and y above the tracked reserves sent to the recipients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkimEvent {
+    pub pool: Key,
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+impl SkimEvent {
+    pub const LEN: usize = HEADER_LEN + 32 + 8 * 2;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Skim)
+            .bytes(&self.pool).u64(self.amount_x).u64(self.amount_y);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Skim, Self::LEN)?;
+        Ok(Self { pool: r.key(), amount_x: r.u64(), amount_y: r.u64() })
+    }
+}
+
+// Emitted by Sync. Reserves adopted from the vault balances, and D over them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncEvent {
+    pub pool: Key,
+    pub authority: Key,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub d: u128,
+}
+
+impl SyncEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 8 * 2 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Sync)
+            .bytes(&self.pool).bytes(&self.authority)
+            .u64(self.reserve_x).u64(self.reserve_y).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Sync, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), authority: r.key(),
+            reserve_x: r.u64(), reserve_y: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Emitted by Donate. Reserves and D are read after the donation settles, the LP supply is unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DonateEvent {
+    pub pool: Key,
+    pub donor: Key,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub d: u128,
+}
+
+impl DonateEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 8 * 4 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::Donate)
+            .bytes(&self.pool).bytes(&self.donor)
+            .u64(self.amount_x).u64(self.amount_y)
+            .u64(self.reserve_x).u64(self.reserve_y).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::Donate, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), donor: r.key(),
+            amount_x: r.u64(), amount_y: r.u64(),
+            reserve_x: r.u64(), reserve_y: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Emitted by FlashLoan. The fees join the reserves, which with D are read once the loan is repaid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashLoanEvent {
+    pub pool: Key,
+    pub borrower: Key,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub fee_x: u64,
+    pub fee_y: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub d: u128,
+}
+
+impl FlashLoanEvent {
+    pub const LEN: usize = HEADER_LEN + 32 * 2 + 8 * 6 + 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        Writer::new(&mut data, EventKind::FlashLoan)
+            .bytes(&self.pool).bytes(&self.borrower)
+            .u64(self.amount_x).u64(self.amount_y)
+            .u64(self.fee_x).u64(self.fee_y)
+            .u64(self.reserve_x).u64(self.reserve_y).u128(self.d);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data, EventKind::FlashLoan, Self::LEN)?;
+        Ok(Self {
+            pool: r.key(), borrower: r.key(),
+            amount_x: r.u64(), amount_y: r.u64(),
+            fee_x: r.u64(), fee_y: r.u64(),
+            reserve_x: r.u64(), reserve_y: r.u64(), d: r.u128(),
+        })
+    }
+}
+
+// Any event emitted by the protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MegaAmmEvent {
+    PoolInitialized(PoolInitialized),
+    Deposit(DepositEvent),
+    Swap(SwapEvent),
+    Withdraw(WithdrawEvent),
+    ConfigChanged(ConfigChanged),
+    Skim(SkimEvent),
+    Sync(SyncEvent),
+    Donate(DonateEvent),
+    FlashLoan(FlashLoanEvent),
+}
+
+impl MegaAmmEvent {
+    // Decodes one `sol_log_data` payload, already base64 decoded.
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let (discriminator, _) = header(data)?;
+        Ok(match EventKind::try_from(discriminator)? {
+            EventKind::PoolInitialized => MegaAmmEvent::PoolInitialized(PoolInitialized::from_bytes(data)?),
+            EventKind::Deposit => MegaAmmEvent::Deposit(DepositEvent::from_bytes(data)?),
+            EventKind::Swap => MegaAmmEvent::Swap(SwapEvent::from_bytes(data)?),
+            EventKind::Withdraw => MegaAmmEvent::Withdraw(WithdrawEvent::from_bytes(data)?),
+            EventKind::ConfigChanged => MegaAmmEvent::ConfigChanged(ConfigChanged::from_bytes(data)?),
+            EventKind::Skim => MegaAmmEvent::Skim(SkimEvent::from_bytes(data)?),
+            EventKind::Sync => MegaAmmEvent::Sync(SyncEvent::from_bytes(data)?),
+            EventKind::Donate => MegaAmmEvent::Donate(DonateEvent::from_bytes(data)?),
+            EventKind::FlashLoan => MegaAmmEvent::FlashLoan(FlashLoanEvent::from_bytes(data)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap_event() -> SwapEvent {
+        SwapEvent {
+            pool: [1u8; 32], user: [2u8; 32], is_x: 1,
+            amount_in: 10_000, amount_out: 9_990, fee: 3,
+            reserve_x: 1_010_000, reserve_y: 990_010, d: 2_000_003,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let swap = swap_event();
+        assert_eq!(MegaAmmEvent::decode(&swap.to_bytes()).unwrap(), MegaAmmEvent::Swap(swap));
+
+        let withdraw = WithdrawEvent {
+            pool: [1u8; 32], user: [3u8; 32], withdraw_mode: 1,
+            amount_x: 500, amount_y: 0, lp_burned: 505, fee: 2,
+            reserve_x: 9_500, reserve_y: 10_000, lp_supply: 19_495, d: 19_495,
+        };
+        assert_eq!(MegaAmmEvent::decode(&withdraw.to_bytes()).unwrap(), MegaAmmEvent::Withdraw(withdraw));
+
+        let changed = ConfigChanged {
+            pool: [1u8; 32], authority: [4u8; 32], param: 0, old_value: 600, new_value: 1_200,
+        };
+        assert_eq!(MegaAmmEvent::decode(&changed.to_bytes()).unwrap(), MegaAmmEvent::ConfigChanged(changed));
+
+        let skim = SkimEvent { pool: [1u8; 32], amount_x: 7, amount_y: 0 };
+        assert_eq!(MegaAmmEvent::decode(&skim.to_bytes()).unwrap(), MegaAmmEvent::Skim(skim));
+
+        let sync = SyncEvent {
+            pool: [1u8; 32], authority: [4u8; 32], reserve_x: 1_000_007, reserve_y: 1_000_000, d: 2_000_007,
+        };
+        assert_eq!(MegaAmmEvent::decode(&sync.to_bytes()).unwrap(), MegaAmmEvent::Sync(sync));
+
+        let donate = DonateEvent {
+            pool: [1u8; 32], donor: [5u8; 32], amount_x: 1_000, amount_y: 1_000,
+            reserve_x: 1_001_000, reserve_y: 1_001_000, d: 2_002_000,
+        };
+        assert_eq!(MegaAmmEvent::decode(&donate.to_bytes()).unwrap(), MegaAmmEvent::Donate(donate));
+
+        let flash_loan = FlashLoanEvent {
+            pool: [1u8; 32], borrower: [6u8; 32], amount_x: 100_000, amount_y: 0, fee_x: 20, fee_y: 0,
+            reserve_x: 1_000_020, reserve_y: 1_000_000, d: 2_000_020,
+        };
+        assert_eq!(MegaAmmEvent::decode(&flash_loan.to_bytes()).unwrap(), MegaAmmEvent::FlashLoan(flash_loan));
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let data = swap_event().to_bytes();
+        assert_eq!(data[0], EventKind::Swap as u8);
+        assert_eq!(data[1], EVENT_VERSION);
+        assert_eq!(u64::from_le_bytes(data[67..75].try_into().unwrap()), 10_000);
+    }
+
+    #[test]
+    fn test_rejects_malformed_payloads() {
+        let data = swap_event().to_bytes();
+        assert_eq!(MegaAmmEvent::decode(&[]), Err(DecodeError::Empty));
+        assert_eq!(MegaAmmEvent::decode(&data[..10]), Err(DecodeError::InvalidLength));
+        assert_eq!(MegaAmmEvent::decode(&[9, EVENT_VERSION]), Err(DecodeError::UnknownEvent(9)));
+
+        let mut newer = data;
+        newer[1] = EVENT_VERSION + 1;
+        assert_eq!(MegaAmmEvent::decode(&newer), Err(DecodeError::UnsupportedVersion(EVENT_VERSION + 1)));
+    }
+}
@@ -9,17 +9,53 @@ use pinocchio::{
 };
 
 use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::oracle_ops::{ema_price, DEFAULT_EMA_HALF_LIFE};
+use crate::helpers::math_procs::curve_ops::{
+    MegaAmmStableSwapCurve, MegaAmmConstantProductCurve, MegaAmmWeightedCurve, MegaAmmCryptoSwapCurve,
+};
+use crate::helpers::math_procs::crypto_ops::{tweak_price_scale, PriceScaleState};
+use crate::helpers::math_procs::curve::{Curve, CurveType, PoolCurve, WEIGHT_ONE};
+use crate::guards::{TradeGuards, OutflowLimiter};
+use crate::helpers::math_procs::numerical_ops::{
+    SolverParams, MAX_SOLVER_ITERATIONS, MAX_SOLVER_TOLERANCE,
+};
 
 #[repr(C)]
 pub struct Config {
     state: u8, // Tracks current status of the AMM. Eg, Uninitialized, etc.
+    locked: u8, // Non zero while a state changing instruction runs on the pool.
     seed: [u8; 8], // Unique seed for the AMM enabling existence of different ones uniquely.
     authority: Address, // Administrative control over the AMM
     mint_x: Address, // Mint address for token X in the pool
     mint_y: Address, // Mint address for token Y in the pool
     fee: [u8; 2], // The swap fee.
+    curve_type: u8, // Invariant the pool prices with, a `CurveType`.
     config_bump: [u8; 1], // PDA config account derivation bump seed.
+    reserve_x: [u8; 8], // Tokens x the pool prices with. Tokens sent straight to the vault are not counted.
+    reserve_y: [u8; 8], // Tokens y the pool prices with. Tokens sent straight to the vault are not counted.
+    last_spot_price: [u8; 16], // Curve marginal price of x in y after the last trade. 1e18 scaled.
+    ema_price: [u8; 16], // Exponential moving average of the spot price. 1e18 scaled.
+    oracle_timestamp: [u8; 8], // Unix timestamp of the last oracle update.
+    ema_half_life: [u8; 8], // Seconds for the EMA to cover half the distance to the spot price.
+    weight_x: [u8; 8], // Weight of token x in a weighted pool, 1e18 scaled. y holds the rest.
+    price_scale: [u8; 16], // Cryptoswap price of x in y the balances are scaled by. 1e18 scaled.
+    xcp_profit: [u8; 16], // Cryptoswap virtual price growth since genesis. 1e18 scaled.
+    xcp_virtual_price: [u8; 16], // Cryptoswap virtual price after the last trade. 1e18 scaled.
+    max_trade_bps: [u8; 2], // Largest swap output in bps of the output reserve, 0 disables it.
+    min_reserve: [u8; 8], // Smallest output reserve a swap may leave, 0 disables it.
+    max_price_impact_bps: [u8; 2], // Largest marginal price move of a swap in bps, 0 disables it.
+    outflow_window: [u8; 8], // Seconds of the rolling outflow limit, 0 disables it.
+    max_outflow: [u8; 16], // Largest net outflow of x then y over the window, 0 leaves it unlimited.
+    outflow_window_start: [u8; 8], // Unix timestamp the current outflow bucket started at.
+    outflow_current: [u8; 16], // Net outflow of x then y in the current bucket, i64 each.
+    outflow_previous: [u8; 16], // Net outflow of x then y in the previous bucket, i64 each.
+    solver_max_iterations: [u8; 2], // Newton solver iteration limit.
+    solver_tolerance: [u8; 8], // Largest step between Newton iterates accepted as converged.
+    transfer_hook_programs: [Address; MAX_TRANSFER_HOOK_PROGRAMS], // Hook programs the pool's mints may invoke. Zero entries are unused.
 }
+
+// Size of the transfer hook allowlist, one program per pool mint.
+pub const MAX_TRANSFER_HOOK_PROGRAMS: usize = 2;
 #[repr(u8)]
 pub enum AmmState {
     Uninitialized = 0u8,
@@ -85,6 +121,8 @@ impl Config {
     #[inline(always)]
     pub fn state(&self) -> u8 { self.state }
     #[inline(always)]
+    pub fn is_locked(&self) -> bool { self.locked != 0 }
+    #[inline(always)]
     pub fn seed(&self) -> u64 { u64::from_le_bytes(self.seed) }
     #[inline(always)]
     pub fn authority(&self) -> &Address { &self.authority }
@@ -95,7 +133,100 @@ impl Config {
     #[inline(always)]
     pub fn fee(&self) -> u16 { u16::from_le_bytes(self.fee) }
     #[inline(always)]
+    pub fn curve_type(&self) -> Result<CurveType, MegaAmmProgramError> {
+        CurveType::try_from(self.curve_type).map_err(|_| MegaAmmProgramError::InvalidAccountData)
+    }
+    #[inline(always)]
     pub fn config_bump(&self) -> [u8; 1] { self.config_bump }
+    #[inline(always)]
+    pub fn reserves(&self) -> [u64; 2] {
+        [u64::from_le_bytes(self.reserve_x), u64::from_le_bytes(self.reserve_y)]
+    }
+    #[inline(always)]
+    pub fn last_spot_price(&self) -> u128 { u128::from_le_bytes(self.last_spot_price) }
+    #[inline(always)]
+    pub fn ema_price(&self) -> u128 { u128::from_le_bytes(self.ema_price) }
+    #[inline(always)]
+    pub fn oracle_timestamp(&self) -> i64 { i64::from_le_bytes(self.oracle_timestamp) }
+    #[inline(always)]
+    pub fn ema_half_life(&self) -> u64 { u64::from_le_bytes(self.ema_half_life) }
+    #[inline(always)]
+    pub fn weight_x(&self) -> u64 { u64::from_le_bytes(self.weight_x) }
+    #[inline(always)]
+    pub fn price_scale(&self) -> u128 { u128::from_le_bytes(self.price_scale) }
+    #[inline(always)]
+    pub fn price_scale_state(&self) -> PriceScaleState {
+        PriceScaleState {
+            price_scale: self.price_scale(),
+            xcp_profit: u128::from_le_bytes(self.xcp_profit),
+            virtual_price: u128::from_le_bytes(self.xcp_virtual_price),
+        }
+    }
+    // Weights of x and y, zero for curves that are not weighted.
+    #[inline(always)]
+    pub fn weights(&self) -> [u64; 2] {
+        match self.weight_x() {
+            0 => [0, 0],
+            weight_x => [weight_x, WEIGHT_ONE - weight_x],
+        }
+    }
+    #[inline(always)]
+    pub fn trade_guards(&self) -> TradeGuards {
+        TradeGuards {
+            max_trade_bps: u16::from_le_bytes(self.max_trade_bps),
+            min_reserve: u64::from_le_bytes(self.min_reserve),
+            max_price_impact_bps: u16::from_le_bytes(self.max_price_impact_bps),
+        }
+    }
+    #[inline(always)]
+    pub fn outflow_limiter(&self) -> OutflowLimiter {
+        let pair_u64 = |bytes: &[u8; 16]| [
+            u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        ];
+        let pair_i64 = |bytes: &[u8; 16]| [
+            i64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            i64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        ];
+        OutflowLimiter {
+            window: u64::from_le_bytes(self.outflow_window),
+            max_outflow: pair_u64(&self.max_outflow),
+            window_start: i64::from_le_bytes(self.outflow_window_start),
+            current: pair_i64(&self.outflow_current),
+            previous: pair_i64(&self.outflow_previous),
+        }
+    }
+    #[inline(always)]
+    pub fn solver_max_iterations(&self) -> u16 { u16::from_le_bytes(self.solver_max_iterations) }
+    #[inline(always)]
+    pub fn solver_tolerance(&self) -> u64 { u64::from_le_bytes(self.solver_tolerance) }
+    #[inline(always)]
+    pub fn solver_params(&self) -> SolverParams {
+        SolverParams { max_iterations: self.solver_max_iterations(), tolerance: self.solver_tolerance() }
+    }
+
+    // A zero address is an unused allowlist entry, never an allowed program.
+    #[inline(always)]
+    pub fn is_transfer_hook_allowed(&self, program: &Address) -> bool {
+        program.as_ref() != &[0u8; 32] && self.transfer_hook_programs.contains(program)
+    }
+
+    // Fails while an instruction further up the CPI stack is changing the pool, so neither
+    // a trade nor a price read can happen against its intermediate state.
+    #[inline(always)]
+    pub fn check_unlocked(&self) -> Result<(), MegaAmmProgramError> {
+        if self.is_locked() {
+            return Err(MegaAmmProgramError::PoolLocked);
+        }
+        Ok(())
+    }
+
+    // EMA decayed up to `now` without writing it. Used by read only price queries.
+    #[inline(always)]
+    pub fn current_ema_price(&self, now: i64) -> Result<u128, MegaAmmProgramError> {
+        let elapsed = now.saturating_sub(self.oracle_timestamp()).max(0) as u64;
+        Ok(ema_price(self.ema_price(), self.last_spot_price(), elapsed, self.ema_half_life())?)
+    }
 
     // =========================== WRITING DATA ====================
     // Return mutable Config from given bytes.
@@ -125,13 +256,38 @@ impl Config {
 
     #[inline(always)]
     pub fn set_state(&mut self, state: u8) -> Result<(), MegaAmmProgramError> {
-        if state == (AmmState::WithdrawOnly as u8) {
+        if state > (AmmState::WithdrawOnly as u8) {
             return Err(MegaAmmProgramError::InvalidAccountData.into());
         }
         self.state = state as u8;
         Ok(())
     }
 
+    // Taken at the start of every state changing instruction and released at its end. A failed
+    // instruction reverts the flag with the rest of its writes, so the pool can't stay locked.
+    #[inline(always)]
+    pub fn lock(&mut self) -> Result<(), MegaAmmProgramError> {
+        self.check_unlocked()?;
+        self.locked = 1;
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn unlock(&mut self) {
+        self.locked = 0;
+    }
+
+    // Withdrawals run on an initialized pool. A pool in `WithdrawOnly` still lets LPs exit
+    // with balanced withdrawals, single sided ones trade against the curve and are refused.
+    #[inline(always)]
+    pub fn check_withdraw_allowed(&self, withdraw_mode: u8) -> Result<(), MegaAmmProgramError> {
+        match self.state {
+            state if state == u8::from(AmmState::Initialized) => Ok(()),
+            state if state == u8::from(AmmState::WithdrawOnly) && withdraw_mode == 0 => Ok(()),
+            _ => Err(MegaAmmProgramError::Unauthorized),
+        }
+    }
+
     #[inline(always)]
     pub fn set_fee(&mut self, fee: u16) -> Result<(), MegaAmmProgramError> {
         if fee.ge(&10_000) {
@@ -145,7 +301,7 @@ impl Config {
     pub fn set_inner(
         &mut self, seed: u64, authority: [u8; 32],
         mint_x: [u8; 32], mint_y: [u8; 32], fee: u16,
-        config_bump: [u8; 1],
+        config_bump: [u8; 1], curve_type: CurveType, weight_x: u64,
     ) -> Result<(), MegaAmmProgramError> {
         self.set_state(AmmState::Initialized as u8)?;
         self.set_seed(seed);
@@ -153,7 +309,24 @@ impl Config {
         self.set_mint_x(mint_x);
         self.set_mint_y(mint_y);
         self.set_fee(fee)?;
+        self.set_curve_type(curve_type);
+        self.set_weight_x(weight_x)?;
         self.set_config_bump(config_bump);
+        self.set_ema_half_life(DEFAULT_EMA_HALF_LIFE)?;
+        self.set_solver_max_iterations(SolverParams::DEFAULT.max_iterations)?;
+        self.set_solver_tolerance(SolverParams::DEFAULT.tolerance)?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_curve_type(&mut self, curve_type: CurveType) {
+        self.curve_type = curve_type.into();
+    }
+
+    #[inline(always)]
+    pub fn set_weight_x(&mut self, weight_x: u64) -> Result<(), MegaAmmProgramError> {
+        self.curve_type()?.check_weight_x(weight_x)?;
+        self.weight_x = weight_x.to_le_bytes();
         Ok(())
     }
 
@@ -187,6 +360,183 @@ impl Config {
         Ok(())
     }
 
+    // Entries past the programs given are cleared.
+    #[inline(always)]
+    pub fn set_transfer_hook_programs(&mut self, programs: &[Option<Address>]) -> Result<(), MegaAmmProgramError> {
+        if programs.len() > MAX_TRANSFER_HOOK_PROGRAMS {
+            return Err(MegaAmmProgramError::InvalidInstructionData);
+        }
+        for (index, entry) in self.transfer_hook_programs.iter_mut().enumerate() {
+            *entry = programs.get(index).cloned().flatten().unwrap_or(Address::new_from_array([0u8; 32]));
+        }
+        Ok(())
+    }
+
+    // Updated by every instruction moving tokens in or out of the vaults, and by Sync.
+    #[inline(always)]
+    pub fn set_reserves(&mut self, reserves: &[u64; 2]) {
+        self.reserve_x = reserves[0].to_le_bytes();
+        self.reserve_y = reserves[1].to_le_bytes();
+    }
+
+    #[inline(always)]
+    pub fn set_ema_half_life(&mut self, half_life: u64) -> Result<(), MegaAmmProgramError> {
+        if half_life == 0 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.ema_half_life = half_life.to_le_bytes();
+        Ok(())
+    }
+
+    // Zero disables the cap, otherwise at most the whole reserve.
+    #[inline(always)]
+    pub fn set_max_trade_bps(&mut self, max_trade_bps: u16) -> Result<(), MegaAmmProgramError> {
+        if max_trade_bps > 10_000 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.max_trade_bps = max_trade_bps.to_le_bytes();
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_min_reserve(&mut self, min_reserve: u64) -> Result<(), MegaAmmProgramError> {
+        self.min_reserve = min_reserve.to_le_bytes();
+        Ok(())
+    }
+
+    // Zero disables the cap, otherwise at most a 100% price move.
+    #[inline(always)]
+    pub fn set_max_price_impact_bps(&mut self, max_price_impact_bps: u16) -> Result<(), MegaAmmProgramError> {
+        if max_price_impact_bps > 10_000 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.max_price_impact_bps = max_price_impact_bps.to_le_bytes();
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_outflow_window(&mut self, window: u64) -> Result<(), MegaAmmProgramError> {
+        if window > i64::MAX as u64 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.outflow_window = window.to_le_bytes();
+        Ok(())
+    }
+
+    // Limit of token x (0) or y (1).
+    #[inline(always)]
+    pub fn set_max_outflow(&mut self, token: usize, max_outflow: u64) -> Result<(), MegaAmmProgramError> {
+        if token > 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.max_outflow[token * 8..token * 8 + 8].copy_from_slice(&max_outflow.to_le_bytes());
+        Ok(())
+    }
+
+    // Counts a change of the reserves against the rolling outflow limit, positive amounts
+    // leaving the pool.
+    #[inline(always)]
+    pub fn record_outflow(&mut self, now: i64, net_outflow: [i64; 2]) -> Result<(), MegaAmmProgramError> {
+        let mut limiter = self.outflow_limiter();
+        limiter.record(now, net_outflow)?;
+        self.outflow_window_start = limiter.window_start.to_le_bytes();
+        for token in 0..2 {
+            self.outflow_current[token * 8..token * 8 + 8].copy_from_slice(&limiter.current[token].to_le_bytes());
+            self.outflow_previous[token * 8..token * 8 + 8].copy_from_slice(&limiter.previous[token].to_le_bytes());
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_solver_max_iterations(&mut self, max_iterations: u16) -> Result<(), MegaAmmProgramError> {
+        if max_iterations == 0 || max_iterations > MAX_SOLVER_ITERATIONS {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.solver_max_iterations = max_iterations.to_le_bytes();
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_solver_tolerance(&mut self, tolerance: u64) -> Result<(), MegaAmmProgramError> {
+        if tolerance == 0 || tolerance > MAX_SOLVER_TOLERANCE {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        self.solver_tolerance = tolerance.to_le_bytes();
+        Ok(())
+    }
+
+    // Records the post trade spot price. The EMA first absorbs the price that prevailed
+    // since the last update, then the new spot is stored for the next one.
+    // The first observation seeds the EMA directly.
+    #[inline(always)]
+    pub fn record_price(&mut self, spot: u128, now: i64) -> Result<(), MegaAmmProgramError> {
+        let ema = if self.ema_price() == 0 {
+            spot
+        } else {
+            self.current_ema_price(now)?
+        };
+        self.ema_price = ema.to_le_bytes();
+        self.last_spot_price = spot.to_le_bytes();
+        self.oracle_timestamp = now.to_le_bytes();
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn set_price_scale_state(&mut self, state: PriceScaleState) {
+        self.price_scale = state.price_scale.to_le_bytes();
+        self.xcp_profit = state.xcp_profit.to_le_bytes();
+        self.xcp_virtual_price = state.virtual_price.to_le_bytes();
+    }
+
+    // Repegs a cryptoswap pool after a trade, once the oracle has absorbed the previous price.
+    // An emptied pool forgets its price scale, the next first deposit sets it again.
+    #[inline(always)]
+    pub fn update_price_scale(&mut self, balances: &[u64], lp_supply: u64) -> Result<(), MegaAmmProgramError> {
+        if self.curve_type()? != CurveType::CryptoSwap {
+            return Ok(());
+        }
+        if lp_supply == 0 || balances.iter().any(|&balance| balance == 0) {
+            self.set_price_scale_state(PriceScaleState::EMPTY);
+            return Ok(());
+        }
+        let state = tweak_price_scale(
+            balances, lp_supply, self.price_scale_state(), self.ema_price(), self.solver_params(),
+        )?;
+        self.set_price_scale_state(state);
+        Ok(())
+    }
+
+    // Prices the pool at its post trade balances and feeds the oracle.
+    // An emptied pool has no price, the last recorded one is kept.
+    #[inline(always)]
+    pub fn update_oracle(&mut self, balances: &[u64], now: i64) -> Result<(), MegaAmmProgramError> {
+        if balances.iter().any(|&balance| balance == 0) {
+            return Ok(());
+        }
+        let spot = self.curve(balances)?.spot_price()?;
+        self.record_price(spot, now)
+    }
+
+    // The pool's curve over the given reserves, with its fee and solver settings.
+    #[inline(always)]
+    pub fn curve<'b>(&self, balances: &'b [u64]) -> Result<PoolCurve<'b>, MegaAmmProgramError> {
+        let fee_bps = self.fee() as u64;
+        match self.curve_type()? {
+            CurveType::StableSwap => Ok(PoolCurve::StableSwap(MegaAmmStableSwapCurve {
+                balances, target_token_idx: None, fee_bps, solver: self.solver_params(),
+            })),
+            CurveType::ConstantProduct => Ok(PoolCurve::ConstantProduct(MegaAmmConstantProductCurve {
+                balances, fee_bps,
+            })),
+            CurveType::Weighted => Ok(PoolCurve::Weighted(MegaAmmWeightedCurve {
+                balances, weights: self.weights(), fee_bps,
+            })),
+            CurveType::CryptoSwap => Ok(PoolCurve::CryptoSwap(MegaAmmCryptoSwapCurve {
+                balances, price_scale: self.price_scale(), fee_bps, solver: self.solver_params(),
+            })),
+        }
+    }
+
     #[inline(always)]
     pub fn has_authority(&self) -> Option<Address> {
         let bytes = self.authority.as_ref();
@@ -0,0 +1,19 @@
+//! Emits the protocol's events through `sol_log_data`. Layouts and the decoder live in the
+//! shared `megaswap-events` crate so indexers and the program can not drift apart.
+pub use megaswap_events::*;
+
+#[cfg(any(target_os = "solana", target_arch = "bpf"))]
+use pinocchio::syscalls::sol_log_data;
+
+// Logs one encoded event as a single `Program data:` entry.
+#[inline(always)]
+pub fn emit(event: &[u8]) {
+    #[cfg(any(target_os = "solana", target_arch = "bpf"))]
+    unsafe {
+        let fields: [&[u8]; 1] = [event];
+        sol_log_data(fields.as_ptr() as *const u8, fields.len() as u64);
+    }
+
+    #[cfg(not(any(target_os = "solana", target_arch = "bpf")))]
+    core::hint::black_box(event);
+}
@@ -0,0 +1,285 @@
+//! Per pool circuit breakers. The trade guards bound what a single swap can take out of the
+//! pool during a depeg or an exploit, the outflow limiter bounds what many transactions can
+//! take out together. Each is off while its limit is zero, set by the pool authority through
+//! `UpdateConfig`, and fails with its own error. Donations are always held to the ratio of
+//! the reserves, so they can't be used to move the price.
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::numerical_ops::mul_div;
+
+const BPS: u128 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradeGuards {
+    // Largest amount out of a swap, in bps of the output reserve before the trade.
+    pub max_trade_bps: u16,
+    // Smallest output reserve a swap may leave in the pool.
+    pub min_reserve: u64,
+    // Largest move of the marginal price of x in y a swap may cause, in bps of the price before.
+    pub max_price_impact_bps: u16,
+}
+
+impl TradeGuards {
+    pub const DISABLED: TradeGuards = TradeGuards { max_trade_bps: 0, min_reserve: 0, max_price_impact_bps: 0 };
+
+    // Rejects paying out more than `max_trade_bps` of the output reserve.
+    #[inline(always)]
+    pub fn check_trade_size(&self, reserve_out: u64, amount_out: u64) -> Result<(), MegaAmmProgramError> {
+        if self.max_trade_bps == 0 {
+            return Ok(());
+        }
+        if amount_out as u128 * BPS > reserve_out as u128 * self.max_trade_bps as u128 {
+            return Err(MegaAmmProgramError::TradeTooLarge);
+        }
+        Ok(())
+    }
+
+    // Rejects leaving less than `min_reserve` of the output token in the pool.
+    #[inline(always)]
+    pub fn check_reserve_floor(&self, post_reserve_out: u64) -> Result<(), MegaAmmProgramError> {
+        if post_reserve_out < self.min_reserve {
+            return Err(MegaAmmProgramError::ReserveBelowFloor);
+        }
+        Ok(())
+    }
+
+    // Rejects moving the marginal price by more than `max_price_impact_bps`.
+    #[inline(always)]
+    pub fn check_price_impact(&self, spot_before: u128, spot_after: u128) -> Result<(), MegaAmmProgramError> {
+        if self.max_price_impact_bps == 0 {
+            return Ok(());
+        }
+        if price_impact_bps(spot_before, spot_after)? > self.max_price_impact_bps as u128 {
+            return Err(MegaAmmProgramError::PriceImpactTooHigh);
+        }
+        Ok(())
+    }
+}
+
+// Rolling window limit on the net outflow of each token, outflows minus inflows.
+// The window is approximated with two fixed buckets: the outflow of the previous window counts
+// for the share of it still inside the rolling window, plus all of the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutflowLimiter {
+    // Window length in seconds, 0 disables the limiter.
+    pub window: u64,
+    // Largest net outflow of x and y over a window, 0 leaves that token unlimited.
+    pub max_outflow: [u64; 2],
+    // Unix timestamp the current bucket started at.
+    pub window_start: i64,
+    // Net outflow of x and y in the current and the previous bucket, negative after net inflows.
+    pub current: [i64; 2],
+    pub previous: [i64; 2],
+}
+
+impl OutflowLimiter {
+    // Records a change of the reserves, `net_outflow` positive for tokens leaving the pool.
+    // A token leaving the pool past its limit rejects the whole change, inflows always pass.
+    pub fn record(&mut self, now: i64, net_outflow: [i64; 2]) -> Result<(), MegaAmmProgramError> {
+        if self.window == 0 {
+            return Ok(());
+        }
+        self.roll(now);
+        let window = self.window as i128;
+        let remaining = window - (now - self.window_start) as i128;
+        let mut current = self.current;
+        for token in 0..2 {
+            current[token] = current[token].checked_add(net_outflow[token])
+                .ok_or(MegaAmmProgramError::MathOverflow)?;
+            let limit = self.max_outflow[token];
+            if net_outflow[token] > 0 && limit != 0 {
+                let rolling = self.previous[token] as i128 * remaining / window + current[token] as i128;
+                if rolling > limit as i128 {
+                    return Err(MegaAmmProgramError::OutflowLimitExceeded);
+                }
+            }
+        }
+        self.current = current;
+        Ok(())
+    }
+
+    // Starts a new bucket once the current one is over. The previous bucket is dropped when
+    // a whole window went by without a change.
+    fn roll(&mut self, now: i64) {
+        let window = self.window as i64;
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < window {
+            return;
+        }
+        self.previous = if elapsed < 2 * window { self.current } else { [0; 2] };
+        self.current = [0; 2];
+        self.window_start = now - elapsed % window;
+    }
+}
+
+// Net outflow of x and y from the amounts a change moves into and out of the pool.
+pub fn net_outflow(inflow: [u64; 2], outflow: [u64; 2]) -> Result<[i64; 2], MegaAmmProgramError> {
+    let mut net = [0i64; 2];
+    for token in 0..2 {
+        net[token] = i64::try_from(outflow[token] as i128 - inflow[token] as i128)
+            .map_err(|_| MegaAmmProgramError::MathOverflow)?;
+    }
+    Ok(net)
+}
+
+// A donation must grow both reserves by the same ratio, within the rounding of either amount,
+// so the marginal price of every curve stays where it was. Empty reserves have no ratio.
+pub fn check_balanced_donation(reserves: [u64; 2], amounts: [u64; 2]) -> Result<(), MegaAmmProgramError> {
+    if reserves.contains(&0) {
+        return Err(MegaAmmProgramError::EmptyPool);
+    }
+    let cross_x = amounts[0] as u128 * reserves[1] as u128;
+    let cross_y = amounts[1] as u128 * reserves[0] as u128;
+    if cross_x.abs_diff(cross_y) >= reserves[0].max(reserves[1]) as u128 {
+        return Err(MegaAmmProgramError::UnbalancedDonation);
+    }
+    Ok(())
+}
+
+// A flash loan is repaid once each vault holds at least what it held before the loan plus the
+// fee. Vault balances rather than reserves, tokens sent to the vaults earlier can't pay for it.
+pub fn check_flash_loan_repaid(
+    vaults_before: [u64; 2], vaults_after: [u64; 2], fees: [u64; 2],
+) -> Result<(), MegaAmmProgramError> {
+    for token in 0..2 {
+        let owed = vaults_before[token].checked_add(fees[token]).ok_or(MegaAmmProgramError::MathOverflow)?;
+        if vaults_after[token] < owed {
+            return Err(MegaAmmProgramError::FlashLoanNotRepaid);
+        }
+    }
+    Ok(())
+}
+
+// Reserves after a flash swap: what the vaults gained or lost around the swap, applied to the
+// reserves before it. Vault balances rather than reserves, tokens sent to the vaults earlier
+// can't pay for the swap.
+pub fn settled_balances(
+    reserves: [u64; 2], vaults_before: [u64; 2], vaults_after: [u64; 2],
+) -> Result<[u64; 2], MegaAmmProgramError> {
+    let mut settled = [0; 2];
+    for token in 0..2 {
+        settled[token] = reserves[token]
+            .checked_add(vaults_after[token])
+            .and_then(|balance| balance.checked_sub(vaults_before[token]))
+            .ok_or(MegaAmmProgramError::MathOverflow)?;
+    }
+    Ok(settled)
+}
+
+// Relative move from `before` to `after` in bps, rounded up so a move just past the limit
+// is never let through.
+pub fn price_impact_bps(before: u128, after: u128) -> Result<u128, MegaAmmProgramError> {
+    if before == 0 {
+        return Err(MegaAmmProgramError::EmptyPool);
+    }
+    let delta = before.abs_diff(after);
+    let impact = mul_div(delta, BPS, before)?;
+    Ok(if mul_div(impact, before, BPS)? < delta { impact + 1 } else { impact })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUARDS: TradeGuards = TradeGuards { max_trade_bps: 1_000, min_reserve: 500, max_price_impact_bps: 200 };
+
+    #[test]
+    fn test_disabled_guards_pass_everything() {
+        let guards = TradeGuards::DISABLED;
+        assert!(guards.check_trade_size(1_000, 1_000).is_ok());
+        assert!(guards.check_reserve_floor(0).is_ok());
+        assert!(guards.check_price_impact(1, u128::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_each_guard_has_its_own_error() {
+        // 10% of the output reserve is the largest trade.
+        assert!(GUARDS.check_trade_size(10_000, 1_000).is_ok());
+        assert_eq!(GUARDS.check_trade_size(10_000, 1_001), Err(MegaAmmProgramError::TradeTooLarge));
+
+        assert!(GUARDS.check_reserve_floor(500).is_ok());
+        assert_eq!(GUARDS.check_reserve_floor(499), Err(MegaAmmProgramError::ReserveBelowFloor));
+
+        // 2% either way, anything past it rounds up to 201 bps.
+        assert!(GUARDS.check_price_impact(1_000_000, 980_000).is_ok());
+        assert!(GUARDS.check_price_impact(1_000_000, 1_020_000).is_ok());
+        assert_eq!(GUARDS.check_price_impact(1_000_000, 1_020_001), Err(MegaAmmProgramError::PriceImpactTooHigh));
+        assert_eq!(GUARDS.check_price_impact(1_000_000, 979_999), Err(MegaAmmProgramError::PriceImpactTooHigh));
+        assert_eq!(price_impact_bps(0, 1), Err(MegaAmmProgramError::EmptyPool));
+    }
+
+    const LIMITER: OutflowLimiter = OutflowLimiter {
+        window: 100, max_outflow: [1_000, 0], window_start: 0, current: [0; 2], previous: [0; 2],
+    };
+
+    #[test]
+    fn test_outflow_limiter_rolls_over_the_window() {
+        let mut limiter = LIMITER;
+        assert!(limiter.record(10, [600, 5_000]).is_ok());
+        assert!(limiter.record(20, [400, 0]).is_ok());
+        // x is at its limit, y has none.
+        assert_eq!(limiter.record(30, [1, 0]), Err(MegaAmmProgramError::OutflowLimitExceeded));
+        assert_eq!(limiter.current, [1_000, 5_000]);
+
+        // Inflows pass and make room for outflows.
+        assert!(limiter.record(40, [-300, -10]).is_ok());
+        assert!(limiter.record(50, [300, 0]).is_ok());
+
+        // A quarter into the next window three quarters of the last one still count.
+        assert_eq!(limiter.record(125, [251, 0]), Err(MegaAmmProgramError::OutflowLimitExceeded));
+        assert!(limiter.record(125, [250, 0]).is_ok());
+        assert_eq!((limiter.window_start, limiter.previous[0], limiter.current[0]), (100, 1_000, 250));
+
+        // Two windows later nothing is left.
+        assert!(limiter.record(320, [1_000, 0]).is_ok());
+        assert_eq!((limiter.window_start, limiter.previous[0]), (300, 0));
+    }
+
+    #[test]
+    fn test_donations_keep_the_reserve_ratio() {
+        assert!(check_balanced_donation([1_000_000, 3_000_000], [1_000, 3_000]).is_ok());
+        // One unit of rounding either way.
+        assert!(check_balanced_donation([1_000_000, 3_000_000], [1_000, 3_002]).is_ok());
+        assert!(check_balanced_donation([3_000_000, 1_000_000], [1_001, 333]).is_ok());
+        assert_eq!(
+            check_balanced_donation([1_000_000, 3_000_000], [1_000, 3_003]),
+            Err(MegaAmmProgramError::UnbalancedDonation),
+        );
+        assert_eq!(
+            check_balanced_donation([1_000_000, 1_000_000], [1_000, 0]),
+            Err(MegaAmmProgramError::UnbalancedDonation),
+        );
+        assert_eq!(check_balanced_donation([0, 0], [1_000, 1_000]), Err(MegaAmmProgramError::EmptyPool));
+    }
+
+    #[test]
+    fn test_flash_loans_repay_the_fee_on_top_of_the_vaults() {
+        assert!(check_flash_loan_repaid([1_000, 2_000], [1_003, 2_000], [3, 0]).is_ok());
+        // Anything repaid above the fee stays in the vaults.
+        assert!(check_flash_loan_repaid([1_000, 2_000], [1_010, 2_001], [3, 1]).is_ok());
+        assert_eq!(
+            check_flash_loan_repaid([1_000, 2_000], [1_002, 2_000], [3, 0]),
+            Err(MegaAmmProgramError::FlashLoanNotRepaid),
+        );
+        assert_eq!(
+            check_flash_loan_repaid([1_000, 2_000], [1_003, 1_999], [3, 0]),
+            Err(MegaAmmProgramError::FlashLoanNotRepaid),
+        );
+    }
+
+    #[test]
+    fn test_flash_swaps_settle_on_the_vault_deltas() {
+        // 100 x paid in, 90 y sent out, on top of a 5 y donation the reserves don't count.
+        assert_eq!(settled_balances([1_000, 2_000], [1_000, 2_005], [1_100, 1_915]), Ok([1_100, 1_910]));
+        assert_eq!(
+            settled_balances([1_000, 50], [1_000, 100], [1_000, 0]),
+            Err(MegaAmmProgramError::MathOverflow),
+        );
+    }
+
+    #[test]
+    fn test_disabled_outflow_limiter() {
+        let mut limiter = OutflowLimiter { window: 0, ..LIMITER };
+        assert!(limiter.record(10, [i64::MAX, 0]).is_ok());
+        assert_eq!(limiter.current, [0; 2]);
+    }
+}
@@ -1,18 +1,88 @@
 //! Defining the protocol's errors.
+//! Codes are stable, clients decode `Custom(n)` with the table in the IDL's `errors` section.
 use pinocchio::error::ProgramError as P;
 
 
 // All fields will be of type u32
 #[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MegaAmmProgramError {
+    // Instruction data has the wrong length or an invalid value.
     InvalidInstructionData = 1,
+    // Account is not owned by the expected program.
     InvalidOwner = 2,
+    // Caller is not allowed to perform the action, or the pool is in the wrong state.
     Unauthorized = 3,
+    // Account data does not match the expected layout or pool.
     InvalidAccountData = 4,
+    // Account address does not match the expected PDA or ATA.
     InvalidAddress = 5,
+    // Fewer accounts than the instruction requires.
     NotEnoughAccountKeys = 6,
+    // A required signature is missing.
     InvalidSignature = 7,
+    // Output below the caller's minimum.
     SlippageExceeded = 8,
+    // Curve math overflowed.
+    MathOverflow = 9,
+    // Curve math underflowed.
+    MathUnderflow = 10,
+    // Curve math divided by zero.
+    DivisionByZero = 11,
+    // A pool balance is zero inside the invariant.
+    ZeroBalance = 12,
+    // The pool holds no liquidity.
+    EmptyPool = 13,
+    // Token index missing or out of range.
+    InvalidTokenIndex = 14,
+    // Number of pool tokens not supported.
+    InvalidTokenCount = 15,
+    // The invariant D decreased where it may only grow.
+    InvariantDecreased = 16,
+    // The pool can not pay out the requested amount.
+    InsufficientLiquidity = 17,
+    // No LP tokens in circulation.
+    ZeroLpSupply = 18,
+    // EMA half-life of zero.
+    InvalidHalfLife = 19,
+    // TWAP target older than the oldest observation.
+    ObservationOutOfRange = 20,
+    // TWAP window of zero seconds.
+    EmptyTwapWindow = 21,
+    // Account is already initialized.
+    AccountAlreadyInitialized = 22,
+    // Account is not initialized.
+    UninitializedAccount = 23,
+    // Not enough lamports or tokens to pay.
+    InsufficientFunds = 24,
+    // A program account is not the expected program.
+    IncorrectProgramId = 25,
+    // Account data is too small for its layout.
+    AccountDataTooSmall = 26,
+    // Runtime error without a dedicated code.
+    RuntimeError = 27,
+    // Newton solver did not converge within the pool's iteration limit.
+    SolverNotConverged = 28,
+    // Swap pays out more of the output reserve than the pool's trade size cap.
+    TradeTooLarge = 29,
+    // Swap leaves the output reserve below the pool's floor.
+    ReserveBelowFloor = 30,
+    // Swap moves the marginal price more than the pool's price impact cap.
+    PriceImpactTooHigh = 31,
+    // Net outflow of a token over the pool's rolling window is above its limit.
+    OutflowLimitExceeded = 32,
+    // The pool is in the middle of another instruction, re-entering it is refused.
+    PoolLocked = 33,
+    // The first deposit into a pool is too small to lock the minimum liquidity.
+    InitialLiquidityTooLow = 34,
+    // A donation would move the pool's price, it must keep the ratio of the reserves.
+    UnbalancedDonation = 35,
+    // A pool mint carries a token-2022 extension the pool can't work with.
+    UnsupportedMintExtension = 36,
+    // A pool mint's transfer hook program is not on the pool's allowlist.
+    TransferHookNotAllowed = 37,
+    // A flash loan's borrower did not return the amounts borrowed plus the fee to the vaults.
+    FlashLoanNotRepaid = 38,
 }
 
 impl From<MegaAmmProgramError> for P {
@@ -21,13 +91,91 @@ impl From<MegaAmmProgramError> for P {
     }
 }
 
+impl TryFrom<u32> for MegaAmmProgramError {
+    type Error = ();
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        use MegaAmmProgramError::*;
+        Ok(match code {
+            1 => InvalidInstructionData,
+            2 => InvalidOwner,
+            3 => Unauthorized,
+            4 => InvalidAccountData,
+            5 => InvalidAddress,
+            6 => NotEnoughAccountKeys,
+            7 => InvalidSignature,
+            8 => SlippageExceeded,
+            9 => MathOverflow,
+            10 => MathUnderflow,
+            11 => DivisionByZero,
+            12 => ZeroBalance,
+            13 => EmptyPool,
+            14 => InvalidTokenIndex,
+            15 => InvalidTokenCount,
+            16 => InvariantDecreased,
+            17 => InsufficientLiquidity,
+            18 => ZeroLpSupply,
+            19 => InvalidHalfLife,
+            20 => ObservationOutOfRange,
+            21 => EmptyTwapWindow,
+            22 => AccountAlreadyInitialized,
+            23 => UninitializedAccount,
+            24 => InsufficientFunds,
+            25 => IncorrectProgramId,
+            26 => AccountDataTooSmall,
+            27 => RuntimeError,
+            28 => SolverNotConverged,
+            29 => TradeTooLarge,
+            30 => ReserveBelowFloor,
+            31 => PriceImpactTooHigh,
+            32 => OutflowLimitExceeded,
+            33 => PoolLocked,
+            34 => InitialLiquidityTooLow,
+            35 => UnbalancedDonation,
+            36 => UnsupportedMintExtension,
+            37 => TransferHookNotAllowed,
+            38 => FlashLoanNotRepaid,
+            _ => return Err(()),
+        })
+    }
+}
+
 impl From<P> for MegaAmmProgramError {
     fn from(e: P) -> Self {
         match e {
+            // Our own codes round trip unchanged.
+            P::Custom(code) => MegaAmmProgramError::try_from(code)
+                .unwrap_or(MegaAmmProgramError::RuntimeError),
             P::AccountBorrowFailed => MegaAmmProgramError::Unauthorized,
             P::MissingRequiredSignature => MegaAmmProgramError::InvalidSignature,
-            P::InvalidInstructionData => MegaAmmProgramError::InvalidInstructionData,
-            _ => MegaAmmProgramError::InvalidAccountData,
+            P::InvalidInstructionData | P::InvalidArgument => MegaAmmProgramError::InvalidInstructionData,
+            P::InvalidAccountData => MegaAmmProgramError::InvalidAccountData,
+            P::IllegalOwner | P::InvalidAccountOwner => MegaAmmProgramError::InvalidOwner,
+            P::InvalidSeeds => MegaAmmProgramError::InvalidAddress,
+            P::IncorrectAuthority => MegaAmmProgramError::Unauthorized,
+            P::NotEnoughAccountKeys => MegaAmmProgramError::NotEnoughAccountKeys,
+            P::ArithmeticOverflow => MegaAmmProgramError::MathOverflow,
+            P::AccountAlreadyInitialized => MegaAmmProgramError::AccountAlreadyInitialized,
+            P::UninitializedAccount => MegaAmmProgramError::UninitializedAccount,
+            P::InsufficientFunds => MegaAmmProgramError::InsufficientFunds,
+            P::IncorrectProgramId => MegaAmmProgramError::IncorrectProgramId,
+            P::AccountDataTooSmall => MegaAmmProgramError::AccountDataTooSmall,
+            _ => MegaAmmProgramError::RuntimeError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_round_trip() {
+        for code in 1..=38u32 {
+            let error = MegaAmmProgramError::try_from(code).ok().unwrap();
+            assert_eq!(error as u32, code);
+            assert!(MegaAmmProgramError::from(P::from(error)) == error);
         }
+        assert!(MegaAmmProgramError::try_from(0).is_err());
+        assert!(MegaAmmProgramError::try_from(39).is_err());
     }
 }
@@ -0,0 +1,423 @@
+//! Cryptoswap (Curve v2) invariant for pairs that are correlated but not pegged, like SOL/LST.
+//! Balances are scaled by the pool's internal `price_scale`, the price of x in y, so the
+//! invariant is centered on the market price instead of 1:1. With x0 = x * price_scale,
+//! x1 = y and S = x0 + x1:
+//!     K * D * (S - D) + x0 * x1 = D^2 / 4
+//!     K0 = 4 * x0 * x1 / D^2, K = A * gamma^2 * K0 / (gamma + 1 - K0)^2
+//! K is close to A near the peg, where the curve is flat like the stableswap, and falls to
+//! zero away from it, where the curve turns into the constant product.
+//! The price scale follows the price oracle in small steps, only when the pool has made
+//! enough profit from fees to pay for the loss of moving its liquidity.
+use crate::helpers::math_procs::MathError;
+use crate::helpers::math_procs::u256::U256;
+use crate::helpers::math_procs::numerical_ops::{
+    mul_div, ratio_scaled, sqrt, Convergence, SolverParams, PRICE_PRECISION,
+};
+
+type Uint = u128;
+const WAD: Uint = PRICE_PRECISION;
+
+// Amplification A * n^n, K's value at the peg.
+pub const CRYPTOSWAP_ANN: Uint = 400;
+// Width of the flat region around the peg, 1e18 scaled (1e-4).
+pub const CRYPTOSWAP_GAMMA: Uint = 100_000_000_000_000;
+// Relative move of the price scale towards the oracle per repeg, 1e18 scaled (0.049%).
+pub const ADJUSTMENT_STEP: Uint = 490_000_000_000_000;
+// Virtual price gain kept above half of the profit before repegging is allowed (2e-6).
+pub const ALLOWED_EXTRA_PROFIT: Uint = 2_000_000_000_000;
+
+// Pool state the repegging carries between trades, stored in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceScaleState {
+    // Price of x in y the balances are scaled by, 1e18 scaled. Zero until the first deposit.
+    pub price_scale: Uint,
+    // Virtual price growth from fees since genesis, 1e18 scaled.
+    pub xcp_profit: Uint,
+    // Value of one LP token in balanced holdings at the price scale, 1e18 scaled.
+    pub virtual_price: Uint,
+}
+
+impl PriceScaleState {
+    pub const EMPTY: PriceScaleState = PriceScaleState { price_scale: 0, xcp_profit: 0, virtual_price: 0 };
+}
+
+// Precision of the solvers' normalized terms. F is evaluated on the balances relative to D,
+// K0 at 1e18 would quantize F far coarser than one unit of D on large pools.
+const SCALE: Uint = 1_000_000_000_000_000_000_000_000_000_000; // 1e30
+const GAMMA: Uint = CRYPTOSWAP_GAMMA * (SCALE / WAD);
+// A balance over 65536 * D is far outside any bracket the solvers search, and keeps the
+// products below in range.
+const MAX_NORMALIZED: Uint = SCALE << 16;
+
+// Invariant terms at one point, all scaled by `SCALE`. u and v are the two balances over D.
+#[derive(Clone, Copy)]
+struct Point {
+    u: Uint,
+    v: Uint,
+    k0: Uint,
+    k: Uint,
+    // dK/dK0 * K0.
+    dk_k0: Uint,
+}
+
+impl Point {
+    // K0 is capped at 1, its value on the constant product bound.
+    fn new(x0: Uint, x1: Uint, d: Uint) -> Result<Self, MathError> {
+        let u = mul_div(x0, SCALE, d)?;
+        let v = mul_div(x1, SCALE, d)?;
+        if u.max(v) > MAX_NORMALIZED {
+            return Err(MathError::Overflow);
+        }
+        let k0 = mul_div(4 * u, v, SCALE)?.min(SCALE);
+        // g = gamma + 1 - K0 >= gamma, so (gamma / g)^2 <= 1.
+        let g = GAMMA + SCALE - k0;
+        let ratio = mul_div(GAMMA, SCALE, g)?;
+        let ratio_sq = mul_div(ratio, ratio, SCALE)?;
+        let k = CRYPTOSWAP_ANN * mul_div(ratio_sq, k0, SCALE)?;
+        // d/dK0 of A * gamma^2 * K0 / g^2 is A * gamma^2 * (gamma + 1 + K0) / g^3.
+        let dk = CRYPTOSWAP_ANN * mul_div(ratio_sq, GAMMA + SCALE + k0, g)?;
+        Ok(Self { u, v, k0, k, dk_k0: mul_div(dk, k0, SCALE)? })
+    }
+
+    // F / D^2 = K * (u + v - 1) + u * v - 1/4, times 4 * SCALE^2, as its sign (true when
+    // >= 0) and magnitude.
+    fn gap(&self) -> Result<(bool, U256), MathError> {
+        let mut positive = U256::mul_u128(4 * self.u, self.v);
+        let mut negative = U256::mul_u128(SCALE, SCALE);
+        let sum = self.u.checked_add(self.v).ok_or(MathError::Overflow)?;
+        let k4 = 4 * self.k;
+        if sum >= SCALE {
+            positive = positive.checked_add(U256::mul_u128(k4, sum - SCALE)).ok_or(MathError::Overflow)?;
+        } else {
+            negative = negative.checked_add(U256::mul_u128(k4, SCALE - sum)).ok_or(MathError::Overflow)?;
+        }
+        if positive >= negative {
+            Ok((true, positive.checked_sub(negative).ok_or(MathError::Underflow)?))
+        } else {
+            Ok((false, negative.checked_sub(positive).ok_or(MathError::Underflow)?))
+        }
+    }
+
+    // dF/dx1 / D = K + u + (u + v - 1) * dK/dK0 * K0 / v, same scale as `gap`. Non negative
+    // for S >= D. Swapping u and v gives dF/dx0.
+    fn slope_v(&self) -> Result<U256, MathError> {
+        let excess = (self.u + self.v).saturating_sub(SCALE);
+        // Kept in 256 bits, dK/dK0 reaches A / gamma near K0 = 1.
+        let curvature = U256::mul_u128(excess, self.dk_k0)
+            .checked_div_u128(self.v).ok_or(MathError::DivisionByZero)?
+            .checked_mul_u128(SCALE).ok_or(MathError::Overflow)?;
+        U256::mul_u128(self.k.checked_add(self.u).ok_or(MathError::Overflow)?, SCALE)
+            .checked_add(curvature).ok_or(MathError::Overflow)?
+            .checked_mul_u128(4).ok_or(MathError::Overflow)
+    }
+
+    fn slope_u(&self) -> Result<U256, MathError> {
+        Point { u: self.v, v: self.u, ..*self }.slope_v()
+    }
+
+    // -dF/dD / D = 2 * dK/dK0 * K0 * (u + v - 1) + K * (2 - u - v) + 1/2, same scale as `gap`.
+    // None when F does not fall with D at this point.
+    fn slope_d(&self) -> Result<Option<U256>, MathError> {
+        let sum = self.u + self.v;
+        let falling = U256::mul_u128(8 * self.k, SCALE)
+            .checked_add(U256::mul_u128(8 * sum.saturating_sub(SCALE), self.dk_k0)).ok_or(MathError::Overflow)?
+            .checked_add(U256::mul_u128(2 * SCALE, SCALE)).ok_or(MathError::Overflow)?;
+        Ok(falling.checked_sub(U256::mul_u128(4 * self.k, sum)))
+    }
+}
+
+// Newton step D * gap / slope, both normalized by D^2. None when the slope vanishes or the
+// step does not fit. Gap and slope are scaled down together while D * gap would overflow.
+fn newton_step(d: Uint, gap: U256, slope: U256) -> Option<Uint> {
+    let (mut gap, mut slope) = (gap, slope);
+    while gap.bits() + 128 > 256 {
+        gap = gap.shr(1);
+        slope = slope.shr(1);
+    }
+    if slope.is_zero() {
+        return None;
+    }
+    gap.checked_mul_u128(d)?.checked_div(slope)?.to_u128()
+}
+
+// sqrt(a * b) rounded down, scaling both down when the product does not fit.
+fn geometric_mean(a: Uint, b: Uint) -> Uint {
+    match a.checked_mul(b) {
+        Some(product) => sqrt(product),
+        None => {
+            let shift = (256 - a.leading_zeros() - b.leading_zeros()).saturating_sub(126).div_ceil(2);
+            sqrt((a >> shift) * (b >> shift)) << shift
+        }
+    }
+}
+
+// Invariant of the scaled balances, safeguarded Newton on D.
+// The root lies between the constant product bound 2 * sqrt(x0 * x1), where F >= 0, and the
+// constant sum S, where F <= 0. Every iterate narrows that bracket by the sign of F, a Newton
+// step leaving it falls back to bisection, and steps are at least the tolerance so the iterates
+// straddle the root. Converged once the bracket is within the tolerance, the lower end is
+// returned so D rounds down.
+pub fn solve_crypto_d(xp: [Uint; 2], params: SolverParams) -> Result<Convergence, MathError> {
+    let [x0, x1] = xp;
+    if x0 == 0 || x1 == 0 {
+        return Err(MathError::ZeroBalance);
+    }
+    let tolerance = (params.tolerance as Uint).max(1);
+    let mut lo = 2 * geometric_mean(x0, x1);
+    let mut hi = x0.checked_add(x1).ok_or(MathError::Overflow)?;
+    let mut d = hi;
+    let mut residual = 0;
+    for iteration in 1..=params.max_iterations {
+        if hi - lo <= tolerance {
+            return Ok(Convergence { value: lo, iterations: iteration, residual, converged: true });
+        }
+        let point = Point::new(x0, x1, d)?;
+        let (positive, gap) = point.gap()?;
+        if gap.is_zero() {
+            return Ok(Convergence { value: d, iterations: iteration, residual, converged: true });
+        }
+        // F falls as D grows, a positive gap puts D below the root.
+        if positive { lo = d } else { hi = d }
+
+        let step = point.slope_d()?
+            .and_then(|slope| newton_step(d, gap, slope))
+            .map(|step| step.max(tolerance));
+        let d_next = match step {
+            Some(step) if positive => d.checked_add(step),
+            Some(step) => d.checked_sub(step),
+            None => None,
+        };
+        let d_next = match d_next {
+            Some(d_next) if d_next > lo && d_next < hi => d_next,
+            _ => lo + (hi - lo) / 2,
+        };
+        residual = d_next.abs_diff(d);
+        d = d_next;
+    }
+    Ok(Convergence { value: lo, iterations: params.max_iterations, residual, converged: false })
+}
+
+// Scaled balance of one token keeping D, given the other scaled balance x. Same safeguarded
+// Newton as `solve_crypto_d`. F grows with the balance, between max(D - x, 0) on the constant
+// sum side and D^2 / (4x) on the constant product side. The upper end of the bracket is
+// returned so the balance left in the pool rounds up.
+pub fn solve_crypto_y(x: Uint, d: Uint, params: SolverParams) -> Result<Convergence, MathError> {
+    if x == 0 || d == 0 {
+        return Err(MathError::ZeroBalance);
+    }
+    let tolerance = (params.tolerance as Uint).max(1);
+    let mut lo = d.saturating_sub(x);
+    let x4 = x.checked_mul(4).ok_or(MathError::Overflow)?;
+    let mut hi = U256::mul_u128(d, d).checked_add(U256::from_u128(x4 - 1)).ok_or(MathError::Overflow)?
+        .checked_div_u128(x4).ok_or(MathError::DivisionByZero)?
+        .to_u128().ok_or(MathError::Overflow)?;
+    let mut y = hi;
+    let mut residual = 0;
+    for iteration in 1..=params.max_iterations {
+        if hi - lo <= tolerance {
+            return Ok(Convergence { value: hi, iterations: iteration, residual, converged: true });
+        }
+        let point = Point::new(x, y, d)?;
+        let (positive, gap) = point.gap()?;
+        if gap.is_zero() {
+            return Ok(Convergence { value: y, iterations: iteration, residual, converged: true });
+        }
+        if positive { hi = y } else { lo = y }
+
+        let step = newton_step(d, gap, point.slope_v()?).map(|step| step.max(tolerance));
+        let y_next = match step {
+            Some(step) if positive => y.checked_sub(step),
+            Some(step) => y.checked_add(step),
+            None => None,
+        };
+        let y_next = match y_next {
+            Some(y_next) if y_next > lo && y_next < hi => y_next,
+            _ => lo + (hi - lo) / 2,
+        };
+        residual = y_next.abs_diff(y);
+        y = y_next;
+    }
+    Ok(Convergence { value: hi, iterations: params.max_iterations, residual, converged: false })
+}
+
+// Marginal price of x in y, 1e18 scaled. In scaled balances it is the ratio of the partial
+// derivatives of F, the price scale converts it back to x.
+pub fn crypto_marginal_price(xp: [Uint; 2], d: Uint, price_scale: Uint) -> Result<Uint, MathError> {
+    let [x0, x1] = xp;
+    if x0 == 0 || x1 == 0 {
+        return Err(MathError::EmptyPool);
+    }
+    let point = Point::new(x0, x1, d)?;
+    let scaled = ratio_scaled(point.slope_u()?, point.slope_v()?)?;
+    mul_div(scaled, price_scale, WAD)
+}
+
+// Holdings of one LP worth D at the price scale, sqrt(D / 2 * D / (2 * price_scale)).
+pub fn get_xcp(d: Uint, price_scale: Uint) -> Result<Uint, MathError> {
+    let half = d / 2;
+    Ok(geometric_mean(half, mul_div(half, WAD, price_scale)?))
+}
+
+// Virtual price of the balances at a price scale, 1e18 scaled.
+pub fn crypto_virtual_price(
+    balances: &[u64], price_scale: Uint, lp_supply: u64, params: SolverParams,
+) -> Result<Uint, MathError> {
+    if lp_supply == 0 {
+        return Err(MathError::ZeroLpSupply);
+    }
+    let d = solve_crypto_d(scale_balances(balances, price_scale)?, params)?.value()?;
+    // get_xcp(d) / lp_supply with both factors taken per LP token first, so the square root
+    // keeps 1e18 precision instead of that of xcp.
+    let half = mul_div(d, WAD, 2 * lp_supply as Uint)?;
+    Ok(geometric_mean(half, mul_div(half, WAD, price_scale)?))
+}
+
+// Balances in units of y, x valued at the price scale.
+pub fn scale_balances(balances: &[u64], price_scale: Uint) -> Result<[Uint; 2], MathError> {
+    if balances.len() != 2 {
+        return Err(MathError::InvalidTokenCount);
+    }
+    Ok([mul_div(balances[0] as Uint, price_scale, WAD)?, balances[1] as Uint])
+}
+
+// Price scale of a pool that has none yet, the ratio of its first deposit.
+pub fn initial_price_scale(balances: &[u64]) -> Result<Uint, MathError> {
+    if balances.len() != 2 {
+        return Err(MathError::InvalidTokenCount);
+    }
+    if balances[0] == 0 || balances[1] == 0 {
+        return Err(MathError::EmptyPool);
+    }
+    mul_div(balances[1] as Uint, WAD, balances[0] as Uint)
+}
+
+// Post trade repegging. Accrues the virtual price growth into the profit, then moves the
+// price scale one `ADJUSTMENT_STEP` towards the oracle when the virtual price is above half
+// of the profit (plus `ALLOWED_EXTRA_PROFIT`) and still is after the move. The other half of
+// the profit stays with the LPs, so repegging never eats into their deposits.
+pub fn tweak_price_scale(
+    balances: &[u64], lp_supply: u64, state: PriceScaleState, price_oracle: Uint, params: SolverParams,
+) -> Result<PriceScaleState, MathError> {
+    // First deposit, or first one after the pool was emptied.
+    if state.price_scale == 0 || state.virtual_price == 0 {
+        let price_scale = match state.price_scale {
+            0 => initial_price_scale(balances)?,
+            price_scale => price_scale,
+        };
+        let virtual_price = crypto_virtual_price(balances, price_scale, lp_supply, params)?;
+        return Ok(PriceScaleState { price_scale, xcp_profit: WAD, virtual_price });
+    }
+
+    let virtual_price = crypto_virtual_price(balances, state.price_scale, lp_supply, params)?;
+    let xcp_profit = mul_div(state.xcp_profit, virtual_price, state.virtual_price)?;
+    let unchanged = PriceScaleState { price_scale: state.price_scale, xcp_profit, virtual_price };
+
+    // 2 * (vp - 1) > xcp_profit - 1 + 2 * extra.
+    let spendable = (2 * virtual_price).saturating_sub(WAD);
+    if spendable <= xcp_profit + 2 * ALLOWED_EXTRA_PROFIT || price_oracle == 0 {
+        return Ok(unchanged);
+    }
+    let norm = mul_div(price_oracle, WAD, state.price_scale)?.abs_diff(WAD);
+    if norm <= ADJUSTMENT_STEP {
+        return Ok(unchanged);
+    }
+    // price_scale + step * (oracle - price_scale) / norm.
+    let price_scale = mul_div(state.price_scale, norm - ADJUSTMENT_STEP, norm)?
+        .checked_add(mul_div(price_oracle, ADJUSTMENT_STEP, norm)?).ok_or(MathError::Overflow)?;
+    let repegged = crypto_virtual_price(balances, price_scale, lp_supply, params)?;
+    if repegged > WAD && (2 * repegged).saturating_sub(WAD) > xcp_profit {
+        return Ok(PriceScaleState { price_scale, xcp_profit, virtual_price: repegged });
+    }
+    Ok(unchanged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const PARAMS: SolverParams = SolverParams::DEFAULT;
+
+    #[test]
+    fn test_crypto_d_balanced_pool() {
+        // Balanced scaled reserves sit on the peg, D is their sum.
+        let d = solve_crypto_d([1_000_000, 1_000_000], PARAMS).unwrap();
+        assert!(d.converged);
+        assert_eq!(d.value, 2_000_000);
+        assert_eq!(solve_crypto_d([0, 1_000_000], PARAMS), Err(MathError::ZeroBalance));
+    }
+
+    #[test]
+    fn test_crypto_curve_flat_at_peg_and_product_away() {
+        let d = solve_crypto_d([1_000_000_000, 1_000_000_000], PARAMS).unwrap().value;
+        // Near the peg 0.1% of the pool trades almost 1:1, like the stableswap.
+        let y = solve_crypto_y(1_001_000_000, d, PARAMS).unwrap().value;
+        assert!(1_000_000_000 - y > 999_000);
+        // Far from it, a 16x imbalance trades close to the constant product, a few percent
+        // under D^2 / 4x.
+        let y = solve_crypto_y(4_000_000_000, d, PARAMS).unwrap().value;
+        let product = d * d / 16_000_000_000;
+        assert!(y <= product + 1 && product - y < product / 20);
+    }
+
+    #[test]
+    fn test_non_convergence_is_reported() {
+        let capped = SolverParams { max_iterations: 1, tolerance: 1 };
+        let d = solve_crypto_d([1_000_000_000, 3_000_000_000], capped).unwrap();
+        assert!(!d.converged);
+        assert_eq!(d.value(), Err(MathError::NotConverged));
+    }
+
+    #[test]
+    fn test_price_scale_genesis_and_repeg() {
+        let balances = [1_000_000_000, 2_000_000_000];
+        let lp_supply = get_xcp(2 * 2_000_000_000, 2 * WAD).unwrap() as u64;
+        // The first deposit sets the price scale to its ratio, at a virtual price of 1.
+        let genesis = tweak_price_scale(&balances, lp_supply, PriceScaleState::EMPTY, 2 * WAD, PARAMS).unwrap();
+        assert_eq!(genesis.price_scale, 2 * WAD);
+        assert_eq!(genesis.xcp_profit, WAD);
+        assert!(genesis.virtual_price.abs_diff(WAD) <= 1_000_000_000);
+
+        // Without profit the oracle can't move the price scale.
+        let oracle = 2 * WAD + WAD / 10;
+        let idle = tweak_price_scale(&balances, lp_supply, genesis, oracle, PARAMS).unwrap();
+        assert_eq!(idle.price_scale, genesis.price_scale);
+
+        // Fees grew the reserves by 1%, the price scale steps towards the oracle.
+        let grown = [1_010_000_000, 2_020_000_000];
+        let repegged = tweak_price_scale(&grown, lp_supply, genesis, oracle, PARAMS).unwrap();
+        assert!(repegged.price_scale > genesis.price_scale && repegged.price_scale < oracle);
+        assert!(repegged.xcp_profit > WAD);
+        assert!(2 * repegged.virtual_price - WAD > repegged.xcp_profit);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_crypto_d_brackets_root(
+            x0 in 1_000..u64::MAX as u128,
+            ratio_bps in 100..1_000_000u128,
+        ) {
+            let x1 = (x0 * ratio_bps / 10_000).max(1);
+            let d = solve_crypto_d([x0, x1], PARAMS).unwrap();
+            prop_assert!(d.converged);
+            // D rounds down: F >= 0 at D, F <= 0 one tolerance above.
+            prop_assert!(Point::new(x0, x1, d.value).unwrap().gap().unwrap().0);
+            let (positive, gap) = Point::new(x0, x1, d.value + 2).unwrap().gap().unwrap();
+            prop_assert!(!positive || gap.is_zero());
+        }
+
+        #[test]
+        fn prop_crypto_y_inverts_d(
+            x0 in 1_000..u64::MAX as u128,
+            ratio_bps in 100..1_000_000u128,
+        ) {
+            let x1 = (x0 * ratio_bps / 10_000).max(1_000);
+            let d = solve_crypto_d([x0, x1], PARAMS).unwrap().value().unwrap();
+            let y = solve_crypto_y(x0, d, PARAMS).unwrap();
+            prop_assert!(y.converged);
+            // y rounds up, F >= 0 at y. D rounding down leaves y at most a few units under x1.
+            prop_assert!(Point::new(x0, y.value, d).unwrap().gap().unwrap().0);
+            prop_assert!(y.value <= x1 + 2, "y {} x1 {}", y.value, x1);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Invariant abstraction for the pools. Instructions price deposits, swaps and withdrawals
+//! through `Curve`, and the pool's stored `CurveType` picks the implementation, so a new
+//! invariant only needs a `Curve` impl and a `PoolCurve` variant.
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::*;
+
+// Amplification coefficient of the stableswap pools.
+pub const STABLESWAP_AMP: u64 = 100;
+// Weights of the weighted pools are scaled by 1e18 and sum to it.
+pub const WEIGHT_ONE: u64 = 1_000_000_000_000_000_000;
+// Smallest weight of a weighted pool token, 1%. Keeps the swap exponents within `MAX_POW_EXPONENT`.
+pub const MIN_WEIGHT: u64 = WEIGHT_ONE / 100;
+// LP tokens the first deposit into a pool mints to the pool itself, never to be burned. The LP
+// supply can't go back to zero, so a donation can't inflate the value of a handful of LP tokens.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+// Smallest LP amount the first deposit may mint, D for stableswap pools and the curve's
+// liquidity measure for the others. Keeps the locked share of the pool at 1% or less.
+pub const MINIMUM_INITIAL_D: u64 = 100 * MINIMUM_LIQUIDITY;
+
+pub trait Curve {
+    // Invariant of the pool's reserves, the unit LP tokens are valued in.
+    fn compute_d(&self) -> Result<u128, MathError>;
+
+    // Amount of token j out and fee kept by the pool, in token j, for `amount_in` of token i.
+    fn swap_out(&self, amount_in: u64, i: usize, j: usize) -> Result<(u64, u64), MathError>;
+
+    // LP tokens minted when the reserves grow to `new_balances`.
+    fn deposit_lp(&self, lp_supply: u64, new_balances: &[u64]) -> Result<u64, MathError>;
+
+    // Amount of token j out and fee kept by the pool for burning `lp_to_burn` into token j only.
+    fn withdraw_one(&self, lp_to_burn: u64, lp_supply: u64, j: usize) -> Result<(u64, u64), MathError>;
+
+    // Marginal price of token 0 quoted in token 1, scaled by 1e18.
+    fn spot_price(&self) -> Result<u128, MathError>;
+
+    // Value of one LP token in units of the invariant, scaled by 1e18.
+    fn virtual_price(&self, lp_supply: u64) -> Result<u128, MathError> {
+        d_per_lp(self.compute_d()?, lp_supply)
+    }
+}
+
+// Invariant a pool prices with, stored in its config.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveType {
+    // Pegged pairs, amplified invariant solved with Newton.
+    StableSwap = 0u8,
+    // Volatile pairs, x * y = k.
+    ConstantProduct = 1u8,
+    // Uneven pairs, x^wx * y^wy = k.
+    Weighted = 2u8,
+    // Correlated pairs without a fixed peg, Curve v2 invariant around a repegged price scale.
+    CryptoSwap = 3u8,
+}
+
+impl CurveType {
+    // Weighted pools take a weight of x within [MIN_WEIGHT, 1 - MIN_WEIGHT], other curves none.
+    pub fn check_weight_x(self, weight_x: u64) -> Result<(), MegaAmmProgramError> {
+        let valid = match self {
+            CurveType::Weighted => (MIN_WEIGHT..=WEIGHT_ONE - MIN_WEIGHT).contains(&weight_x),
+            _ => weight_x == 0,
+        };
+        if !valid {
+            return Err(MegaAmmProgramError::InvalidInstructionData);
+        }
+        Ok(())
+    }
+}
+
+impl From<CurveType> for u8 {
+    fn from(curve_type: CurveType) -> Self {
+        curve_type as u8
+    }
+}
+
+impl TryFrom<u8> for CurveType {
+    type Error = MegaAmmProgramError;
+    fn try_from(curve_type: u8) -> Result<Self, Self::Error> {
+        match curve_type {
+            0 => Ok(CurveType::StableSwap),
+            1 => Ok(CurveType::ConstantProduct),
+            2 => Ok(CurveType::Weighted),
+            3 => Ok(CurveType::CryptoSwap),
+            _ => Err(MegaAmmProgramError::InvalidInstructionData),
+        }
+    }
+}
+
+// A pool's curve over its reserves, dispatching to the implementation of its `CurveType`.
+pub enum PoolCurve<'b> {
+    StableSwap(MegaAmmStableSwapCurve<'b>),
+    ConstantProduct(MegaAmmConstantProductCurve<'b>),
+    Weighted(MegaAmmWeightedCurve<'b>),
+    CryptoSwap(MegaAmmCryptoSwapCurve<'b>),
+}
+
+impl<'b> Curve for PoolCurve<'b> {
+    fn compute_d(&self) -> Result<u128, MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => curve.compute_d(),
+            PoolCurve::ConstantProduct(curve) => curve.compute_d(),
+            PoolCurve::Weighted(curve) => curve.compute_d(),
+            PoolCurve::CryptoSwap(curve) => curve.compute_d(),
+        }
+    }
+
+    fn swap_out(&self, amount_in: u64, i: usize, j: usize) -> Result<(u64, u64), MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => curve.swap_out(amount_in, i, j),
+            PoolCurve::ConstantProduct(curve) => curve.swap_out(amount_in, i, j),
+            PoolCurve::Weighted(curve) => curve.swap_out(amount_in, i, j),
+            PoolCurve::CryptoSwap(curve) => curve.swap_out(amount_in, i, j),
+        }
+    }
+
+    fn deposit_lp(&self, lp_supply: u64, new_balances: &[u64]) -> Result<u64, MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => curve.deposit_lp(lp_supply, new_balances),
+            PoolCurve::ConstantProduct(curve) => curve.deposit_lp(lp_supply, new_balances),
+            PoolCurve::Weighted(curve) => curve.deposit_lp(lp_supply, new_balances),
+            PoolCurve::CryptoSwap(curve) => curve.deposit_lp(lp_supply, new_balances),
+        }
+    }
+
+    fn withdraw_one(&self, lp_to_burn: u64, lp_supply: u64, j: usize) -> Result<(u64, u64), MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => curve.withdraw_one(lp_to_burn, lp_supply, j),
+            PoolCurve::ConstantProduct(curve) => curve.withdraw_one(lp_to_burn, lp_supply, j),
+            PoolCurve::Weighted(curve) => curve.withdraw_one(lp_to_burn, lp_supply, j),
+            PoolCurve::CryptoSwap(curve) => curve.withdraw_one(lp_to_burn, lp_supply, j),
+        }
+    }
+
+    fn spot_price(&self) -> Result<u128, MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => Curve::spot_price(curve),
+            PoolCurve::ConstantProduct(curve) => curve.spot_price(),
+            PoolCurve::Weighted(curve) => curve.spot_price(),
+            PoolCurve::CryptoSwap(curve) => curve.spot_price(),
+        }
+    }
+
+    fn virtual_price(&self, lp_supply: u64) -> Result<u128, MathError> {
+        match self {
+            PoolCurve::StableSwap(curve) => Curve::virtual_price(curve, lp_supply),
+            PoolCurve::ConstantProduct(curve) => curve.virtual_price(lp_supply),
+            PoolCurve::Weighted(curve) => curve.virtual_price(lp_supply),
+            PoolCurve::CryptoSwap(curve) => curve.virtual_price(lp_supply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stableswap(balances: &[u64]) -> PoolCurve<'_> {
+        PoolCurve::StableSwap(MegaAmmStableSwapCurve {
+            balances, target_token_idx: None, fee_bps: 30, solver: SolverParams::DEFAULT,
+        })
+    }
+
+    #[test]
+    fn test_curve_type_round_trip() {
+        assert_eq!(CurveType::try_from(u8::from(CurveType::StableSwap)), Ok(CurveType::StableSwap));
+        assert_eq!(CurveType::try_from(u8::from(CurveType::ConstantProduct)), Ok(CurveType::ConstantProduct));
+        assert_eq!(CurveType::try_from(u8::from(CurveType::Weighted)), Ok(CurveType::Weighted));
+        assert_eq!(CurveType::try_from(u8::from(CurveType::CryptoSwap)), Ok(CurveType::CryptoSwap));
+        assert!(CurveType::try_from(u8::MAX).is_err());
+        assert!(CurveType::Weighted.check_weight_x(WEIGHT_ONE * 4 / 5).is_ok());
+        assert!(CurveType::Weighted.check_weight_x(MIN_WEIGHT - 1).is_err());
+        assert!(CurveType::Weighted.check_weight_x(WEIGHT_ONE - MIN_WEIGHT + 1).is_err());
+        assert!(CurveType::StableSwap.check_weight_x(0).is_ok());
+        assert!(CurveType::ConstantProduct.check_weight_x(MIN_WEIGHT).is_err());
+    }
+
+    #[test]
+    fn test_stableswap_dispatch_matches_curve_ops() {
+        let balances = [1_000_000, 3_000_000];
+        let pool = stableswap(&balances);
+        let direct = MegaAmmStableSwapCurve {
+            balances: &balances, target_token_idx: Some(1), fee_bps: 30, solver: SolverParams::DEFAULT,
+        };
+
+        assert_eq!(pool.compute_d().unwrap(), get_d(STABLESWAP_AMP, &balances).unwrap());
+        assert_eq!(pool.swap_out(10_000, 0, 1).unwrap(), direct.stableswap_with_fee(10_000, 0, STABLESWAP_AMP).unwrap());
+        assert_eq!(
+            pool.deposit_lp(1_000_000, &[1_100_000, 3_000_000]).unwrap(),
+            direct.deposit_to_amm(STABLESWAP_AMP, 1_000_000, &[1_100_000, 3_000_000]).unwrap(),
+        );
+        assert_eq!(
+            pool.withdraw_one(10_000, 1_000_000, 1).unwrap(),
+            direct.amm_imbalanced_withdrawal_with_fee(10_000, 1_000_000, STABLESWAP_AMP).unwrap(),
+        );
+        assert_eq!(pool.spot_price().unwrap(), direct.spot_price(STABLESWAP_AMP).unwrap());
+        assert_eq!(pool.virtual_price(1_000_000).unwrap(), direct.virtual_price(STABLESWAP_AMP, 1_000_000).unwrap());
+    }
+}
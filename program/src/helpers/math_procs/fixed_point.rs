@@ -0,0 +1,229 @@
+//! Fixed point exponentials for the weighted pools, values scaled by 1e18 (WAD).
+//!
+//! Error bounds, in WAD units (1 = 1e-18):
+//! - `ln` reduces x to m * 2^k with m in [1, 2) and sums 2 * atanh((m - 1) / (m + 1)).
+//!   z = (m - 1) / (m + 1) < 1/3, so the series needs at most 20 terms, each truncated by
+//!   at most 1. With k * ln(2) off by at most 0.5 per doubling and |k| <= 128, the
+//!   absolute error stays below `LN_MAX_ERROR`.
+//! - `exp` reduces x to r + k * ln(2) with |r| <= ln(2) / 2 and sums the Taylor series of e^r,
+//!   under 25 terms each truncated by at most 1 on a result near 1, then shifts by k.
+//!   Relative error below `EXP_MAX_RELATIVE_ERROR`, plus 1 from the final shift right.
+//! - `pow(b, e) = exp(e * ln(b))`. An absolute error d on the exponent is a relative error
+//!   of e^d - 1 ~ d on the result, so with e <= `MAX_POW_EXPONENT` the relative error is under
+//!   MAX_POW_EXPONENT * LN_MAX_ERROR + EXP_MAX_RELATIVE_ERROR, well inside
+//!   `MAX_POW_RELATIVE_ERROR`. `pow_up` and `pow_down` widen the raw result by that bound
+//!   plus one, so they always land above, respectively below, the exact power.
+use crate::helpers::math_procs::MathError;
+
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+// ln(2) rounded to 18 decimals, 0.693147180559945309417...
+const LN_2: i128 = 693_147_180_559_945_309;
+pub const LN_MAX_ERROR: u128 = 200;
+pub const EXP_MAX_RELATIVE_ERROR: u128 = 100;
+// Largest exponent `pow` accepts, 100 covers 1 / 1% weights.
+pub const MAX_POW_EXPONENT: u128 = 100 * WAD;
+pub const MAX_POW_RELATIVE_ERROR: u128 = 100_000; // 1e-13
+// e^x with x above this does not fit a u128 in WAD.
+const MAX_EXP_ARGUMENT: i128 = 47 * WAD as i128;
+// e^x with x below this rounds to zero in WAD.
+const MIN_EXP_ARGUMENT: i128 = -42 * WAD as i128;
+
+#[inline(always)]
+pub fn mul_down(a: u128, b: u128) -> Result<u128, MathError> {
+    crate::helpers::math_procs::numerical_ops::mul_div(a, b, WAD)
+}
+
+#[inline(always)]
+pub fn mul_up(a: u128, b: u128) -> Result<u128, MathError> {
+    let down = mul_down(a, b)?;
+    if a.checked_mul(b).map_or(true, |product| product % WAD != 0) {
+        down.checked_add(1).ok_or(MathError::Overflow)
+    } else {
+        Ok(down)
+    }
+}
+
+#[inline(always)]
+pub fn div_down(a: u128, b: u128) -> Result<u128, MathError> {
+    crate::helpers::math_procs::numerical_ops::mul_div(a, WAD, b)
+}
+
+#[inline(always)]
+pub fn div_up(a: u128, b: u128) -> Result<u128, MathError> {
+    let down = div_down(a, b)?;
+    // Rounds up unless a * WAD is known to be an exact multiple of b.
+    if a.checked_mul(WAD).map_or(true, |product| product % b != 0) {
+        down.checked_add(1).ok_or(MathError::Overflow)
+    } else {
+        Ok(down)
+    }
+}
+
+// 1 - x, floored at zero.
+#[inline(always)]
+pub fn complement(x: u128) -> u128 {
+    WAD.saturating_sub(x)
+}
+
+// Natural logarithm of a positive WAD value.
+pub fn ln(x: u128) -> Result<i128, MathError> {
+    if x == 0 {
+        return Err(MathError::ZeroBalance);
+    }
+    // x = m * 2^k with m in [1, 2).
+    let (m, k) = if x >= WAD {
+        let k = 127 - (x / WAD).leading_zeros();
+        (x >> k, k as i128)
+    } else {
+        let mut k = x.leading_zeros() - WAD.leading_zeros();
+        if x << k < WAD {
+            k += 1;
+        }
+        (x << k, -(k as i128))
+    };
+
+    // ln(m) = 2 * (z + z^3/3 + z^5/5 + ...), z = (m - 1) / (m + 1).
+    let z = ((m - WAD) * WAD / (m + WAD)) as i128;
+    let z_squared = z * z / WAD as i128;
+    let mut term = z;
+    let mut sum = 0i128;
+    let mut n = 1i128;
+    while term != 0 {
+        sum += term / n;
+        term = term * z_squared / WAD as i128;
+        n += 2;
+    }
+    Ok(k * LN_2 + 2 * sum)
+}
+
+// e^x of a signed WAD value.
+pub fn exp(x: i128) -> Result<u128, MathError> {
+    if x > MAX_EXP_ARGUMENT {
+        return Err(MathError::Overflow);
+    }
+    if x < MIN_EXP_ARGUMENT {
+        return Ok(0);
+    }
+    // x = r + k * ln(2), |r| <= ln(2) / 2.
+    let half = if x >= 0 { LN_2 / 2 } else { -LN_2 / 2 };
+    let k = (x + half) / LN_2;
+    let r = x - k * LN_2;
+
+    // e^r = 1 + r + r^2/2! + ...
+    let mut term = WAD as i128;
+    let mut sum = WAD as i128;
+    let mut n = 1i128;
+    while term != 0 {
+        term = term * r / (WAD as i128 * n);
+        sum += term;
+        n += 1;
+    }
+    let sum = sum as u128;
+    if k >= 0 {
+        if sum.leading_zeros() < k as u32 {
+            return Err(MathError::Overflow);
+        }
+        Ok(sum << k)
+    } else {
+        Ok(sum >> (-k) as u32)
+    }
+}
+
+// base^exponent, both WAD. Error bounded by `MAX_POW_RELATIVE_ERROR`, see `pow_up` and `pow_down`.
+pub fn pow(base: u128, exponent: u128) -> Result<u128, MathError> {
+    if exponent == 0 {
+        return Ok(WAD);
+    }
+    if base == 0 {
+        return Ok(0);
+    }
+    if exponent > MAX_POW_EXPONENT {
+        return Err(MathError::Overflow);
+    }
+    let exponent = exponent as i128;
+    let ln_base = ln(base)?;
+    // ln(base) * exponent / WAD, split so the product fits an i128.
+    let whole = ln_base.checked_mul(exponent / WAD as i128).ok_or(MathError::Overflow)?;
+    let fraction = ln_base.checked_mul(exponent % WAD as i128).ok_or(MathError::Overflow)? / WAD as i128;
+    exp(whole.checked_add(fraction).ok_or(MathError::Overflow)?)
+}
+
+// Upper bound of base^exponent.
+pub fn pow_up(base: u128, exponent: u128) -> Result<u128, MathError> {
+    let raw = pow(base, exponent)?;
+    let error = mul_up(raw, MAX_POW_RELATIVE_ERROR)?.checked_add(1).ok_or(MathError::Overflow)?;
+    raw.checked_add(error).ok_or(MathError::Overflow)
+}
+
+// Lower bound of base^exponent.
+pub fn pow_down(base: u128, exponent: u128) -> Result<u128, MathError> {
+    let raw = pow(base, exponent)?;
+    let error = mul_up(raw, MAX_POW_RELATIVE_ERROR)?.checked_add(1).ok_or(MathError::Overflow)?;
+    Ok(raw.saturating_sub(error))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use proptest::prelude::*;
+
+    fn to_f64(value: u128) -> f64 {
+        value as f64 / WAD as f64
+    }
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(ln(WAD).unwrap(), 0);
+        assert!((ln(2 * WAD).unwrap() - LN_2).unsigned_abs() <= LN_MAX_ERROR);
+        assert_eq!(exp(0).unwrap(), WAD);
+        // e = 2.718281828459045235...
+        assert!(exp(WAD as i128).unwrap().abs_diff(2_718_281_828_459_045_235) <= 1_000);
+        // 0.5^2 = 0.25, 4^0.5 = 2.
+        assert!(pow(WAD / 2, 2 * WAD).unwrap().abs_diff(WAD / 4) <= 1_000);
+        assert!(pow(4 * WAD, WAD / 2).unwrap().abs_diff(2 * WAD) <= 1_000);
+        assert_eq!(ln(0), Err(MathError::ZeroBalance));
+        assert_eq!(exp(48 * WAD as i128), Err(MathError::Overflow));
+        assert_eq!(exp(-43 * WAD as i128).unwrap(), 0);
+        assert_eq!(mul_up(1, 1).unwrap(), 1);
+        assert_eq!(mul_down(1, 1).unwrap(), 0);
+        assert_eq!(div_up(1, 3 * WAD).unwrap(), 1);
+        assert_eq!(div_up(WAD, 2 * WAD).unwrap(), WAD / 2);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_ln_within_bound(x in 1_000u128..u128::MAX / 4) {
+            let expected = to_f64(x).ln();
+            let actual = ln(x).unwrap() as f64 / WAD as f64;
+            // f64 itself carries a relative error of ~1e-16 on the reference.
+            let tolerance = LN_MAX_ERROR as f64 / WAD as f64 + expected.abs() * 1e-15;
+            prop_assert!((actual - expected).abs() <= tolerance, "ln({}) = {} vs {}", x, actual, expected);
+        }
+
+        #[test]
+        fn prop_exp_within_bound(x in -40 * WAD as i128..46 * WAD as i128) {
+            let expected = (x as f64 / WAD as f64).exp();
+            let actual = to_f64(exp(x).unwrap());
+            // Rounding x to f64 moves the reference by up to |x| * 2^-53 relative.
+            let tolerance = expected * (EXP_MAX_RELATIVE_ERROR as f64 / WAD as f64 + 1e-14) + 2.0 / WAD as f64;
+            prop_assert!((actual - expected).abs() <= tolerance, "exp({}) = {} vs {}", x, actual, expected);
+        }
+
+        #[test]
+        fn prop_pow_brackets_exact_value(
+            base in WAD / 2..2 * WAD,
+            exponent in WAD / 100..MAX_POW_EXPONENT,
+        ) {
+            let expected = to_f64(base).powf(to_f64(exponent));
+            prop_assume!(expected > 1e-9 && expected < 1e20);
+            // The f64 reference itself is off by up to exponent * 2^-53 relative.
+            let slack = expected * 1e-14;
+            prop_assert!(to_f64(pow_down(base, exponent).unwrap()) <= expected + slack);
+            prop_assert!(to_f64(pow_up(base, exponent).unwrap()) >= expected - slack);
+            let raw = to_f64(pow(base, exponent).unwrap());
+            let bound = expected * MAX_POW_RELATIVE_ERROR as f64 / WAD as f64 + 1.0 / WAD as f64;
+            prop_assert!((raw - expected).abs() <= bound + slack, "pow = {} vs {}", raw, expected);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Typed failures of the curve math. Each one maps to its own `MegaAmmProgramError` code
+//! so clients can tell why a trade was rejected.
+use crate::helpers::errors::MegaAmmProgramError;
+use pinocchio::error::ProgramError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathError {
+    // A checked multiplication, addition or scale down to u64 overflowed.
+    Overflow,
+    // A checked subtraction went below zero.
+    Underflow,
+    // Division by zero.
+    DivisionByZero,
+    // A pool balance is zero inside the invariant.
+    ZeroBalance,
+    // The pool holds no liquidity.
+    EmptyPool,
+    // Token index missing or out of range.
+    InvalidTokenIndex,
+    // Number of pool tokens not supported.
+    InvalidTokenCount,
+    // The invariant D went down where it may only grow.
+    InvariantDecreased,
+    // The pool can not pay out the requested amount.
+    InsufficientLiquidity,
+    // No LP tokens in circulation.
+    ZeroLpSupply,
+    // EMA half-life of zero.
+    InvalidHalfLife,
+    // TWAP target older than the oldest observation.
+    ObservationOutOfRange,
+    // TWAP window of zero seconds.
+    EmptyTwapWindow,
+    // Newton solver ran out of iterations before reaching its tolerance.
+    NotConverged,
+}
+
+impl From<MathError> for MegaAmmProgramError {
+    fn from(e: MathError) -> Self {
+        match e {
+            MathError::Overflow => MegaAmmProgramError::MathOverflow,
+            MathError::Underflow => MegaAmmProgramError::MathUnderflow,
+            MathError::DivisionByZero => MegaAmmProgramError::DivisionByZero,
+            MathError::ZeroBalance => MegaAmmProgramError::ZeroBalance,
+            MathError::EmptyPool => MegaAmmProgramError::EmptyPool,
+            MathError::InvalidTokenIndex => MegaAmmProgramError::InvalidTokenIndex,
+            MathError::InvalidTokenCount => MegaAmmProgramError::InvalidTokenCount,
+            MathError::InvariantDecreased => MegaAmmProgramError::InvariantDecreased,
+            MathError::InsufficientLiquidity => MegaAmmProgramError::InsufficientLiquidity,
+            MathError::ZeroLpSupply => MegaAmmProgramError::ZeroLpSupply,
+            MathError::InvalidHalfLife => MegaAmmProgramError::InvalidHalfLife,
+            MathError::ObservationOutOfRange => MegaAmmProgramError::ObservationOutOfRange,
+            MathError::EmptyTwapWindow => MegaAmmProgramError::EmptyTwapWindow,
+            MathError::NotConverged => MegaAmmProgramError::SolverNotConverged,
+        }
+    }
+}
+
+impl From<MathError> for ProgramError {
+    fn from(e: MathError) -> Self {
+        MegaAmmProgramError::from(e).into()
+    }
+}
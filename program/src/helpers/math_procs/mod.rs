@@ -1,6 +1,17 @@
 //! Math module for computing the AMM curve.
 pub mod numerical_ops;
+pub mod curve;
 pub mod curve_ops;
+pub mod crypto_ops;
+pub mod fixed_point;
+pub mod oracle_ops;
+pub mod math_error;
+pub mod u256;
+
+pub use math_error::MathError;
 
 use numerical_ops::*;
+use curve::*;
 use curve_ops::*;
+use crypto_ops::*;
+use oracle_ops::*;
@@ -1,14 +1,17 @@
 //! utility functions for performing price discovery math.
 use core::cmp::Ordering;
 use pinocchio_log::log;
+use crate::helpers::math_procs::MathError;
+use crate::helpers::math_procs::u256::U256;
 
 type Uint = u128; // Used to represent fixed point numbers (1e18 decimals).
 pub const MAX_TOKENS: usize = 2;
+pub const PRICE_PRECISION: Uint = 1_000_000_000_000_000_000; // 1e18
 
 // Fee calculator function. Uses ceiling division
 pub fn apply_swap_fee(
     amount_out_raw: u64, fee_bps: u64, // e.g., 30 for 0.3%
-) -> Result<u64, &'static str> {
+) -> Result<u64, MathError> {
     if fee_bps == 0 {
         return Ok(amount_out_raw);
     }
@@ -17,51 +20,150 @@ pub fn apply_swap_fee(
     // Formula: (Amount * FeeBps) / 10,000. 10,000 Bps equals 100%
     let fee = (amount_out_raw as u128)
         .checked_mul(fee_bps as u128)
-        .ok_or("Fee multiplication overflow")?
+        .ok_or(MathError::Overflow)?
         .checked_div(10_000)
-        .ok_or("Fee division error")?;
+        .ok_or(MathError::DivisionByZero)?;
 
     // Subtract fee from the raw amount
     let final_amount = (amount_out_raw as u128)
         .checked_sub(fee)
-        .ok_or("Fee underflow")?;
+        .ok_or(MathError::Underflow)?;
 
     Ok(final_amount as u64)
 }
 
+// Swap fee in units of the output token, rounded up in favour of the pool.
+pub fn swap_fee(amount_out_raw: u64, fee_bps: u64) -> Result<u64, MathError> {
+    let fee = (amount_out_raw as u128)
+        .checked_mul(fee_bps as u128).ok_or(MathError::Overflow)?
+        .checked_add(9_999u128).ok_or(MathError::Overflow)?
+        .checked_div(10_000u128).ok_or(MathError::DivisionByZero)?;
+    u64::try_from(fee).map_err(|_| MathError::Overflow)
+}
+
+// Integer square root, rounded down. Newton iterations from a power of two above the root,
+// decreasing monotonically until they stop.
+pub fn sqrt(value: Uint) -> Uint {
+    if value < 2 {
+        return value;
+    }
+    let mut x: Uint = 1 << (128 - value.leading_zeros()).div_ceil(2);
+    loop {
+        let next = (x + value / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+// Newton solver limits, stored per pool in the Config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolverParams {
+    pub max_iterations: u16,
+    // Largest step between two iterates accepted as converged.
+    pub tolerance: u64,
+}
+
+impl SolverParams {
+    pub const DEFAULT: SolverParams = SolverParams { max_iterations: 32, tolerance: 1 };
+}
+
+// Bounds for the pool level solver settings.
+pub const MAX_SOLVER_ITERATIONS: u16 = 255;
+pub const MAX_SOLVER_TOLERANCE: u64 = 1_000;
+
+// Outcome of a Newton solve. A value that did not converge is never used for pricing,
+// `value()` rejects it, diagnostics read the fields directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Convergence {
+    pub value: u128,
+    pub iterations: u16,
+    // Last step between two iterates.
+    pub residual: u128,
+    pub converged: bool,
+}
+
+impl Convergence {
+    // value u128, iterations u16, residual u128, converged u8, all little endian.
+    pub const LEN: usize = 16 + 2 + 16 + 1;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        data[0..16].copy_from_slice(&self.value.to_le_bytes());
+        data[16..18].copy_from_slice(&self.iterations.to_le_bytes());
+        data[18..34].copy_from_slice(&self.residual.to_le_bytes());
+        data[34] = self.converged as u8;
+        data
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> Result<u128, MathError> {
+        if !self.converged {
+            return Err(MathError::NotConverged);
+        }
+        Ok(self.value)
+    }
+}
+
+// Ann = A * n^n. Optimized for powers of two with bit shifts, if n=2^k then n^n = 2^(k*2^k).
+#[inline(always)]
+fn get_ann(amp: u64, n_len: usize) -> Result<Uint, MathError> {
+    let n = n_len as Uint;
+    if n_len.is_power_of_two() {
+        let k = n_len.trailing_zeros() as Uint; // Finding k where n = 2^k
+        // Shifting left by k*n to get n^n. Shifting left is multiplying by powers of 2.
+        let nn = 1u128 << (k.checked_mul(n).ok_or(MathError::Overflow)?); // n^n = 2^(k*n)
+        (amp as Uint).checked_mul(nn).ok_or(MathError::Overflow)
+    } else {
+        (amp as Uint)
+            .checked_mul(n.checked_pow(n_len as u32).ok_or(MathError::Overflow)?).ok_or(MathError::Overflow)
+    }
+}
+
 // Calculating the invariant D using Newton's method.
 // This computes the pool's total virtual liquidity surface
 // Invariant equation is Ann * sum(x_i) + D = Ann * D + D^(n+1) / (n^n * prod(x_i))
-pub fn get_d(amp: u64, balances: &[u64]) -> Result<u64, &'static str> {
+// Intermediates are 256 bit, D^(n+1) sized terms fit for balances up to u64::MAX.
+// D itself can exceed u64::MAX (two full reserves), so it is returned as u128.
+pub fn get_d(amp: u64, balances: &[u64]) -> Result<u128, MathError> {
+    get_d_with(amp, balances, SolverParams::DEFAULT)
+}
+
+// D with the pool's solver settings, rejecting a solve that did not converge.
+#[inline(always)]
+pub fn get_d_with(amp: u64, balances: &[u64], params: SolverParams) -> Result<u128, MathError> {
+    solve_d(amp, balances, params)?.value()
+}
+
+// Runs the D solver and reports how it went, converged or not.
+pub fn solve_d(amp: u64, balances: &[u64], params: SolverParams) -> Result<Convergence, MathError> {
     let n_len = balances.len();
-    // Scaling to u128 to accomodate large integer computations.
     let n = n_len as Uint;
     let sum_x: Uint = balances.iter().map(|&x| x as Uint).sum();
-    if sum_x == 0 { return Ok(0); }
+    if sum_x == 0 {
+        return Ok(Convergence { value: 0, iterations: 0, residual: 0, converged: true });
+    }
 
-    let mut d = sum_x;
-    let ann = if n_len.is_power_of_two() {
-        let k = n_len.trailing_zeros() as Uint;
-        let nn = 1u128 << (k.checked_mul(n).ok_or("Bitshift overflow")?);
-        (amp as Uint).checked_mul(nn).ok_or("Ann overflow")?
-    } else {
-        (amp as Uint)
-            .checked_mul(n.checked_pow(n_len as u32).ok_or("Power overflow")?).ok_or("Ann overflow")?
-    };
+    let ann = get_ann(amp, n_len)?;
+    // Ann * sum_x and the (Ann - 1), (n + 1) factors are constant across iterations.
+    let ann_sum = U256::mul_u128(ann, sum_x);
+    let ann_minus_one = ann.checked_sub(1).ok_or(MathError::Underflow)?;
+    let n_plus_one = n.checked_add(1).ok_or(MathError::Overflow)?;
 
-    for _ in 0..32 {
+    let tolerance = U256::from(params.tolerance);
+    let mut d = U256::from(sum_x);
+    let mut residual = U256::ZERO;
+    for iteration in 1..=params.max_iterations {
         let mut d_p = d;
         for &x in balances {
             if x == 0 {
-                return Err("Zero balance in invariant");
+                return Err(MathError::ZeroBalance);
             }
-
-            let x_u128 = x as Uint;
-            let denom = x_u128.checked_mul(n).ok_or("Overflow")?;
             // d_p = d_p * d / (x*n)
-            d_p = d_p.checked_mul(d).ok_or("D_p mul overflow")?
-                .checked_div(denom)
-                .ok_or("D_p div error")?;
+            d_p = d_p.checked_mul(d).ok_or(MathError::Overflow)?
+                .checked_div_u128((x as Uint) * n)
+                .ok_or(MathError::DivisionByZero)?;
         }
 
         // Convergence check
@@ -69,25 +171,111 @@ pub fn get_d(amp: u64, balances: &[u64]) -> Result<u64, &'static str> {
 
         // Newton's method for d.
         // d = [ (Ann * sum_x + d_p *n) * d ] / [ (Ann - 1) * d + (n+1) * d_p ]
-        let num = d.checked_mul(ann.checked_mul(sum_x).ok_or("Overflow error on computing numerator")?
-            .checked_add(d_p.checked_mul(n).ok_or("Overflow error on computing numerator")?)
-            .ok_or("Overflow error on addition in numerator")?)
-            .ok_or("Overflow on D computation")?;
-        
-        let den = d.checked_mul(ann.checked_sub(1).ok_or("Underflow error for denominator")?)
-            .ok_or("Overflow on denominator computation")?
-            .checked_add(d_p.checked_mul(n.checked_add(1).ok_or("Overflow on denominator addition")?)
-                .ok_or("Overflow on denominator multiplication")?)
-            .ok_or("Overflow on addition on the denominator")?;
+        let num = ann_sum.checked_add(d_p.checked_mul_u128(n).ok_or(MathError::Overflow)?)
+            .ok_or(MathError::Overflow)?
+            .checked_mul(d).ok_or(MathError::Overflow)?;
+
+        let den = d.checked_mul_u128(ann_minus_one).ok_or(MathError::Overflow)?
+            .checked_add(d_p.checked_mul_u128(n_plus_one).ok_or(MathError::Overflow)?)
+            .ok_or(MathError::Overflow)?;
 
-        d = num.checked_div(den).ok_or("D div error")?;
+        d = num.checked_div(den).ok_or(MathError::DivisionByZero)?;
 
         // Checking convergence.
-        if d > d_prev && d - d_prev <= 1 { return Ok(d.try_into().map_err(|_| "Error scalling down to u64")?); }
-        if d_prev > d && d_prev - d <= 1 { return Ok(d.try_into().map_err(|_| "Error scalling down to u64")?); }
+        residual = if d > d_prev { d.checked_sub(d_prev) } else { d_prev.checked_sub(d) }
+            .ok_or(MathError::Underflow)?;
+        if residual <= tolerance {
+            return Ok(Convergence {
+                value: d.to_u128().ok_or(MathError::Overflow)?,
+                iterations: iteration,
+                residual: residual.to_u128().ok_or(MathError::Overflow)?,
+                converged: true,
+            });
+        }
+    }
+    // Out of iterations, the caller decides whether the last iterate is usable.
+    Ok(Convergence {
+        value: d.to_u128().ok_or(MathError::Overflow)?,
+        iterations: params.max_iterations,
+        residual: residual.to_u128().ok_or(MathError::Overflow)?,
+        converged: false,
+    })
+}
+
+// Computes a * b / c rounding down, exact through a 256 bit product.
+pub fn mul_div(a: Uint, b: Uint, c: Uint) -> Result<Uint, MathError> {
+    if c == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    if let Some(product) = a.checked_mul(b) {
+        return Ok(product / c);
     }
-    // Return new value of the liquidity after deposit. Will be used to calculate LP tokens to mint
-    Ok(d.try_into().map_err(|_| "Error scalling down deposit")?)
+    U256::mul_u128(a, b).checked_div_u128(c)
+        .ok_or(MathError::DivisionByZero)?
+        .to_u128().ok_or(MathError::Overflow)
+}
+
+// num * 1e18 / den for 256 bit operands. When num * 1e18 does not fit both are
+// scaled down together so their ratio is kept, trading the lowest bits for range.
+pub(crate) fn ratio_scaled(num: U256, den: U256) -> Result<Uint, MathError> {
+    let (mut num, mut den) = (num, den);
+    let precision = U256::from_u128(PRICE_PRECISION);
+    let product = loop {
+        if den.is_zero() {
+            return Err(MathError::DivisionByZero);
+        }
+        match num.checked_mul(precision) {
+            Some(product) => break product,
+            None => {
+                num = num.shr(1);
+                den = den.shr(1);
+            }
+        }
+    };
+    product.checked_div(den).ok_or(MathError::DivisionByZero)?
+        .to_u128().ok_or(MathError::Overflow)
+}
+
+// Marginal price of token i in terms of token j, -dx_j/dx_i, scaled by 1e18.
+// From the invariant F = Ann * sum(x) + D - Ann * D - D^(n+1) / (n^n * prod(x)) = 0
+// the partial derivative for token k is Ann + D_p / x_k, with D_p = D^(n+1) / (n^n * prod(x)).
+// Price is the ratio of partials, rearranged to
+// x_j * (Ann * x_i + D_p) / (x_i * (Ann * x_j + D_p)).
+pub fn get_marginal_price(
+    amp: u64, balances: &[u64], i: usize, j: usize,
+) -> Result<Uint, MathError> {
+    get_marginal_price_with(amp, balances, i, j, SolverParams::DEFAULT)
+}
+
+// Marginal price with the pool's solver settings.
+pub fn get_marginal_price_with(
+    amp: u64, balances: &[u64], i: usize, j: usize, params: SolverParams,
+) -> Result<Uint, MathError> {
+    let n_len = balances.len();
+    if i >= n_len || j >= n_len || i == j {
+        return Err(MathError::InvalidTokenIndex);
+    }
+    let n = n_len as Uint;
+    let d = get_d_with(amp, balances, params)?;
+    if d == 0 {
+        return Err(MathError::EmptyPool);
+    }
+    let ann = get_ann(amp, n_len)?;
+
+    let mut d_p = U256::from_u128(d);
+    for &x in balances {
+        if x == 0 {
+            return Err(MathError::ZeroBalance);
+        }
+        d_p = d_p.checked_mul_u128(d).ok_or(MathError::Overflow)?
+            .checked_div_u128((x as Uint) * n).ok_or(MathError::DivisionByZero)?;
+    }
+
+    let x_i = balances[i] as Uint;
+    let x_j = balances[j] as Uint;
+    let num = U256::mul_u128(ann, x_i).checked_add(d_p).ok_or(MathError::Overflow)?;
+    let den = U256::mul_u128(ann, x_j).checked_add(d_p).ok_or(MathError::Overflow)?;
+    mul_div(ratio_scaled(num, den)?, x_j, x_i)
 }
 
 // Withdrawal function to withdraw one coin.
@@ -98,43 +286,57 @@ pub fn withdraw_imbalanced(
     current_balances: &[u64],
     target_token_index: usize,
     amp: u64
-) -> Result<u64, &'static str> {
+) -> Result<u64, MathError> {
+    withdraw_imbalanced_with(
+        lp_tokens_to_burn, total_lp_supply, current_balances, target_token_index, amp, SolverParams::DEFAULT,
+    )
+}
+
+// Single coin withdrawal with the pool's solver settings.
+pub fn withdraw_imbalanced_with(
+    lp_tokens_to_burn: u64,
+    total_lp_supply: u64,
+    current_balances: &[u64],
+    target_token_index: usize,
+    amp: u64,
+    params: SolverParams,
+) -> Result<u64, MathError> {
     if lp_tokens_to_burn == 0 || total_lp_supply == 0 {
         return Ok(0);
     }
     // Calculating the current liquidity D.
-    let d_current = get_d(amp, current_balances)?;
+    let d_current = get_d_with(amp, current_balances, params)?;
     // Calcuating the invariant after withdrawal to find new value of y.
-    let d_reduction = d_current.checked_mul(lp_tokens_to_burn).ok_or("Multiplication overflow")?
-        .checked_div(total_lp_supply).ok_or("Division by zero")?;
-    let d_target = d_current.checked_sub(d_reduction).ok_or("D underflow")?;
+    let d_reduction = mul_div(d_current, lp_tokens_to_burn as Uint, total_lp_supply as Uint)?;
+    let d_target = d_current.checked_sub(d_reduction).ok_or(MathError::Underflow)?;
 
     // Finding the new balance for token y.
-    let y_new = get_y(
+    let y_new = get_y_with(
         amp,
         current_balances, 
         d_target,
-        target_token_index
+        target_token_index,
+        params,
     )?;
     
     // Extracting the old balance for token y
     let old_balance = current_balances[target_token_index] as Uint;
 
-    let amount_out_raw = old_balance.checked_sub(y_new as Uint).ok_or("Withdrawal limit exceeded")?;
+    let amount_out_raw = old_balance.checked_sub(y_new as Uint).ok_or(MathError::InsufficientLiquidity)?;
 
     // Applying fee. Fee is applied where this function is called.
     //let fee = amount_out_raw.checked_div(100).unwrap_or(0); //0.1% fee
-    //let final_payment = amount_out_raw.checked_sub(fee).ok_or("Fee error")?;
-    Ok(amount_out_raw.try_into().map_err(|_| "Error scaling down withraw result")?)
+    //let final_payment = amount_out_raw.checked_sub(fee).ok_or(MathError::Underflow)?;
+    Ok(amount_out_raw.try_into().map_err(|_| MathError::Overflow)?)
 }
 
 // Withdrawing proportional amount of each token from the pool.
-pub fn withdraw_balanced(reserves: &[u64], lp_to_burn: u64, total_lp_supply: u64) -> Result<[u64; MAX_TOKENS], &'static str> {
+pub fn withdraw_balanced(reserves: &[u64], lp_to_burn: u64, total_lp_supply: u64) -> Result<[u64; MAX_TOKENS], MathError> {
     let n_len = reserves.len();
-    if n_len == 0 || n_len > MAX_TOKENS { return Err("Invalid reserve length"); }
-    if total_lp_supply == 0 { return Err("Zero total supply"); }
+    if n_len == 0 || n_len > MAX_TOKENS { return Err(MathError::InvalidTokenCount); }
+    if total_lp_supply == 0 { return Err(MathError::ZeroLpSupply); }
     if lp_to_burn == 0 { return Ok([0u64; MAX_TOKENS]); }
-    if lp_to_burn > total_lp_supply { return Err("Burn amount exceeded supply"); }
+    if lp_to_burn > total_lp_supply { return Err(MathError::InsufficientLiquidity); }
 
     let mut amount_out = [0u64; MAX_TOKENS];
     for i in 0..n_len {
@@ -142,10 +344,10 @@ pub fn withdraw_balanced(reserves: &[u64], lp_to_burn: u64, total_lp_supply: u64
         let burn_amt: Uint = lp_to_burn.into();
         let supply: Uint = total_lp_supply.into();
 
-        let out = reserve_i.checked_mul(burn_amt).ok_or("Overflowing balanced withdrawal")?
-            .checked_div(supply).ok_or("Div error for withdrawal")?;
+        let out = reserve_i.checked_mul(burn_amt).ok_or(MathError::Overflow)?
+            .checked_div(supply).ok_or(MathError::DivisionByZero)?;
 
-        amount_out[i] = out.try_into().map_err(|_| "Error scaling down balanced withdrawal")?;
+        amount_out[i] = out.try_into().map_err(|_| MathError::Overflow)?;
     }
     // Return array containing amount of each token to be sent to the user
     // returns them with the arrangement that they were supplied
@@ -168,79 +370,92 @@ pub fn withdraw_balanced(reserves: &[u64], lp_to_burn: u64, total_lp_supply: u64
 // j: Index of the token we are solving for.
 #[inline(always)]
 pub fn get_y(
-    amp: u64, balances: &[u64], d: u64, j: usize
-) -> Result<u64, &'static str> {
+    amp: u64, balances: &[u64], d: u128, j: usize
+) -> Result<u64, MathError> {
+    get_y_with(amp, balances, d, j, SolverParams::DEFAULT)
+}
+
+// y with the pool's solver settings, rejecting a solve that did not converge.
+#[inline(always)]
+pub fn get_y_with(
+    amp: u64, balances: &[u64], d: u128, j: usize, params: SolverParams,
+) -> Result<u64, MathError> {
+    u64::try_from(solve_y(amp, balances, d, j, params)?.value()?).map_err(|_| MathError::Overflow)
+}
+
+// Runs the y solver and reports how it went, converged or not.
+pub fn solve_y(
+    amp: u64, balances: &[u64], d: u128, j: usize, params: SolverParams,
+) -> Result<Convergence, MathError> {
     // No tokens available in in the pool yet
     if balances.len() == 0 {
-        return Err("Insufficient funds");
+        return Err(MathError::EmptyPool);
     }
     // Scaling up to u128 to accommodate large integer computations.
     let n_len = balances.len(); // Number of tokens
     let n = n_len as Uint;
-    let d_u128 = d as Uint;
-    // Computing A*n^n. Optimized for bitwise powers of two with bit shifts.
-    // If n=2^k, then n^n = 2^(k*2^k).
-    let ann = if n_len.is_power_of_two() {
-        let k = n_len.trailing_zeros() as Uint; // Finding k where n = 2^k
-        // Shifting left by k*n to get n^n. Shifting left is multiplying by powers of 2.
-        let nn = 1u128 << (k.checked_mul(n).ok_or("Bitshift overflow")?); // n^n = 2^(k*n)
-        (amp as Uint).checked_mul(nn).ok_or("Ann overflow")?
-    } else {
-        (amp as Uint)
-            .checked_mul(n.checked_pow(n_len as u32).ok_or("Power overflow")?).ok_or("Ann overflow")?
-    };
+    // Computing A*n^n.
+    let ann = get_ann(amp, n_len)?;
 
     let mut s_prime = 0 as Uint;
-    let mut c = d_u128;
+    // c is built in 256 bits, its D^(n+1) numerator does not fit in u128 for large pools.
+    let mut c = U256::from_u128(d);
 
     // Solving for constants b & c. Iterate through all tokens except one we are solving for.
     for (idx, &x) in balances.iter().enumerate() {
         if idx != j {
             let x_u128 = x as Uint;
             // Sum of all tokens except the output token
-            s_prime = s_prime.checked_add(x_u128).ok_or("Sum overflow")?;
+            s_prime = s_prime.checked_add(x_u128).ok_or(MathError::Overflow)?;
 
             // c = C * D / (x * n)
             // Building the term D^(n+1) / (n^n * prod(x_others))
-            c = c.checked_mul(d_u128).ok_or("C multiplication overflow")?
-                .checked_div(x_u128.checked_mul(n).ok_or("Division overflow")?)
-                .ok_or("C division error")?;
+            c = c.checked_mul_u128(d).ok_or(MathError::Overflow)?
+                .checked_div_u128(x_u128.checked_mul(n).ok_or(MathError::Overflow)?)
+                .ok_or(MathError::DivisionByZero)?;
         }
     }
-    c = c.checked_mul(d_u128).ok_or("C overflow")?
-        .checked_div(ann.checked_mul(n).ok_or("C mul error 2")?)
-        .ok_or("C division error 2")?;
-    let b = s_prime.checked_add(d_u128.checked_div(ann).ok_or("b div error")?).ok_or("B overflow")?;
+    c = c.checked_mul_u128(d).ok_or(MathError::Overflow)?
+        .checked_div_u128(ann.checked_mul(n).ok_or(MathError::Overflow)?)
+        .ok_or(MathError::DivisionByZero)?;
+    let b = s_prime.checked_add(d.checked_div(ann).ok_or(MathError::DivisionByZero)?).ok_or(MathError::Overflow)?;
 
     // Better initial guess with Bitwise shift. y=D not taken directly but y=D - S' which is much
     // closer to the root.
-    let mut y = d_u128.checked_sub(s_prime).unwrap_or(d_u128);
+    let mut y = d.checked_sub(s_prime).unwrap_or(d);
 
     // Bounds for hybrid bisection. Safeguarding Newton.
     let mut y_min = 0u128;
-    let mut y_max = d_u128 << 1; // Bitwise shift for D * 2
+    let mut y_max = d << 1; // Bitwise shift for D * 2
 
-    for _ in 0..32 {
+    let mut residual = 0u128;
+    for iteration in 1..=params.max_iterations {
         let y_prev = y;
 
         // BITWISE NEWTON STEP
         // We are computing y_next = (y^2 + c) / (2y + b - D)
-        let num = y.checked_mul(y).ok_or("y_sq overflow")?
-            .checked_add(c).ok_or("num overflow")?; // y^2 + c
+        let num = U256::mul_u128(y, y)
+            .checked_add(c).ok_or(MathError::Overflow)?; // y^2 + c
         // Use `y << 1` instead of `y * 2`.
-        let den = (y << 1).checked_add(b).ok_or("den_add overflow")?
-            .checked_sub(d_u128).ok_or("den_sub underflow")?;
+        let den = (y << 1).checked_add(b).ok_or(MathError::Overflow)?
+            .checked_sub(d).ok_or(MathError::Underflow)?;
         // We can't avoid division. Bisection fallback with shifts. We use ceiling division
         // to help round in favour of the pool.
         // (a / b) rounding up will be (a + b - 1) / b
-        let y_next = num.checked_add(den.checked_sub(1).unwrap_or(0))
-            .ok_or("Overflow in rounding up")?
-            .checked_div(den).unwrap_or_else(|| (y_min + y_max) >> 1);
+        let y_next = match num.checked_add(U256::from_u128(den.saturating_sub(1)))
+            .ok_or(MathError::Overflow)?
+            .checked_div_u128(den)
+        {
+            Some(y_next) => y_next.to_u128().ok_or(MathError::Overflow)?,
+            None => (y_min + y_max) >> 1,
+        };
 
         // BITWISE CONVERGENCE CHECK
-        // Checking if diff <= 1.
-        let diff = if y_next > y { y_next - y } else { y - y_next };
-        if diff <= 1 { return Ok(y_next as u64); }
+        // Checking if diff <= tolerance.
+        residual = if y_next > y { y_next - y } else { y - y_next };
+        if residual <= params.tolerance as Uint {
+            return Ok(Convergence { value: y_next, iterations: iteration, residual, converged: true });
+        }
 
         // BOUND ENFORCEMENT WITH SHIFTED MIDPOINT.
         if y_next > y_min && y_next < y_max {
@@ -254,18 +469,20 @@ pub fn get_y(
         if y > y_prev { y_min = y_prev; } else { y_max = y_prev; }
     }
 
-    Ok(y.try_into().map_err(|_| "Error scaling down")?)
+    Ok(Convergence { value: y, iterations: params.max_iterations, residual, converged: false })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::helpers::errors::MegaAmmProgramError;
+    use pinocchio::error::ProgramError;
     use proptest::prelude::*;
 
     type Uint = u128;
 
     // ================== Invariant Verification ==================
-    fn check_d_consistency(amp: u64, balances: &[u64], d_expected: u64) {
+    fn check_d_consistency(amp: u64, balances: &[u64], d_expected: u128) {
         let d_actual = get_d(amp, balances).expect("D calculation failed");
         let diff = if d_actual > d_expected { d_actual - d_expected } else {d_expected - d_actual};
         // Allowing tolerance of 1 due to integer truncation.
@@ -320,6 +537,109 @@ mod tests {
         assert!(d.is_ok(), "Failed to converge on extreme imbalance");
     }
 
+    #[test]
+    fn test_full_u64_reserves() {
+        // Two full reserves, D no longer fits in a u64.
+        let balances = [u64::MAX, u64::MAX];
+        let d = get_d(100, &balances).unwrap();
+        assert_eq!(d, 2 * u64::MAX as u128);
+        // Rounding in favour of the pool may push y past u64::MAX, solve just below it instead.
+        let below = [u64::MAX, u64::MAX - 1_000];
+        let y = get_y(100, &below, get_d(100, &below).unwrap(), 1).unwrap();
+        assert!(y.abs_diff(below[1]) <= 1);
+        assert_eq!(get_marginal_price(100, &balances, 0, 1).unwrap(), PRICE_PRECISION);
+    }
+
+
+    // ================== MARGINAL PRICE TESTS ==================
+    #[test]
+    fn test_marginal_price_balanced_pool() {
+        let price = get_marginal_price(100, &[1_000_000, 1_000_000], 0, 1).unwrap();
+        assert_eq!(price, PRICE_PRECISION);
+    }
+
+    #[test]
+    fn test_marginal_price_imbalanced_pool() {
+        let balances = [1_500_000, 500_000];
+        // Abundant token is cheaper than the scarce one.
+        let price_x = get_marginal_price(100, &balances, 0, 1).unwrap();
+        let price_y = get_marginal_price(100, &balances, 1, 0).unwrap();
+        assert!(price_x < PRICE_PRECISION);
+        assert!(price_y > PRICE_PRECISION);
+        // Prices in both directions are reciprocal.
+        let product = price_x * price_y / PRICE_PRECISION;
+        assert!(product > PRICE_PRECISION - 1_000 && product < PRICE_PRECISION + 1_000);
+    }
+
+    #[test]
+    fn test_mul_div_beyond_u128() {
+        assert_eq!(mul_div(u128::MAX, 2, 4).unwrap(), u128::MAX / 2);
+        assert_eq!(mul_div(10, 20, 3).unwrap(), 66);
+        assert!(mul_div(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_failures_are_typed() {
+
+        assert_eq!(mul_div(1, 1, 0), Err(MathError::DivisionByZero));
+        assert_eq!(get_d(100, &[1_000, 0]), Err(MathError::ZeroBalance));
+        assert_eq!(get_marginal_price(100, &[0, 0], 0, 1), Err(MathError::EmptyPool));
+        assert_eq!(withdraw_balanced(&[1_000, 1_000], 10, 0), Err(MathError::ZeroLpSupply));
+        assert_eq!(withdraw_balanced(&[1_000, 1_000], 11, 10), Err(MathError::InsufficientLiquidity));
+        // Math failures surface as their own program error codes.
+        assert_eq!(ProgramError::from(MathError::ZeroBalance), ProgramError::Custom(MegaAmmProgramError::ZeroBalance as u32));
+        assert_eq!(ProgramError::from(MathError::Overflow), ProgramError::Custom(9));
+    }
+
+    #[test]
+    fn test_sqrt_rounds_down() {
+        assert_eq!(sqrt(0), 0);
+        assert_eq!(sqrt(1), 1);
+        assert_eq!(sqrt(15), 3);
+        assert_eq!(sqrt(16), 4);
+        assert_eq!(sqrt(u128::MAX), u64::MAX as u128);
+        let root = sqrt((u64::MAX as u128) * (u64::MAX as u128) - 1);
+        assert_eq!(root, u64::MAX as u128 - 1);
+        assert_eq!(swap_fee(800_000, 30).unwrap(), 2_400);
+        assert_eq!(swap_fee(1, 30).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_solver_reports_non_convergence() {
+        let balances = [1_000_000, 50_000_000];
+        let tight = SolverParams { max_iterations: 1, tolerance: 1 };
+
+        let outcome = solve_d(100, &balances, tight).unwrap();
+        assert!(!outcome.converged);
+        assert_eq!(outcome.iterations, 1);
+        assert!(outcome.residual > 1);
+        // The last iterate is never used for pricing.
+        assert_eq!(outcome.value(), Err(MathError::NotConverged));
+        assert_eq!(get_d_with(100, &balances, tight), Err(MathError::NotConverged));
+
+        let d = get_d(100, &balances).unwrap();
+        assert_eq!(solve_y(100, &[2_000_000, 50_000_000], d, 1, tight).unwrap().converged, false);
+        assert_eq!(get_y_with(100, &[2_000_000, 50_000_000], d, 1, tight), Err(MathError::NotConverged));
+        assert_eq!(
+            ProgramError::from(MathError::NotConverged),
+            ProgramError::Custom(MegaAmmProgramError::SolverNotConverged as u32),
+        );
+    }
+
+    #[test]
+    fn test_solver_reports_iterations_and_residual() {
+        let balances = [1_000_000, 50_000_000];
+        let outcome = solve_d(100, &balances, SolverParams::DEFAULT).unwrap();
+        assert!(outcome.converged);
+        assert!(outcome.iterations > 1 && outcome.iterations <= SolverParams::DEFAULT.max_iterations);
+        assert!(outcome.residual <= SolverParams::DEFAULT.tolerance as u128);
+        assert_eq!(outcome.value().unwrap(), get_d(100, &balances).unwrap());
+
+        // A looser tolerance stops earlier, within that tolerance of the exact D.
+        let loose = solve_d(100, &balances, SolverParams { max_iterations: 32, tolerance: MAX_SOLVER_TOLERANCE }).unwrap();
+        assert!(loose.converged && loose.iterations <= outcome.iterations);
+        assert!(loose.value.abs_diff(outcome.value) <= MAX_SOLVER_TOLERANCE as u128);
+    }
 
     // ==================== PROPERTY TESTING FOR ROBUSTNESS =======================
     proptest! {
@@ -343,6 +663,21 @@ mod tests {
             // It can stay same or grow slightly due to rounding.
             prop_assert!(d_final >= d_initial - 1);
         }
+
+        #[test]
+        fn prop_marginal_price_bounds_swap_rate(
+            amp in 10..5000u64, bal_a in 1_000_000..100_000_000u64,
+            bal_b in 1_000_000..100_000_000u64, swap_amount in 1_000..50_000u64
+        ) {
+            // Convex curve: the average rate of a trade is never better than the marginal price.
+            let balances = [bal_a, bal_b];
+            let price = get_marginal_price(amp, &balances, 0, 1).unwrap();
+            let d = get_d(amp, &balances).unwrap();
+            let y = get_y(amp, &[bal_a + swap_amount, bal_b], d, 1).unwrap();
+            let out = (bal_b - y) as Uint;
+            // Allowing a couple of tokens for the integer rounding of D.
+            prop_assert!(out <= price * swap_amount as Uint / PRICE_PRECISION + 2);
+        }
     }
 
     // ============== TESTING WITHDRAWALS ==================================o
@@ -413,7 +748,7 @@ mod tests {
             burn_percent in 1..99u64,
         ) {
             let balances = [bal_x, bal_y];
-            let total_lp = get_d(amp, &balances).unwrap();
+            let total_lp = get_d(amp, &balances).unwrap() as u64;
             let burn_amount = (total_lp * burn_percent) / 100;
 
             let out_x = withdraw_imbalanced(burn_amount, total_lp, &balances, 0, amp).unwrap();
@@ -433,7 +768,7 @@ mod tests {
         ) {
             let balances = [bal_x, bal_y];
             let d_initial = get_d(amp, &balances).unwrap();
-            let total_lp = d_initial; // 1 D = 1 LP token
+            let total_lp = d_initial as u64; // 1 D = 1 LP token
 
             let amount_out = withdraw_imbalanced(burn_amount, total_lp, &balances, 0, amp).unwrap();
             
@@ -443,7 +778,7 @@ mod tests {
 
             // The reduction in D should match the proportion of LP tokens burned
             let d_reduction_actual = d_initial - d_final;
-            let d_reduction_expected = (d_initial as u128 * burn_amount as u128 / total_lp as u128) as u64;
+            let d_reduction_expected = d_initial * burn_amount as u128 / total_lp as u128;
 
             // Allow for tiny rounding difference
             let diff = if d_reduction_actual > d_reduction_expected {
@@ -454,6 +789,42 @@ mod tests {
             
             prop_assert!(diff <= 2);
         }
+
+        #[test]
+        fn prop_large_reserves_swap_keeps_invariant(
+            amp in 10..5000u64,
+            // 9 decimal tokens with reserves from millions of units up to u64::MAX.
+            bal_a in 1_000_000_000_000_000u64..=u64::MAX / 2,
+            bal_b in 1_000_000_000_000_000u64..=u64::MAX / 2,
+            swap_percent in 1..100u64,
+        ) {
+            let balances = [bal_a, bal_b];
+            let d = get_d(amp, &balances).unwrap();
+            // D lies between the geometric mean bound and the sum of reserves.
+            prop_assert!(d <= bal_a as u128 + bal_b as u128);
+            prop_assert!(d > 0);
+
+            let swap_amount = bal_a / 100 * swap_percent;
+            let y = get_y(amp, &[bal_a + swap_amount, bal_b], d, 1).unwrap();
+            prop_assert!(y <= bal_b);
+            // Pool side rounding keeps D from decreasing.
+            let d_after = get_d(amp, &[bal_a + swap_amount, y]).unwrap();
+            prop_assert!(d_after + 1 >= d);
+        }
+
+        #[test]
+        fn prop_extreme_reserves_solve(
+            amp in 1..10_000u64,
+            bal_a in 1..=u64::MAX,
+            bal_b in 1..u64::MAX,
+        ) {
+            let balances = [bal_a, bal_b];
+            let d = get_d(amp, &balances).unwrap();
+            prop_assert!(d <= bal_a as u128 + bal_b as u128);
+            // Solving back for either balance at the same D recovers it.
+            let y = get_y(amp, &balances, d, 1).unwrap() as u128;
+            let diff = if y > bal_b as u128 { y - bal_b as u128 } else { bal_b as u128 - y };
+            prop_assert!(diff <= 1 + bal_b as u128 / 1_000_000_000);
+        }
     }
 }
-
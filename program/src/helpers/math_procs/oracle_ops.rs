@@ -0,0 +1,198 @@
+//! Price oracle math. Exponential moving average of the curve's marginal price and
+//! time weighted averages over cumulative price observations.
+use crate::helpers::math_procs::numerical_ops::{PRICE_PRECISION, mul_div};
+use crate::helpers::math_procs::MathError;
+
+type Uint = u128;
+
+// Default EMA half-life in seconds, used when the pool is initialized.
+pub const DEFAULT_EMA_HALF_LIFE: u64 = 600;
+// Number of fractional bits used when decaying by a non integer number of half-lives.
+const DECAY_FRACTION_BITS: usize = 24;
+// 2^(-1/2^k) for k = 1..=24, scaled by 1e18. Used to build 2^(-f) bit by bit for f in [0, 1).
+const HALVING_ROOTS: [Uint; DECAY_FRACTION_BITS] = [
+    707106781186547524, 840896415253714543, 917004043204671232, 957603280698573647,
+    978572062087700135, 989228013193975484, 994599423483633176, 997296056085470126,
+    998647112890970174, 999323327502650752, 999661606496243684, 999830788931929063,
+    999915390886613498, 999957694548431133, 999978847050491930, 999989423469314464,
+    999994711720674283, 999997355856841395, 999998677927546760, 999999338963554895,
+    999999669481722826, 999999834740847758, 999999917370420465, 999999958685209379,
+];
+
+// Weight kept by the old EMA after `elapsed` seconds, 2^(-elapsed/half_life) scaled by 1e18.
+// Whole half-lives are applied with a right shift, the remainder is expanded in binary
+// and multiplied in from the HALVING_ROOTS table. Always rounds down.
+pub fn decay_factor(elapsed: u64, half_life: u64) -> Result<Uint, MathError> {
+    if half_life == 0 {
+        return Err(MathError::InvalidHalfLife);
+    }
+    let whole = elapsed / half_life;
+    if whole >= 64 {
+        return Ok(0);
+    }
+    let mut remainder = (elapsed % half_life) as Uint;
+    let half_life = half_life as Uint;
+    let mut factor = PRICE_PRECISION;
+    for root in HALVING_ROOTS.iter() {
+        if remainder == 0 { break; }
+        remainder <<= 1;
+        if remainder >= half_life {
+            remainder -= half_life;
+            factor = factor.checked_mul(*root).ok_or(MathError::Overflow)? / PRICE_PRECISION;
+        }
+    }
+    Ok(factor >> whole)
+}
+
+// Moves the EMA towards the price that prevailed since the last update.
+// ema_new = last_spot + (ema_prev - last_spot) * 2^(-elapsed/half_life)
+// Using the previous spot instead of the post trade one means a trade can not move
+// the EMA within the block it was executed in.
+pub fn ema_price(
+    ema_prev: Uint, last_spot: Uint, elapsed: u64, half_life: u64,
+) -> Result<Uint, MathError> {
+    let weight = decay_factor(elapsed, half_life)?;
+    if ema_prev >= last_spot {
+        let delta = (ema_prev - last_spot).checked_mul(weight).ok_or(MathError::Overflow)? / PRICE_PRECISION;
+        last_spot.checked_add(delta).ok_or(MathError::Overflow)
+    } else {
+        let delta = (last_spot - ema_prev).checked_mul(weight).ok_or(MathError::Overflow)? / PRICE_PRECISION;
+        last_spot.checked_sub(delta).ok_or(MathError::Underflow)
+    }
+}
+
+// Invariant D backing one LP token, scaled by 1e18.
+pub fn d_per_lp(d: u128, lp_supply: u64) -> Result<Uint, MathError> {
+    if lp_supply == 0 {
+        return Err(MathError::ZeroLpSupply);
+    }
+    mul_div(d, PRICE_PRECISION, lp_supply as Uint)
+}
+
+// Accumulator value after `elapsed` seconds at a constant `value`.
+// Accumulators wrap on overflow, only their differences are meaningful.
+#[inline(always)]
+pub fn accumulate(cumulative: Uint, value: Uint, elapsed: u64) -> Uint {
+    cumulative.wrapping_add(value.wrapping_mul(elapsed as Uint))
+}
+
+// Linear interpolation of an accumulator between two observations.
+pub fn interpolate_cumulative(
+    before_ts: i64, before: Uint, after_ts: i64, after: Uint, target_ts: i64,
+) -> Result<Uint, MathError> {
+    if target_ts < before_ts || target_ts > after_ts {
+        return Err(MathError::ObservationOutOfRange);
+    }
+    if after_ts == before_ts {
+        return Ok(before);
+    }
+    let span = (after_ts - before_ts) as Uint;
+    let offset = (target_ts - before_ts) as Uint;
+    let delta = mul_div(after.wrapping_sub(before), offset, span)?;
+    Ok(before.wrapping_add(delta))
+}
+
+// Time weighted average between two accumulator readings.
+pub fn twap_from_cumulatives(
+    start_ts: i64, start: Uint, end_ts: i64, end: Uint,
+) -> Result<Uint, MathError> {
+    if end_ts <= start_ts {
+        return Err(MathError::EmptyTwapWindow);
+    }
+    Ok(end.wrapping_sub(start) / (end_ts - start_ts) as Uint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn assert_close(actual: Uint, expected: Uint, tolerance: Uint) {
+        let diff = if actual > expected { actual - expected } else { expected - actual };
+        assert!(diff <= tolerance, "Expected {} got {}", expected, actual);
+    }
+
+    #[test]
+    fn test_decay_factor_half_lives() {
+        assert_eq!(decay_factor(0, 600).unwrap(), PRICE_PRECISION);
+        assert_eq!(decay_factor(600, 600).unwrap(), PRICE_PRECISION / 2);
+        assert_eq!(decay_factor(1_800, 600).unwrap(), PRICE_PRECISION / 8);
+        assert_eq!(decay_factor(600 * 64, 600).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_decay_factor_fractional() {
+        // Half a half-life keeps 1/sqrt(2) of the old value.
+        assert_close(decay_factor(300, 600).unwrap(), 707_106_781_186_547_524, 1_000);
+        // A quarter of a half-life keeps 2^(-1/4).
+        assert_close(decay_factor(150, 600).unwrap(), 840_896_415_253_714_543, 1_000);
+    }
+
+    #[test]
+    fn test_ema_moves_towards_prevailing_price() {
+        let ema = 1_000_000_000_000_000_000;
+        let spot = 900_000_000_000_000_000;
+        // No time elapsed, EMA does not move.
+        assert_eq!(ema_price(ema, spot, 0, 600).unwrap(), ema);
+        // After one half-life, EMA covers half the distance.
+        assert_eq!(ema_price(ema, spot, 600, 600).unwrap(), 950_000_000_000_000_000);
+        // Approaching from below.
+        assert_eq!(ema_price(spot, ema, 600, 600).unwrap(), 950_000_000_000_000_000);
+    }
+
+    #[test]
+    fn test_twap_of_constant_price() {
+        let price = 1_000_000_000_000_000_000;
+        let start = accumulate(0, price, 100);
+        let end = accumulate(start, price, 500);
+        assert_eq!(twap_from_cumulatives(100, start, 600, end).unwrap(), price);
+        assert!(twap_from_cumulatives(600, end, 600, end).is_err());
+    }
+
+    #[test]
+    fn test_twap_survives_accumulator_wrap() {
+        let price = 3_000_000_000_000_000_000;
+        let start = u128::MAX - 10;
+        let end = accumulate(start, price, 60);
+        assert!(end < start);
+        assert_eq!(twap_from_cumulatives(0, start, 60, end).unwrap(), price);
+    }
+
+    #[test]
+    fn test_interpolate_cumulative() {
+        let cumulative = interpolate_cumulative(100, 1_000, 200, 2_000, 150).unwrap();
+        assert_eq!(cumulative, 1_500);
+        assert!(interpolate_cumulative(100, 1_000, 200, 2_000, 250).is_err());
+    }
+
+    #[test]
+    fn test_d_per_lp() {
+        assert_eq!(d_per_lp(2_000_000, 2_000_000).unwrap(), PRICE_PRECISION);
+        assert_eq!(d_per_lp(2_100_000, 2_000_000).unwrap(), 1_050_000_000_000_000_000);
+        assert!(d_per_lp(2_000_000, 0).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_ema_stays_between_prices(
+            ema in 1u128..10_000_000_000_000_000_000u128,
+            spot in 1u128..10_000_000_000_000_000_000u128,
+            elapsed in 0u64..100_000u64,
+            half_life in 1u64..10_000u64,
+        ) {
+            let next = ema_price(ema, spot, elapsed, half_life).unwrap();
+            prop_assert!(next >= ema.min(spot) && next <= ema.max(spot));
+        }
+
+        #[test]
+        fn prop_decay_is_monotonic(
+            elapsed in 0u64..100_000u64,
+            step in 1u64..1_000u64,
+            half_life in 1u64..10_000u64,
+        ) {
+            let earlier = decay_factor(elapsed, half_life).unwrap();
+            let later = decay_factor(elapsed + step, half_life).unwrap();
+            prop_assert!(later <= earlier);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Minimal unsigned 256 bit integer for the invariant solvers' intermediates.
+//! Four little endian u64 limbs, only the checked operations the curve math needs.
+//! Products of two u128 values always fit, so D^3 sized terms no longer overflow.
+use core::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+
+    #[inline(always)]
+    pub const fn from_u128(value: u128) -> Self {
+        U256([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    #[inline(always)]
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    // Value as u128, None when the upper half is in use.
+    #[inline(always)]
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.0[2] != 0 || self.0[3] != 0 {
+            return None;
+        }
+        Some((self.0[0] as u128) | ((self.0[1] as u128) << 64))
+    }
+
+    #[inline(always)]
+    pub fn to_u64(&self) -> Option<u64> {
+        self.to_u128().and_then(|value| u64::try_from(value).ok())
+    }
+
+    // Number of significant bits.
+    #[inline(always)]
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+
+    // Full 256 bit product of two u128 values, never overflows.
+    #[inline(always)]
+    pub fn mul_u128(a: u128, b: u128) -> Self {
+        U256::from_u128(a).checked_mul(U256::from_u128(b)).unwrap_or(U256::ZERO)
+    }
+
+    pub fn checked_add(self, other: U256) -> Option<U256> {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for i in 0..4 {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            out[i] = sum;
+            carry = c1 || c2;
+        }
+        if carry { None } else { Some(U256(out)) }
+    }
+
+    pub fn checked_sub(self, other: U256) -> Option<U256> {
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            out[i] = diff;
+            borrow = b1 || b2;
+        }
+        if borrow { None } else { Some(U256(out)) }
+    }
+
+    // Schoolbook multiplication over the limbs, None when the product needs more than 256 bits.
+    pub fn checked_mul(self, other: U256) -> Option<U256> {
+        let mut out = [0u64; 4];
+        for i in 0..4 {
+            if self.0[i] == 0 {
+                continue;
+            }
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let product = (self.0[i] as u128) * (other.0[j] as u128) + carry;
+                if i + j < 4 {
+                    let sum = out[i + j] as u128 + (product as u64) as u128;
+                    out[i + j] = sum as u64;
+                    carry = (product >> 64) + (sum >> 64);
+                } else if product != 0 {
+                    return None;
+                } else {
+                    carry = 0;
+                }
+            }
+            if carry != 0 {
+                return None;
+            }
+        }
+        Some(U256(out))
+    }
+
+    #[inline(always)]
+    pub fn checked_mul_u128(self, other: u128) -> Option<U256> {
+        self.checked_mul(U256::from_u128(other))
+    }
+
+    // Quotient rounding down, None on division by zero.
+    // Divisors that fit in a u64 take the limb by limb path. Larger ones fall back to
+    // shift and subtract over the difference in bit lengths only, which stays short for
+    // the solvers where quotients are about the size of D.
+    pub fn checked_div(self, divisor: U256) -> Option<U256> {
+        if divisor.is_zero() {
+            return None;
+        }
+        if self < divisor {
+            return Some(U256::ZERO);
+        }
+        if divisor.bits() <= 64 {
+            let d = divisor.0[0] as u128;
+            let mut out = [0u64; 4];
+            let mut rem = 0u128;
+            for i in (0..4).rev() {
+                let current = (rem << 64) | self.0[i] as u128;
+                out[i] = (current / d) as u64;
+                rem = current % d;
+            }
+            return Some(U256(out));
+        }
+
+        let shift = self.bits() - divisor.bits();
+        let mut rem = self;
+        let mut quotient = U256::ZERO;
+        let mut shifted = divisor.shl(shift);
+        for bit in (0..=shift).rev() {
+            if rem >= shifted {
+                rem = rem.checked_sub(shifted)?;
+                quotient.set_bit(bit);
+            }
+            shifted = shifted.shr(1);
+        }
+        Some(quotient)
+    }
+
+    #[inline(always)]
+    pub fn checked_div_u128(self, divisor: u128) -> Option<U256> {
+        self.checked_div(U256::from_u128(divisor))
+    }
+
+    // Shift left by `shift` bits, dropping what leaves the top.
+    pub fn shl(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        let mut out = [0u64; 4];
+        for i in (limbs..4).rev() {
+            let src = i - limbs;
+            out[i] = self.0[src] << bits;
+            if bits > 0 && src > 0 {
+                out[i] |= self.0[src - 1] >> (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    // Shift right by `shift` bits.
+    pub fn shr(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        let mut out = [0u64; 4];
+        for i in 0..(4 - limbs) {
+            let src = i + limbs;
+            out[i] = self.0[src] >> bits;
+            if bits > 0 && src < 3 {
+                out[i] |= self.0[src + 1] << (64 - bits);
+            }
+        }
+        U256(out)
+    }
+
+    #[inline(always)]
+    fn set_bit(&mut self, bit: u32) {
+        self.0[(bit / 64) as usize] |= 1u64 << (bit % 64);
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        U256::from_u128(value)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_mul_beyond_u128() {
+        let product = U256::mul_u128(u128::MAX, u128::MAX);
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        assert_eq!(product.bits(), 256);
+        assert_eq!(product.checked_div_u128(u128::MAX).unwrap().to_u128(), Some(u128::MAX));
+        assert!(product.checked_mul_u128(2).is_none());
+        assert!(product.checked_add(product).is_none());
+        assert!(U256::ZERO.checked_sub(U256::ONE).is_none());
+        assert!(U256::ONE.checked_div(U256::ZERO).is_none());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_mul_div_round_trip(a in any::<u128>(), b in 1..u128::MAX) {
+            let product = U256::mul_u128(a, b);
+            prop_assert_eq!(product.checked_div_u128(b).unwrap().to_u128(), Some(a));
+            prop_assert_eq!(product.checked_div_u128(a.max(1)).unwrap().to_u128(), Some(if a == 0 { 0 } else { b }));
+        }
+
+        #[test]
+        fn prop_matches_u128(a in any::<u64>(), b in any::<u64>(), c in 1..u128::MAX) {
+            let (a, b) = (a as u128, b as u128);
+            let product = U256::mul_u128(a, b);
+            prop_assert_eq!(product.to_u128(), Some(a * b));
+            prop_assert_eq!(product.checked_div_u128(c).unwrap().to_u128(), Some(a * b / c));
+            prop_assert_eq!(U256::from(a).checked_add(U256::from(b)).unwrap().to_u128(), Some(a + b));
+        }
+    }
+}
@@ -2,6 +2,7 @@
 pub mod errors;
 pub mod math_procs;
 pub mod utils;
+pub mod token_extensions;
 
 use errors::*;
 use math_procs::*;
@@ -0,0 +1,291 @@
+//! Token-2022 mint extensions. A pool works with the extensions below and rejects any other
+//! when it is created. Transfer fees are withheld by the token program on every transfer, so
+//! the pool prices on what its vaults actually receive and grosses up what it pays out when
+//! the user asks for an exact amount. Transfer hooks run on every transfer too, vault
+//! transfers forward the accounts the hook needs and the hook program must be allowlisted
+//! by the pool.
+use pinocchio::{AccountView, Address, error::ProgramError};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::MathError;
+use crate::helpers::utils::TOKEN_2022_MINT_DISCRIMINATOR;
+
+// Token-2022 appends the account type and the extensions after the legacy token account length.
+const ACCOUNT_TYPE_OFFSET: usize = 165;
+const EXTENSIONS_OFFSET: usize = ACCOUNT_TYPE_OFFSET + 1;
+// Every extension is a u16 type, a u16 length and the value.
+const TLV_HEADER_LEN: usize = 4;
+const BPS: u128 = 10_000;
+
+// Token-2022 extension types.
+pub const TRANSFER_FEE_CONFIG: u16 = 1;
+pub const MINT_CLOSE_AUTHORITY: u16 = 3;
+pub const INTEREST_BEARING_CONFIG: u16 = 10;
+pub const TRANSFER_HOOK: u16 = 14;
+pub const METADATA_POINTER: u16 = 18;
+pub const TOKEN_METADATA: u16 = 19;
+pub const GROUP_POINTER: u16 = 20;
+pub const TOKEN_GROUP: u16 = 21;
+pub const GROUP_MEMBER_POINTER: u16 = 22;
+pub const TOKEN_GROUP_MEMBER: u16 = 23;
+pub const SCALED_UI_AMOUNT: u16 = 25;
+
+// Extensions that leave transfers in and out of the vaults working as the pool expects.
+// Metadata, groups and UI amounts don't touch balances, a mint can only be closed at zero
+// supply, transfer hooks are held to the pool's allowlist. Non-transferable, permanent
+// delegate, confidential transfers, default frozen accounts and pausable mints are rejected.
+const SUPPORTED_EXTENSIONS: [u16; 11] = [
+    TRANSFER_FEE_CONFIG, MINT_CLOSE_AUTHORITY, INTEREST_BEARING_CONFIG, TRANSFER_HOOK,
+    METADATA_POINTER, TOKEN_METADATA, GROUP_POINTER, TOKEN_GROUP, GROUP_MEMBER_POINTER,
+    TOKEN_GROUP_MEMBER, SCALED_UI_AMOUNT,
+];
+
+// `TransferFeeConfig` layout: two authorities, the withheld amount, then the older and the
+// newer transfer fee.
+const OLDER_TRANSFER_FEE_OFFSET: usize = 32 + 32 + 8;
+const NEWER_TRANSFER_FEE_OFFSET: usize = OLDER_TRANSFER_FEE_OFFSET + TransferFee::LEN;
+const TRANSFER_FEE_CONFIG_LEN: usize = NEWER_TRANSFER_FEE_OFFSET + TransferFee::LEN;
+// `TransferHook` layout: the authority, then the hook program. A zero program runs no hook.
+const TRANSFER_HOOK_PROGRAM_OFFSET: usize = 32;
+const TRANSFER_HOOK_LEN: usize = TRANSFER_HOOK_PROGRAM_OFFSET + 32;
+
+// Calls `f` with the type and value of every extension of a mint's data. Legacy mints and
+// token-2022 mints without extensions have none.
+fn for_each_extension(
+    data: &[u8],
+    mut f: impl FnMut(u16, &[u8]) -> Result<(), MegaAmmProgramError>,
+) -> Result<(), MegaAmmProgramError> {
+    if data.len() <= ACCOUNT_TYPE_OFFSET {
+        return Ok(());
+    }
+    if data[ACCOUNT_TYPE_OFFSET] != TOKEN_2022_MINT_DISCRIMINATOR {
+        return Err(MegaAmmProgramError::InvalidAccountData);
+    }
+    let mut offset = EXTENSIONS_OFFSET;
+    while offset + TLV_HEADER_LEN <= data.len() {
+        let extension_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        // Uninitialized entries pad the end of the account.
+        if extension_type == 0 {
+            break;
+        }
+        let len = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let start = offset + TLV_HEADER_LEN;
+        let value = data.get(start..start + len).ok_or(MegaAmmProgramError::InvalidAccountData)?;
+        f(extension_type, value)?;
+        offset = start + len;
+    }
+    Ok(())
+}
+
+// Rejects mints carrying an extension outside `SUPPORTED_EXTENSIONS`.
+pub fn check_supported_extensions(mint: &AccountView) -> Result<(), ProgramError> {
+    let data = mint.try_borrow()?;
+    for_each_extension(&data, |extension_type, _| {
+        if !SUPPORTED_EXTENSIONS.contains(&extension_type) {
+            return Err(MegaAmmProgramError::UnsupportedMintExtension);
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+// Transfer fee the mint charges in `epoch`, `TransferFee::NONE` without the extension.
+pub fn transfer_fee(mint: &AccountView, epoch: u64) -> Result<TransferFee, ProgramError> {
+    let data = mint.try_borrow()?;
+    let mut fee = TransferFee::NONE;
+    for_each_extension(&data, |extension_type, value| {
+        if extension_type == TRANSFER_FEE_CONFIG {
+            fee = TransferFee::from_config(value, epoch)?;
+        }
+        Ok(())
+    })?;
+    Ok(fee)
+}
+
+// Program the mint's transfers invoke, `None` without the extension or with the hook unset.
+pub fn transfer_hook_program(mint: &AccountView) -> Result<Option<Address>, ProgramError> {
+    let data = mint.try_borrow()?;
+    let mut program = None;
+    for_each_extension(&data, |extension_type, value| {
+        if extension_type == TRANSFER_HOOK {
+            program = transfer_hook_program_from(value)?;
+        }
+        Ok(())
+    })?;
+    Ok(program)
+}
+
+fn transfer_hook_program_from(value: &[u8]) -> Result<Option<Address>, MegaAmmProgramError> {
+    if value.len() != TRANSFER_HOOK_LEN {
+        return Err(MegaAmmProgramError::InvalidAccountData);
+    }
+    let program: [u8; 32] = value[TRANSFER_HOOK_PROGRAM_OFFSET..].try_into().unwrap();
+    if program == [0u8; 32] {
+        return Ok(None);
+    }
+    Ok(Some(Address::new_from_array(program)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferFee {
+    // First epoch the fee applies to.
+    pub epoch: u64,
+    // Cap on the fee of a single transfer.
+    pub maximum_fee: u64,
+    // Share of the amount transferred withheld as the fee, in bps.
+    pub basis_points: u16,
+}
+
+impl TransferFee {
+    pub const LEN: usize = 8 + 8 + 2;
+    pub const NONE: TransferFee = TransferFee { epoch: 0, maximum_fee: 0, basis_points: 0 };
+
+    // Fee in force in `epoch`. A scheduled fee change takes over from its epoch on.
+    fn from_config(config: &[u8], epoch: u64) -> Result<Self, MegaAmmProgramError> {
+        if config.len() != TRANSFER_FEE_CONFIG_LEN {
+            return Err(MegaAmmProgramError::InvalidAccountData);
+        }
+        let newer = Self::from_bytes(&config[NEWER_TRANSFER_FEE_OFFSET..]);
+        if epoch >= newer.epoch {
+            return Ok(newer);
+        }
+        Ok(Self::from_bytes(&config[OLDER_TRANSFER_FEE_OFFSET..]))
+    }
+
+    fn from_bytes(data: &[u8]) -> Self {
+        Self {
+            epoch: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+            maximum_fee: u64::from_le_bytes(data[8..16].try_into().unwrap()),
+            basis_points: u16::from_le_bytes(data[16..18].try_into().unwrap()),
+        }
+    }
+
+    // Fee withheld on a transfer of `amount`, rounded up and capped like token-2022 does.
+    pub fn fee(&self, amount: u64) -> u64 {
+        if self.basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let fee = (amount as u128 * self.basis_points as u128).div_ceil(BPS);
+        fee.min(self.maximum_fee as u128) as u64
+    }
+
+    // Amount received once the fee on a transfer of `amount` is withheld.
+    pub fn net(&self, amount: u64) -> u64 {
+        amount - self.fee(amount)
+    }
+
+    // Smallest transfer delivering `net_amount` once the fee is withheld.
+    pub fn gross(&self, net_amount: u64) -> Result<u64, MathError> {
+        if self.basis_points == 0 || net_amount == 0 {
+            return Ok(net_amount);
+        }
+        if self.basis_points as u128 >= BPS {
+            return net_amount.checked_add(self.maximum_fee).ok_or(MathError::Overflow);
+        }
+        let raw = (net_amount as u128 * BPS).div_ceil(BPS - self.basis_points as u128);
+        if raw - net_amount as u128 >= self.maximum_fee as u128 {
+            return net_amount.checked_add(self.maximum_fee).ok_or(MathError::Overflow);
+        }
+        u64::try_from(raw).map_err(|_| MathError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::{vec, vec::Vec};
+
+    fn tlv(extension_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = extension_type.to_le_bytes().to_vec();
+        data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
+    fn mint_data(extensions: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0u8; ACCOUNT_TYPE_OFFSET];
+        data.push(TOKEN_2022_MINT_DISCRIMINATOR);
+        for extension in extensions {
+            data.extend_from_slice(extension);
+        }
+        data
+    }
+
+    fn fee_config(older: TransferFee, newer: TransferFee) -> Vec<u8> {
+        let mut value = vec![0u8; OLDER_TRANSFER_FEE_OFFSET];
+        for fee in [older, newer] {
+            value.extend_from_slice(&fee.epoch.to_le_bytes());
+            value.extend_from_slice(&fee.maximum_fee.to_le_bytes());
+            value.extend_from_slice(&fee.basis_points.to_le_bytes());
+        }
+        value
+    }
+
+    #[test]
+    fn test_extensions_are_walked_in_order() {
+        let data = mint_data(&[tlv(METADATA_POINTER, &[7u8; 64]), tlv(TRANSFER_FEE_CONFIG, &[1u8; 108])]);
+        let mut seen = Vec::new();
+        for_each_extension(&data, |extension_type, value| {
+            seen.push((extension_type, value.len()));
+            Ok(())
+        }).unwrap();
+        assert_eq!(seen, vec![(METADATA_POINTER, 64), (TRANSFER_FEE_CONFIG, 108)]);
+
+        // Legacy mints have no extensions, truncated entries are rejected.
+        assert!(for_each_extension(&[0u8; 82], |_, _| panic!("no extensions")).is_ok());
+        let mut truncated = mint_data(&[tlv(TRANSFER_FEE_CONFIG, &[1u8; 108])]);
+        truncated.truncate(truncated.len() - 1);
+        assert_eq!(
+            for_each_extension(&truncated, |_, _| Ok(())),
+            Err(MegaAmmProgramError::InvalidAccountData),
+        );
+    }
+
+    #[test]
+    fn test_transfer_hook_program() {
+        let mut value = [3u8; TRANSFER_HOOK_LEN];
+        assert_eq!(transfer_hook_program_from(&value), Ok(Some(Address::new_from_array([3u8; 32]))));
+        // The authority can unset the hook by zeroing the program.
+        value[TRANSFER_HOOK_PROGRAM_OFFSET..].fill(0);
+        assert_eq!(transfer_hook_program_from(&value), Ok(None));
+        assert!(transfer_hook_program_from(&value[1..]).is_err());
+    }
+
+    #[test]
+    fn test_fee_follows_the_schedule() {
+        let older = TransferFee { epoch: 0, maximum_fee: 1_000, basis_points: 100 };
+        let newer = TransferFee { epoch: 10, maximum_fee: 5_000, basis_points: 250 };
+        let config = fee_config(older, newer);
+        assert_eq!(TransferFee::from_config(&config, 9), Ok(older));
+        assert_eq!(TransferFee::from_config(&config, 10), Ok(newer));
+        assert!(TransferFee::from_config(&config[1..], 10).is_err());
+    }
+
+    #[test]
+    fn test_fee_rounds_up_and_is_capped() {
+        let fee = TransferFee { epoch: 0, maximum_fee: 500, basis_points: 100 };
+        assert_eq!(fee.fee(0), 0);
+        assert_eq!(fee.fee(1), 1);
+        assert_eq!(fee.fee(10_000), 100);
+        assert_eq!(fee.fee(10_001), 101);
+        assert_eq!(fee.fee(1_000_000), 500);
+        assert_eq!(fee.net(10_000), 9_900);
+        assert_eq!(TransferFee::NONE.net(10_000), 10_000);
+    }
+
+    #[test]
+    fn test_gross_is_the_smallest_transfer_delivering_the_net_amount() {
+        for basis_points in [1u16, 25, 333, 5_000, 9_999, 10_000] {
+            for maximum_fee in [0u64, 1, 100, 1_000_000, u64::MAX] {
+                let fee = TransferFee { epoch: 0, maximum_fee, basis_points };
+                for net_amount in (0..2_000u64).chain([1_000_000_007, u64::MAX / 20_000]) {
+                    let Ok(gross) = fee.gross(net_amount) else { continue };
+                    assert_eq!(fee.net(gross), net_amount);
+                    if gross > 0 {
+                        assert!(fee.net(gross - 1) < net_amount);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,13 +3,14 @@
 use solana_address;
 use pinocchio::{
     AccountView, Address, error::ProgramError, ProgramResult,
-    cpi::{Signer, Seed}, sysvars::{rent::Rent, Sysvar}
+    cpi::{Signer, Seed, invoke_signed_with_bounds}, sysvars::{rent::Rent, Sysvar},
+    instruction::{InstructionView, InstructionAccount},
 };
 use pinocchio_token_2022::ID as TOKEN_2022_PROGRAM_ID;
-use pinocchio_system::instructions::CreateAccount;
-use pinocchio_token::instructions::{
-    InitializeMint2, InitializeAccount3, MintTo, Transfer,
-    Burn,
+use pinocchio_system::instructions::{Allocate, Assign, CreateAccount, Transfer as SystemTransfer};
+use pinocchio_token_2022::instructions::{
+    InitializeMint2, InitializeAccount3, MintTo, TransferChecked,
+    Burn, CloseAccount,
 };
 use pinocchio_associated_token_account::{
     instructions::{Create, CreateIdempotent},
@@ -18,10 +19,19 @@ use pinocchio_log::log;
 
 use crate::helpers::MegaAmmProgramError;
 use crate::config::Config;
+use crate::helpers::token_extensions::transfer_hook_program;
 
 const TOKEN_2022_ACCOUNT_DISCRIMINATOR_OFFSET: usize = 165;
 pub const TOKEN_2022_MINT_DISCRIMINATOR: u8 = 0x01;
 pub const TOKEN_2022_TOKEN_ACCOUNT_DISCRIMINATOR: u8 = 0x02;
+// Mints whose token accounts hold wrapped SOL, for the legacy token program and token-2022.
+pub const NATIVE_MINT: Address = Address::from_str_const("So11111111111111111111111111111111111111112");
+pub const NATIVE_MINT_2022: Address = Address::from_str_const("9pan9bMn5HatX4EJdBwg9VgCa7Uz5HL8N1rjtMe2Zvg");
+// Largest number of accounts forwarded to a flash loan or flash swap callback.
+pub const MAX_CALLBACK_ACCOUNTS: usize = 32;
+// Largest number of accounts forwarded to a transfer hook, the hook program and its
+// `ExtraAccountMetaList` included.
+pub const MAX_TRANSFER_HOOK_ACCOUNTS: usize = 16;
 // Signer accounts checks.
 pub struct SignerAccount;
 impl SignerAccount {
@@ -60,6 +70,41 @@ impl MintInterface {
         }
         Ok(())
     }
+
+    /// Token program owning the mint, legacy or token-2022.
+    pub fn token_program(account: &AccountView) -> Result<&'static Address, MegaAmmProgramError> {
+        if account.owned_by(&TOKEN_2022_PROGRAM_ID) {
+            Ok(&TOKEN_2022_PROGRAM_ID)
+        } else if account.owned_by(&pinocchio_token::ID) {
+            Ok(&pinocchio_token::ID)
+        } else {
+            Err(MegaAmmProgramError::InvalidOwner)
+        }
+    }
+
+    /// Confirm the token program passed by the caller is the one owning the mint.
+    pub fn check_token_program(
+        account: &AccountView, token_program: &AccountView,
+    ) -> Result<(), MegaAmmProgramError> {
+        if Self::token_program(account)?.ne(token_program.address()) {
+            return Err(MegaAmmProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+
+    /// Whether the mint is the native mint, its token accounts wrap SOL.
+    pub fn is_native(account: &AccountView) -> bool {
+        account.address().eq(&NATIVE_MINT) || account.address().eq(&NATIVE_MINT_2022)
+    }
+
+    /// Decimals of the mint, `TransferChecked` needs them.
+    pub fn decimals(account: &AccountView) -> Result<u8, ProgramError> {
+        let mint_data_ref = account.try_borrow()?;
+        let mint = unsafe {
+            pinocchio_token_2022::state::Mint::from_bytes_unchecked(&mint_data_ref)
+        };
+        Ok(mint.decimals())
+    }
 }
 
 pub struct TokenInterface;
@@ -97,7 +142,8 @@ impl MintAccount {
         account: &AccountView, payer: &AccountView,
         decimals: u8, mint_authority: &Address,
         mint_signer: &[Signer],
-        freeze_authority: Option<&Address>
+        freeze_authority: Option<&Address>,
+        token_program: &Address,
     ) -> ProgramResult {
         // Get required lamports for rent.
         let rent = Rent::get()?;
@@ -106,13 +152,14 @@ impl MintAccount {
         // We ran invoke_signed since the mint is controlled by PDA
         CreateAccount {
             from: payer, to: account, lamports, space: pinocchio_token::state::Mint::LEN as u64,
-            owner: &pinocchio_token::ID,
+            owner: token_program,
         }.invoke_signed(&mint_signer)?;
         InitializeMint2 {
             mint: account,
             decimals,
             mint_authority,
             freeze_authority,
+            token_program,
         }.invoke()
     }
 
@@ -122,11 +169,14 @@ impl MintAccount {
         decimals: u8,
         mint_authority: &Address,
         mint_signer: &[Signer],
-        freeze_authority: Option<&Address>
+        freeze_authority: Option<&Address>,
+        token_program: &Address,
     ) -> ProgramResult {
         match MintInterface::check(account) {
             Ok(_) => Ok(()),
-            Err(_) => Ok(Self::init(account, payer, decimals, mint_authority, mint_signer, freeze_authority)?),
+            Err(_) => Ok(Self::init(
+                account, payer, decimals, mint_authority, mint_signer, freeze_authority, token_program,
+            )?),
         }
     }
 }
@@ -139,6 +189,8 @@ impl TokenAccount {
         payer: &AccountView,
         owner: &Address,
     ) -> ProgramResult {
+        // The token account belongs to the program owning its mint.
+        let token_program = MintInterface::token_program(mint)?;
         // Getting the required lamports.
         let rent = Rent::get()?;
         let lamports = rent.minimum_balance(pinocchio_token::state::TokenAccount::LEN);
@@ -148,10 +200,10 @@ impl TokenAccount {
             to: account,
             lamports,
             space: pinocchio_token::state::TokenAccount::LEN as u64,
-            owner: &pinocchio_token::ID,
+            owner: token_program,
         }.invoke()?;
         InitializeAccount3 {
-            account, mint, owner
+            account, mint, owner, token_program,
         }.invoke()
     }
 
@@ -176,7 +228,8 @@ impl TokenAccount {
             mint,
             account,
             mint_authority: authority,
-            amount
+            amount,
+            token_program: MintInterface::token_program(mint)?,
         }.invoke_signed(&mint_signer)
     }
 
@@ -192,6 +245,7 @@ impl TokenAccount {
             account: from,
             authority,
             amount,
+            token_program: MintInterface::token_program(mint)?,
         };
 
         match signer_seeds {
@@ -200,28 +254,197 @@ impl TokenAccount {
         }
     }
 
+    // Transfers go through `TransferChecked` on the program owning the mint, token-2022
+    // rejects the plain `Transfer` for mints with extensions.
+    /// Transfers with `TransferChecked`. When the mint has a transfer hook, `hook_accounts` are
+    /// appended to the transfer so token-2022 can invoke the hook: the hook program, the mint's
+    /// `ExtraAccountMetaList` and the extra accounts it lists. They are ignored otherwise.
     pub fn transfer_spl_tokens(
-        from: &AccountView, to: &AccountView,
+        from: &AccountView, mint: &AccountView, to: &AccountView,
         authority: &AccountView, amount: u64,
         signer_seeds: Option<&[Signer]>,
+        hook_accounts: &[AccountView],
     ) -> ProgramResult {
-        match signer_seeds {
-            Some(seeds) => {
-                Transfer {
-                    from, to, authority, amount,
-                }.invoke_signed(seeds)
+        let decimals = MintInterface::decimals(mint)?;
+        let token_program = MintInterface::token_program(mint)?;
+        if hook_accounts.is_empty() || transfer_hook_program(mint)?.is_none() {
+            let transfer_ix = TransferChecked { from, mint, to, authority, amount, decimals, token_program };
+            return match signer_seeds {
+                Some(seeds) => transfer_ix.invoke_signed(seeds),
+                None => transfer_ix.invoke(),
+            };
+        }
+        if hook_accounts.len() > MAX_TRANSFER_HOOK_ACCOUNTS {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        }
+
+        // `TransferChecked` accounts followed by the hook accounts, which keep the privileges
+        // they were passed with.
+        const MAX_ACCOUNTS: usize = 4 + MAX_TRANSFER_HOOK_ACCOUNTS;
+        let account_count = 4 + hook_accounts.len();
+        let account_views: [&AccountView; MAX_ACCOUNTS] = core::array::from_fn(|index| match index {
+            0 => from,
+            1 => mint,
+            2 => to,
+            3 => authority,
+            _ => hook_accounts.get(index - 4).unwrap_or(mint),
+        });
+        let instruction_accounts: [InstructionAccount; MAX_ACCOUNTS] = core::array::from_fn(|index| match index {
+            0 => InstructionAccount::writable(from.address()),
+            1 => InstructionAccount::readonly(mint.address()),
+            2 => InstructionAccount::writable(to.address()),
+            3 => InstructionAccount::readonly_signer(authority.address()),
+            _ => {
+                let account = account_views[index];
+                InstructionAccount::new(account.address(), account.is_writable(), account.is_signer())
             },
-            None => {
-                Transfer {
-                    from, to, authority, amount
-                }.invoke()
+        });
+        // `TransferChecked` discriminator, amount and decimals.
+        let mut instruction_data = [0u8; 10];
+        instruction_data[0] = 12;
+        instruction_data[1..9].copy_from_slice(&amount.to_le_bytes());
+        instruction_data[9] = decimals;
+        let transfer_ix = InstructionView {
+            program_id: token_program,
+            accounts: &instruction_accounts[..account_count],
+            data: &instruction_data,
+        };
+        invoke_signed_with_bounds::<MAX_ACCOUNTS>(
+            &transfer_ix, &account_views[..account_count], signer_seeds.unwrap_or(&[]),
+        )
+    }
+}
+
+// Settling native SOL legs in lamports. Each native leg goes through a temporary WSOL account,
+// a PDA of the pool program for the user and the native mint: it is created and funded from
+// the user's lamports, pays or receives the tokens, and is closed back to the user within the
+// instruction. The user's own WSOL account is never touched.
+pub struct NativeSol;
+impl NativeSol {
+    /// Address and bump of the owner's temporary WSOL account for `mint`.
+    pub fn temporary_account(owner: &Address, mint: &Address) -> (Address, u8) {
+        Address::find_program_address(
+            &[b"wsol", owner.as_ref(), mint.as_ref()],
+            &Address::new_from_array(crate::ID),
+        )
+    }
+
+    /// Whether `account` is the owner's temporary WSOL account for `mint`. Always false for
+    /// other mints, so pools without SOL don't pay for the derivation.
+    pub fn is_temporary(account: &AccountView, owner: &AccountView, mint: &Address) -> bool {
+        (mint.eq(&NATIVE_MINT) || mint.eq(&NATIVE_MINT_2022))
+            && Self::temporary_account(owner.address(), mint).0.eq(account.address())
+    }
+
+    /// Native legs settle in SOL through the temporary accounts exactly when `native_sol` is
+    /// set, and the user's token accounts are used otherwise.
+    pub fn check_accounts(
+        native_sol: bool, owner: &AccountView, user_accounts: [(&AccountView, &AccountView); 2],
+    ) -> Result<(), MegaAmmProgramError> {
+        if native_sol && !user_accounts.iter().any(|(_, mint)| MintInterface::is_native(mint)) {
+            return Err(MegaAmmProgramError::InvalidInstructionData);
+        }
+        for (account, mint) in user_accounts {
+            if MintInterface::is_native(mint) && Self::is_temporary(account, owner, mint.address()) != native_sol {
+                return Err(MegaAmmProgramError::InvalidAccountData);
             }
         }
+        Ok(())
+    }
+
+    /// Creates the owner's temporary WSOL account with `amount` lamports wrapped on top of its
+    /// rent, both paid by the owner. Lamports sent to the address beforehand are wrapped too.
+    /// The system program must be among the instruction's accounts.
+    pub fn wrap(
+        owner: &AccountView, account: &AccountView, mint: &AccountView, amount: u64, token_program: &Address,
+    ) -> ProgramResult {
+        let (_, bump) = Self::temporary_account(owner.address(), mint.address());
+        let bump_binding = [bump];
+        let seeds = [
+            Seed::from(b"wsol"),
+            Seed::from(owner.address().as_ref()),
+            Seed::from(mint.address().as_ref()),
+            Seed::from(&bump_binding),
+        ];
+        let signer = [Signer::from(&seeds)];
+        let space = pinocchio_token::state::TokenAccount::LEN;
+        let lamports = Rent::get()?.try_minimum_balance(space)?
+            .checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        if account.lamports() == 0 {
+            CreateAccount { from: owner, to: account, lamports, space: space as u64, owner: token_program }
+                .invoke_signed(&signer)?;
+        } else {
+            // Creating an account holding lamports fails, it is funded, allocated and assigned instead.
+            SystemTransfer { from: owner, to: account, lamports }.invoke()?;
+            Allocate { account, space: space as u64 }.invoke_signed(&signer)?;
+            Assign { account, owner: token_program }.invoke_signed(&signer)?;
+        }
+        // The native mint's accounts start out with every lamport above the rent wrapped.
+        InitializeAccount3 { account, mint, owner: owner.address(), token_program }.invoke()
+    }
+
+    /// Closes the owner's temporary WSOL account to them, its wrapped SOL and rent included.
+    pub fn unwrap(owner: &AccountView, account: &AccountView, token_program: &Address) -> ProgramResult {
+        CloseAccount { account, destination: owner, authority: owner, token_program }.invoke()
+    }
+
+    /// Splits the system program, when passed first, off the trailing accounts.
+    pub fn split_system_program(
+        accounts: &[AccountView],
+    ) -> (Option<&AccountView>, &[AccountView]) {
+        match accounts {
+            [system_program, rest @ ..] if system_program.address().eq(&pinocchio_system::ID) => {
+                (Some(system_program), rest)
+            },
+            _ => (None, accounts),
+        }
+    }
+}
+
+// Calling back into a program chosen by the user, a flash loan borrower or a flash swap
+// callee. The pool never signs the callback, the accounts keep the privileges they were
+// passed with and the data is forwarded unchanged.
+pub struct Callback;
+impl Callback {
+    pub fn invoke(program: &AccountView, accounts: &[AccountView], data: &[u8]) -> ProgramResult {
+        if !program.executable() {
+            return Err(MegaAmmProgramError::IncorrectProgramId.into());
+        }
+        if accounts.len() > MAX_CALLBACK_ACCOUNTS {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        }
+        let account_views: [&AccountView; MAX_CALLBACK_ACCOUNTS] = core::array::from_fn(|index| {
+            accounts.get(index).unwrap_or(program)
+        });
+        let instruction_accounts: [InstructionAccount; MAX_CALLBACK_ACCOUNTS] = core::array::from_fn(|index| {
+            let account = account_views[index];
+            InstructionAccount::new(account.address(), account.is_writable(), account.is_signer())
+        });
+        let callback_ix = InstructionView {
+            program_id: program.address(),
+            accounts: &instruction_accounts[..accounts.len()],
+            data,
+        };
+        invoke_signed_with_bounds::<MAX_CALLBACK_ACCOUNTS>(&callback_ix, &account_views[..accounts.len()], &[])
+    }
+
+    /// Splits the callback's `len` accounts off the trailing accounts, the transfer hook
+    /// accounts follow them. The callback's accounts never reach the hooks.
+    pub fn split_accounts(
+        accounts: &[AccountView], len: u8,
+    ) -> Result<(&[AccountView], &[AccountView]), MegaAmmProgramError> {
+        accounts.split_at_checked(len as usize).ok_or(MegaAmmProgramError::NotEnoughAccountKeys)
     }
 }
 
 pub struct AssociatedTokenAccount;
 impl AssociatedTokenAccount {
+    // Address of `authority`'s associated token account for `mint`.
+    pub fn address(authority: &AccountView, mint: &Address, token_program: &AccountView) -> Address {
+        Address::find_program_address(&[authority.address().as_ref(), token_program.address().as_ref(), mint.as_ref()],
+            &pinocchio_associated_token_account::ID).0
+    }
+
     pub fn check(
         account: &AccountView, 
         authority: &AccountView,
@@ -231,8 +454,7 @@ impl AssociatedTokenAccount {
         // Validate token account structure and owner
         TokenInterface::check(account)?;
         // Validating the PDA address.
-        if Address::find_program_address(&[authority.address().as_ref(), token_program.address().as_ref(), mint.as_ref()],
-            &pinocchio_associated_token_account::ID).0.ne(account.address()) {
+        if Self::address(authority, mint, token_program).ne(account.address()) {
             return Err(MegaAmmProgramError::InvalidAddress.into());
         }
         Ok(())
@@ -317,3 +539,77 @@ impl ProgramAccount {
         account.close()
     }
 }
+
+// Read only view of a pool, shared by the query instructions.
+pub struct PoolAccounts;
+impl PoolAccounts {
+    // Callers price off these accounts, so every one of them must belong to the pool.
+    pub fn check(
+        config: &AccountView,
+        mint_lp: &AccountView,
+        vault_x: &AccountView,
+        vault_y: &AccountView,
+        token_program: &AccountView,
+    ) -> Result<(), MegaAmmProgramError> {
+        ProgramAccount::check(config)?;
+        MintInterface::check(mint_lp)?;
+        if Address::find_program_address(&[b"lp_mint", config.address().as_ref()],
+            &Address::new_from_array(crate::ID)).0.ne(mint_lp.address()) {
+            return Err(MegaAmmProgramError::InvalidAddress.into());
+        }
+        let config_state = Config::load(config)?;
+        AssociatedTokenAccount::check(vault_x, config, config_state.mint_x(), token_program)?;
+        AssociatedTokenAccount::check(vault_y, config, config_state.mint_y(), token_program)?;
+        Ok(())
+    }
+
+    // Mints of the pool's tokens, needed for checked transfers. The token program the caller
+    // passed derives the vault and user ATAs, so it must be the one owning the mints.
+    pub fn check_mints(
+        config: &Config,
+        mint_x: &AccountView,
+        mint_y: &AccountView,
+        token_program: &AccountView,
+    ) -> Result<(), MegaAmmProgramError> {
+        if mint_x.address().ne(config.mint_x()) || mint_y.address().ne(config.mint_y()) {
+            return Err(MegaAmmProgramError::InvalidAddress);
+        }
+        MintInterface::check_token_program(mint_x, token_program)?;
+        MintInterface::check_token_program(mint_y, token_program)?;
+        // A mint's hook authority can repoint the hook at any time, vault transfers only
+        // invoke the programs the pool allowlisted.
+        for mint in [mint_x, mint_y] {
+            if let Some(program) = transfer_hook_program(mint)? {
+                if !config.is_transfer_hook_allowed(&program) {
+                    return Err(MegaAmmProgramError::TransferHookNotAllowed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Reserves tracked by the pool and LP supply. Pricing off the tracked reserves instead of
+    // the vault balances keeps tokens sent straight to a vault from moving the curve.
+    // The mint borrow is dropped before returning so it can be used again for token operations.
+    pub fn reserves(config: &Config, mint_lp: &AccountView) -> Result<(u64, u64, u64), ProgramError> {
+        let mint_data_ref = mint_lp.try_borrow()?;
+        let mint_lp = unsafe {
+            pinocchio_token::state::Mint::from_bytes_unchecked(&mint_data_ref)
+        };
+        let [reserve_x, reserve_y] = config.reserves();
+        Ok((reserve_x, reserve_y, mint_lp.supply()))
+    }
+
+    // Token balances of the vaults, tracked reserves plus anything sent to them directly.
+    pub fn vault_balances(vault_x: &AccountView, vault_y: &AccountView) -> Result<[u64; 2], ProgramError> {
+        let vault_x_data_ref = vault_x.try_borrow()?;
+        let vault_x = unsafe {
+            pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_x_data_ref)
+        };
+        let vault_y_data_ref = vault_y.try_borrow()?;
+        let vault_y = unsafe {
+            pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_y_data_ref)
+        };
+        Ok([vault_x.amount(), vault_y.amount()])
+    }
+}
@@ -7,17 +7,23 @@
 use pinocchio::{
     AccountView, Address, ProgramResult,
     error::ProgramError,
-    cpi::{Signer, Seed}, sysvars::{rent::Rent, Sysvar},
+    cpi::{Signer, Seed, set_return_data}, sysvars::{rent::Rent, Sysvar},
 };
 use pinocchio_token::instructions::Transfer;
 use pinocchio::sysvars::clock::Clock;
 use crate::helpers::utils::{
-    SignerAccount, MintInterface, TokenInterface,
-    MintAccount, TokenAccount, ProgramAccount, AssociatedTokenAccount,
+    SignerAccount, MintInterface,
+    MintAccount, TokenAccount, AssociatedTokenAccount,
+    PoolAccounts, NativeSol,
 };
 use crate::helpers::errors::MegaAmmProgramError;
-use crate::helpers::math_procs::curve_ops::MegaAmmStableSwapCurve;
 use crate::config::{Config, AmmState};
+use crate::observations::update_price_oracles;
+use crate::guards::net_outflow;
+use crate::helpers::token_extensions::transfer_fee;
+use crate::events::{emit, DepositEvent};
+use crate::helpers::math_procs::curve::{Curve, MINIMUM_LIQUIDITY, MINIMUM_INITIAL_D};
+use crate::helpers::math_procs::MathError;
 use solana_address;
 use pinocchio_log::log;
 
@@ -45,6 +51,21 @@ pub struct DepositAccounts<'info> {
     //pub vault: &'info AccountView,
     // SPL Token program account. Required to perform token operations such as minting.(executable)
     pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // The pool's own associated token account for LP tokens, where the first deposit locks
+    // `MINIMUM_LIQUIDITY`. Only required by the first deposit, ahead of any other trailing
+    // account, and told apart from them by its address.(mutable)
+    pub locked_lp: Option<&'info AccountView>,
+    // System program wrapping native SOL, after `locked_lp` when SOL is deposited.
+    pub system_program: Option<&'info AccountView>,
+    // Accounts forwarded to the transfer hooks of the pool's mints: the hook programs, their
+    // `ExtraAccountMetaList` accounts and the extra accounts they list. Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
 }
 
 impl<'info> TryFrom<&'info [AccountView]> for DepositAccounts<'info> {
@@ -53,25 +74,42 @@ impl<'info> TryFrom<&'info [AccountView]> for DepositAccounts<'info> {
         let [
             user, config, mint_lp, user_lp_ata,
             vault_x, vault_y, user_x_ata, user_y_ata, 
-            token_program, _rem_data @ ..
+            token_program, observations, mint_x, mint_y, rem_data @ ..
         ] = accounts else {
             return Err(MegaAmmProgramError::InvalidAccountData.into());
         };
         // Checking the accounts.
         SignerAccount::check(user)?;
-        MintInterface::check(mint_lp)?;
-        TokenInterface::check(vault_x)?;
-        TokenInterface::check(vault_y)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
         AssociatedTokenAccount::check(user_lp_ata, user, mint_lp.address(), token_program)?;
-        // Check the config account and load it for mint checks.
-        ProgramAccount::check(config)?;
+        // Load the config account for mint checks.
         let conf_state = Config::load(config)?;
-        AssociatedTokenAccount::check(user_x_ata, user, conf_state.mint_x(), token_program)?;
-        AssociatedTokenAccount::check(user_y_ata, user, conf_state.mint_y(), token_program)?;
+        // A native leg may go through the user's temporary WSOL account instead of their ATA.
+        for (user_ata, mint) in [(user_x_ata, conf_state.mint_x()), (user_y_ata, conf_state.mint_y())] {
+            if !NativeSol::is_temporary(user_ata, user, mint) {
+                AssociatedTokenAccount::check(user_ata, user, mint, token_program)?;
+            }
+        }
+        PoolAccounts::check_mints(conf_state, mint_x, mint_y, token_program)?;
+        MintInterface::check_token_program(mint_lp, token_program)?;
+        // Nothing but the pool owns its LP account, so the LP tokens locked there can't move.
+        // Any other first trailing account is the system program or a transfer hook account.
+        let (locked_lp, rem_data) = match rem_data {
+            [locked_lp, rem_data @ ..]
+                if AssociatedTokenAccount::address(config, mint_lp.address(), token_program)
+                    .eq(locked_lp.address()) =>
+            {
+                AssociatedTokenAccount::check(locked_lp, config, mint_lp.address(), token_program)?;
+                (Some(locked_lp), rem_data)
+            },
+            _ => (None, rem_data),
+        };
+        let (system_program, transfer_hook_accounts) = NativeSol::split_system_program(rem_data);
 
         Ok(Self {
             user, mint_lp, vault_x, vault_y, user_x_ata, user_y_ata,
-            user_lp_ata, config, token_program
+            user_lp_ata, config, token_program, observations, mint_x, mint_y, locked_lp,
+            system_program, transfer_hook_accounts,
         })
     }
 }
@@ -84,25 +122,32 @@ pub struct DepositInstructionData {
     // Expiration of this order, Makes sure that the transaction has to 
     // be done within a certain amount of time.
     pub expiration: i64,
+    // The native mint side is deposited from SOL, bool value (1 or 0). Optional, 0 if absent.
+    pub native_sol: u8,
 }
 
 impl<'a> TryFrom<&'a [u8]> for DepositInstructionData {
     type Error = MegaAmmProgramError;
     fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
-        if data.len() != size_of::<DepositInstructionData>() {
+        const DEPOSIT_DATA_LEN: usize = 8 * 3;
+        if data.len() != DEPOSIT_DATA_LEN && data.len() != DEPOSIT_DATA_LEN + 1 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
 
         let amount_x = u64::from_le_bytes(data[0..8].try_into().unwrap());
         let amount_y = u64::from_le_bytes(data[8..16].try_into().unwrap());
         let expiration = i64::from_le_bytes(data[16..24].try_into().unwrap());
+        let native_sol = data.get(24).copied().unwrap_or(0);
 
         if amount_x == 0 || amount_y == 0 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
+        if native_sol != 0 && native_sol != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
 
         Ok(Self {
-            amount_x, amount_y, expiration
+            amount_x, amount_y, expiration, native_sol,
         })
     }
 }
@@ -118,6 +163,14 @@ impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Deposit<'info> {
     fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
         let accounts = DepositAccounts::try_from(accounts)?;
         let instruction_data = DepositInstructionData::try_from(data)?;
+        // Depositing SOL needs the native mint on one side and the system program to wrap it.
+        NativeSol::check_accounts(
+            instruction_data.native_sol == 1, accounts.user,
+            [(accounts.user_x_ata, accounts.mint_x), (accounts.user_y_ata, accounts.mint_y)],
+        )?;
+        if instruction_data.native_sol == 1 && accounts.system_program.is_none() {
+            return Err(MegaAmmProgramError::NotEnoughAccountKeys);
+        }
         // Returning the validated struct.
         Ok(Self {
             accounts,
@@ -126,66 +179,128 @@ impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Deposit<'info> {
     }
 }
 
+// LP tokens minted for a deposit.
+// Return data layout: [0..8] LP minted, little endian u64.
+pub struct DepositQuote {
+    pub lp_minted: u64,
+    // LP tokens minted to the pool itself, `MINIMUM_LIQUIDITY` on the first deposit, 0 after.
+    pub lp_locked: u64,
+    // Pool reserves (x, y) once the deposit settles.
+    pub post_balances: [u64; 2],
+}
+
+impl DepositQuote {
+    pub const LEN: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        self.lp_minted.to_le_bytes()
+    }
+}
+
 impl<'info> Deposit<'info> {
     pub const DISCRIMINATOR: &'info u8 = &1;
+
+    // Using the pool's curve to calculate the amount of LP tokens to be minted.
+    // Shared by Deposit and QuoteDeposit so quotes follow the exact execution path.
+    pub fn quote(
+        amm_config: &Config, vault_x_amount: u64, vault_y_amount: u64, lp_supply: u64,
+        amount_x: u64, amount_y: u64,
+    ) -> Result<DepositQuote, ProgramError> {
+        let balances = [vault_x_amount, vault_y_amount];
+        let new_x = vault_x_amount.checked_add(amount_x).ok_or(MathError::Overflow)?;
+        let new_y = vault_y_amount.checked_add(amount_y).ok_or(MathError::Overflow)?;
+        let new_balances = [new_x, new_y];
+        let lp_minted = amm_config.curve(&balances)?.deposit_lp(lp_supply, &new_balances)?;
+        if lp_supply != 0 {
+            return Ok(DepositQuote { lp_minted, lp_locked: 0, post_balances: new_balances });
+        }
+        // The first deposit sets the value of an LP token. It has to be large enough for the
+        // locked share to be dust, and `MINIMUM_LIQUIDITY` of it never belongs to anyone.
+        if lp_minted < MINIMUM_INITIAL_D {
+            return Err(MegaAmmProgramError::InitialLiquidityTooLow.into());
+        }
+        Ok(DepositQuote {
+            lp_minted: lp_minted - MINIMUM_LIQUIDITY,
+            lp_locked: MINIMUM_LIQUIDITY,
+            post_balances: new_balances,
+        })
+    }
+
     // Create vault and submit tokens x and y to the vault.
     pub fn process(&mut self) -> ProgramResult {
         // We first load the config account from self.accounts.config.
-        let amm_config = Config::load(self.accounts.config)?;
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
         if amm_config.state() != AmmState::Initialized.into() {
             return Err(MegaAmmProgramError::Unauthorized.into());
         }
 
         // Deserializing the token accounts. 
-        // Context added to drop all borrows before the accounts are used again
+        // Borrows are dropped before the accounts are used again
         // for transfer instructions etc.
-        let (vault_x_amount, vault_y_amount, lp_supply) = {
-            let mint_data_ref = self.accounts.mint_lp.try_borrow()?;
-            let mint_lp = unsafe {
-                pinocchio_token::state::Mint::from_bytes_unchecked(&mint_data_ref)
-            };
-            let vault_x_data_ref = self.accounts.vault_x.try_borrow()?;
-            let vault_x = unsafe {
-                pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_x_data_ref)
-            };
-            let vault_y_data_ref = self.accounts.vault_y.try_borrow()?;
-            let vault_y = unsafe {
-                pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_y_data_ref)
-            };
-            (vault_x.amount(), vault_y.amount(), mint_lp.supply())
-        };
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
 
-        // Using newton to calculate the amount of LP tokens to be minted.
-        // We provide the amounts of token x and y that we want to deposit 
-        // in the liquidity pool.
-        let balances = [vault_x_amount, vault_y_amount];
-        let curve = MegaAmmStableSwapCurve { balances: &balances, target_token_idx: None, fee_bps: 0 };
-        let new_x = vault_x_amount.checked_add(self.instruction_data.amount_x).ok_or( ProgramError::Custom(0) )?;
-        let new_y = vault_y_amount.checked_add(self.instruction_data.amount_y).ok_or( ProgramError::Custom(0) )?;
-        let new_balances = [new_x, new_y];
-        let mint_lp_amount = curve.deposit_to_amm(
-            100u64, lp_supply, &new_balances
-        ).map_err(|e| { ProgramError::Custom(0) })?;
+        // Token-2022 transfer fees are withheld from what the vaults receive, LP tokens are
+        // minted for the net amounts.
+        let clock = Clock::get()?;
+        let inflow = [
+            transfer_fee(self.accounts.mint_x, clock.epoch)?.net(self.instruction_data.amount_x),
+            transfer_fee(self.accounts.mint_y, clock.epoch)?.net(self.instruction_data.amount_y),
+        ];
+        let quote = Self::quote(
+            amm_config, vault_x_amount, vault_y_amount, lp_supply, inflow[0], inflow[1],
+        )?;
+        let mint_lp_amount = quote.lp_minted;
+
+        // Deposits count as inflows against the rolling outflow limit.
+        amm_config.record_outflow(clock.unix_timestamp, net_outflow(inflow, [0; 2])?)?;
+
+        // SOL deposited is wrapped into a temporary WSOL account first.
+        let native_sol = self.instruction_data.native_sol == 1;
+        let token_program = self.accounts.token_program.address();
+        let user_accounts = [
+            (self.accounts.user_x_ata, self.accounts.mint_x, self.instruction_data.amount_x),
+            (self.accounts.user_y_ata, self.accounts.mint_y, self.instruction_data.amount_y),
+        ];
+        if native_sol {
+            for (user_account, mint, amount) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::wrap(self.accounts.user, user_account, mint, amount, token_program)?;
+                }
+            }
+        }
 
         // Transfer tokens(x & y) from ata to vaults/token accounts of the pool.
         // Amount to transfer is calculated from the lp token to be minted.
         TokenAccount::transfer_spl_tokens(
             self.accounts.user_x_ata,
+            self.accounts.mint_x,
             self.accounts.vault_x,
             self.accounts.user, // Wallet signer
             self.instruction_data.amount_x, // x tokens amount to transfer
             None, // user signs normally.
+            self.accounts.transfer_hook_accounts,
         )?;
         TokenAccount::transfer_spl_tokens(
             self.accounts.user_y_ata,
+            self.accounts.mint_y,
             self.accounts.vault_y,
             self.accounts.user, // Wallet signer
             self.instruction_data.amount_y, // y token amounts to transfer
             None, // user signs normally.
+            self.accounts.transfer_hook_accounts,
         )?;
+        // The emptied temporary WSOL account is closed, its rent goes back to the user.
+        if native_sol {
+            for (user_account, mint, _) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::unwrap(self.accounts.user, user_account, token_program)?;
+                }
+            }
+        }
 
-        // Getting the lp bump
-        let (expected_lp_mint, lp_bump) = Address::find_program_address(
+        // Getting the lp bump, the mint itself is checked with the pool accounts.
+        let (_, lp_bump) = Address::find_program_address(
             &[b"lp_mint", self.accounts.config.address().as_ref()],
             &crate::ID.into()
         );
@@ -206,6 +321,41 @@ impl<'info> Deposit<'info> {
             mint_lp_amount,
             &mint_signer,
         )?;
+        if quote.lp_locked != 0 {
+            let locked_lp = self.accounts.locked_lp.ok_or(MegaAmmProgramError::NotEnoughAccountKeys)?;
+            TokenAccount::mint_tokens(
+                self.accounts.mint_lp,
+                locked_lp,
+                self.accounts.mint_lp,
+                quote.lp_locked,
+                &mint_signer,
+            )?;
+        }
+
+        // The pool prices off its own reserves, not the vault balances.
+        amm_config.set_reserves(&quote.post_balances);
+
+        // Feeding the price oracles with the post deposit reserves.
+        let post_lp_supply = lp_supply.checked_add(mint_lp_amount)
+            .and_then(|supply| supply.checked_add(quote.lp_locked))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations,
+            &quote.post_balances, post_lp_supply,
+        )?;
+        set_return_data(&quote.to_bytes());
+        emit(&DepositEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            user: self.accounts.user.address().to_bytes(),
+            amount_x: self.instruction_data.amount_x,
+            amount_y: self.instruction_data.amount_y,
+            lp_minted: mint_lp_amount,
+            reserve_x: quote.post_balances[0],
+            reserve_y: quote.post_balances[1],
+            lp_supply: post_lp_supply,
+            d: amm_config.curve(&quote.post_balances)?.compute_d()?,
+        }.to_bytes());
+        amm_config.unlock();
         Ok(())
     }
 }
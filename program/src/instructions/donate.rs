@@ -0,0 +1,181 @@
+//! Donates tokens x and y to the pool's LPs. The tokens join the reserves and grow D
+//! without minting LP tokens, so every LP token is worth more. Donations must keep the
+//! ratio of the reserves, they raise the value of the pool but never move its price.
+use pinocchio::{
+    AccountView, ProgramResult,
+};
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use crate::helpers::utils::{
+    SignerAccount, TokenAccount, ProgramAccount, AssociatedTokenAccount, PoolAccounts,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::MathError;
+use crate::config::{Config, AmmState};
+use crate::observations::update_price_oracles;
+use crate::guards::{check_balanced_donation, net_outflow};
+use crate::helpers::token_extensions::transfer_fee;
+use crate::events::{emit, DonateEvent};
+use crate::helpers::math_procs::curve::Curve;
+
+pub struct DonateAccounts<'info> {
+    // Account donating the tokens.(signer)
+    pub donor: &'info AccountView,
+    // The config account for the AMM pool.(mutable)
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.(mutable)
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.(mutable)
+    pub vault_y: &'info AccountView,
+    // Donor associated token account for token x.(mutable)
+    pub donor_x_ata: &'info AccountView,
+    // Donor associated token account for token y.(mutable)
+    pub donor_y_ata: &'info AccountView,
+    // SPL token program account.
+    pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // Accounts forwarded to the transfer hooks of the pool's mints: the hook programs, their
+    // `ExtraAccountMetaList` accounts and the extra accounts they list. Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for DonateAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [
+            donor, config, mint_lp, vault_x, vault_y,
+            donor_x_ata, donor_y_ata, token_program, observations, mint_x, mint_y, transfer_hook_accounts @ ..
+        ] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        SignerAccount::check(donor)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+        let config_state = Config::load(config)?;
+        AssociatedTokenAccount::check(donor_x_ata, donor, config_state.mint_x(), token_program)?;
+        AssociatedTokenAccount::check(donor_y_ata, donor, config_state.mint_y(), token_program)?;
+        PoolAccounts::check_mints(config_state, mint_x, mint_y, token_program)?;
+
+        Ok(Self {
+            donor, config, mint_lp, vault_x, vault_y,
+            donor_x_ata, donor_y_ata, token_program, observations, mint_x, mint_y,
+            transfer_hook_accounts,
+        })
+    }
+}
+
+pub struct DonateInstructionData {
+    // Amount of token x donated.
+    pub amount_x: u64,
+    // Amount of token y donated.
+    pub amount_y: u64,
+}
+
+impl<'a> TryFrom<&'a [u8]> for DonateInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
+        if data.len() != size_of::<DonateInstructionData>() {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let amount_x = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let amount_y = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+        if amount_x == 0 && amount_y == 0 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { amount_x, amount_y })
+    }
+}
+
+pub struct Donate<'info> {
+    pub accounts: DonateAccounts<'info>,
+    pub instruction_data: DonateInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Donate<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = DonateAccounts::try_from(accounts)?;
+        let instruction_data = DonateInstructionData::try_from(data)?;
+
+        Ok(Self { accounts, instruction_data })
+    }
+}
+
+impl<'info> Donate<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &14;
+
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+        if amm_config.state() != AmmState::Initialized.into() {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        // Without LP tokens there is no one to donate to, the next first deposit would take it all.
+        let (reserve_x, reserve_y, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        if lp_supply == 0 {
+            return Err(MegaAmmProgramError::EmptyPool.into());
+        }
+        // Token-2022 transfer fees are withheld from what the vaults receive, only the net
+        // amounts join the reserves.
+        let clock = Clock::get()?;
+        let gross_amounts = [self.instruction_data.amount_x, self.instruction_data.amount_y];
+        let amounts = [
+            transfer_fee(self.accounts.mint_x, clock.epoch)?.net(gross_amounts[0]),
+            transfer_fee(self.accounts.mint_y, clock.epoch)?.net(gross_amounts[1]),
+        ];
+        check_balanced_donation([reserve_x, reserve_y], amounts)?;
+        let post_balances = [
+            reserve_x.checked_add(amounts[0]).ok_or(MathError::Overflow)?,
+            reserve_y.checked_add(amounts[1]).ok_or(MathError::Overflow)?,
+        ];
+
+        // Donations count as inflows against the rolling outflow limit.
+        amm_config.record_outflow(clock.unix_timestamp, net_outflow(amounts, [0; 2])?)?;
+
+        let transfers = [
+            (self.accounts.donor_x_ata, self.accounts.mint_x, self.accounts.vault_x, gross_amounts[0]),
+            (self.accounts.donor_y_ata, self.accounts.mint_y, self.accounts.vault_y, gross_amounts[1]),
+        ];
+        for (from, mint, to, amount) in transfers {
+            if amount == 0 {
+                continue;
+            }
+            TokenAccount::transfer_spl_tokens(
+                from,
+                mint,
+                to,
+                self.accounts.donor, // Wallet signer
+                amount,
+                None, // donor signs normally.
+                self.accounts.transfer_hook_accounts,
+            )?;
+        }
+
+        // The donation joins the reserves, the LP supply stays the same.
+        amm_config.set_reserves(&post_balances);
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations, &post_balances, lp_supply,
+        )?;
+        emit(&DonateEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            donor: self.accounts.donor.address().to_bytes(),
+            amount_x: amounts[0],
+            amount_y: amounts[1],
+            reserve_x: post_balances[0],
+            reserve_y: post_balances[1],
+            d: amm_config.curve(&post_balances)?.compute_d()?,
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Lends vault reserves for the length of one instruction. The amounts borrowed are sent to
+//! the borrower, the borrower program is called back with the trailing accounts, and the
+//! vaults must then hold what they held before plus a fee, which joins the reserves and
+//! accrues to the LPs.
+use pinocchio::{
+    AccountView, Address, ProgramResult,
+    cpi::{Seed, Signer},
+};
+use pinocchio::sysvars::{clock::Clock, Sysvar};
+use crate::helpers::utils::{
+    SignerAccount, TokenAccount, ProgramAccount, AssociatedTokenAccount, PoolAccounts, Callback,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::MathError;
+use crate::helpers::math_procs::numerical_ops::swap_fee;
+use crate::config::{Config, AmmState};
+use crate::observations::update_price_oracles;
+use crate::guards::{check_flash_loan_repaid, net_outflow};
+use crate::events::{emit, FlashLoanEvent};
+use crate::helpers::math_procs::curve::Curve;
+
+pub struct FlashLoanAccounts<'info> {
+    // Account borrowing the tokens.(signer)
+    pub borrower: &'info AccountView,
+    // The config account for the AMM pool.(mutable)
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.(mutable)
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.(mutable)
+    pub vault_y: &'info AccountView,
+    // Borrower associated token account for token x, receives the x borrowed.(mutable)
+    pub borrower_x_ata: &'info AccountView,
+    // Borrower associated token account for token y, receives the y borrowed.(mutable)
+    pub borrower_y_ata: &'info AccountView,
+    // SPL token program account.
+    pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // Program called back once the tokens are lent, it repays the vaults.
+    pub borrower_program: &'info AccountView,
+    // Accounts forwarded to the borrower program, `borrower_accounts_len` of them.
+    pub borrower_accounts: &'info [AccountView],
+    // Accounts forwarded to the transfer hooks of the pool's mints, after the borrower's.
+    // Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for FlashLoanAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [
+            borrower, config, mint_lp, vault_x, vault_y, borrower_x_ata, borrower_y_ata,
+            token_program, observations, mint_x, mint_y, borrower_program, rem_data @ ..
+        ] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        SignerAccount::check(borrower)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+        let config_state = Config::load(config)?;
+        AssociatedTokenAccount::check(borrower_x_ata, borrower, config_state.mint_x(), token_program)?;
+        AssociatedTokenAccount::check(borrower_y_ata, borrower, config_state.mint_y(), token_program)?;
+        PoolAccounts::check_mints(config_state, mint_x, mint_y, token_program)?;
+        // The pool itself is never the borrower, it would be trading against its own vaults.
+        if borrower_program.address().eq(&Address::new_from_array(crate::ID)) {
+            return Err(MegaAmmProgramError::IncorrectProgramId);
+        }
+
+        Ok(Self {
+            borrower, config, mint_lp, vault_x, vault_y, borrower_x_ata, borrower_y_ata,
+            token_program, observations, mint_x, mint_y, borrower_program,
+            borrower_accounts: rem_data, transfer_hook_accounts: &[],
+        })
+    }
+}
+
+pub struct FlashLoanInstructionData<'info> {
+    // Amount of token x borrowed.
+    pub amount_x: u64,
+    // Amount of token y borrowed.
+    pub amount_y: u64,
+    // Number of trailing accounts forwarded to the borrower program, the transfer hook
+    // accounts follow them.
+    pub borrower_accounts_len: u8,
+    // Instruction data of the borrower program's callback, forwarded unchanged.
+    pub callback_data: &'info [u8],
+}
+
+impl<'info> TryFrom<&'info [u8]> for FlashLoanInstructionData<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() < 8 * 2 + 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let amount_x = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let amount_y = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+        if amount_x == 0 && amount_y == 0 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { amount_x, amount_y, borrower_accounts_len: data[16], callback_data: &data[17..] })
+    }
+}
+
+pub struct FlashLoan<'info> {
+    pub accounts: FlashLoanAccounts<'info>,
+    pub instruction_data: FlashLoanInstructionData<'info>,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for FlashLoan<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let mut accounts = FlashLoanAccounts::try_from(accounts)?;
+        let instruction_data = FlashLoanInstructionData::try_from(data)?;
+        let (borrower_accounts, transfer_hook_accounts) =
+            Callback::split_accounts(accounts.borrower_accounts, instruction_data.borrower_accounts_len)?;
+        accounts.borrower_accounts = borrower_accounts;
+        accounts.transfer_hook_accounts = transfer_hook_accounts;
+
+        Ok(Self { accounts, instruction_data })
+    }
+}
+
+impl<'info> FlashLoan<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &15;
+
+    pub fn process(&mut self) -> ProgramResult {
+        // The lock is held through the callback, the borrower can't trade against the pool
+        // while its vaults are short.
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+        if amm_config.state() != AmmState::Initialized.into() {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        let (reserve_x, reserve_y, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        if lp_supply == 0 {
+            return Err(MegaAmmProgramError::EmptyPool.into());
+        }
+        let amounts = [self.instruction_data.amount_x, self.instruction_data.amount_y];
+        if amounts[0] > reserve_x || amounts[1] > reserve_y {
+            return Err(MegaAmmProgramError::InsufficientLiquidity.into());
+        }
+        // The pool's swap fee on each amount borrowed, rounded up.
+        let fee_bps = amm_config.fee() as u64;
+        let fees = [swap_fee(amounts[0], fee_bps)?, swap_fee(amounts[1], fee_bps)?];
+
+        let vaults_before = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+        let conf_bump_binding = amm_config.config_bump();
+        let config_signer_seeds = [
+            Seed::from(b"config"),
+            Seed::from(&conf_bump_binding),
+        ];
+        let signer_seeds = [Signer::from(&config_signer_seeds)];
+        let transfers = [
+            (self.accounts.vault_x, self.accounts.mint_x, self.accounts.borrower_x_ata, amounts[0]),
+            (self.accounts.vault_y, self.accounts.mint_y, self.accounts.borrower_y_ata, amounts[1]),
+        ];
+        for (from, mint, to, amount) in transfers {
+            if amount == 0 {
+                continue;
+            }
+            TokenAccount::transfer_spl_tokens(
+                from,
+                mint,
+                to,
+                self.accounts.config, // Pool signer
+                amount,
+                Some(&signer_seeds),
+                self.accounts.transfer_hook_accounts,
+            )?;
+        }
+
+        Callback::invoke(
+            self.accounts.borrower_program,
+            self.accounts.borrower_accounts,
+            self.instruction_data.callback_data,
+        )?;
+
+        let vaults_after = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+        check_flash_loan_repaid(vaults_before, vaults_after, fees)?;
+
+        // The fees join the reserves, anything repaid above them is left for Skim or Sync.
+        let post_balances = [
+            reserve_x.checked_add(fees[0]).ok_or(MathError::Overflow)?,
+            reserve_y.checked_add(fees[1]).ok_or(MathError::Overflow)?,
+        ];
+        // The loan itself nets out, only the fees count as inflows against the outflow limit.
+        let clock = Clock::get()?;
+        amm_config.record_outflow(clock.unix_timestamp, net_outflow(fees, [0; 2])?)?;
+
+        amm_config.set_reserves(&post_balances);
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations, &post_balances, lp_supply,
+        )?;
+        emit(&FlashLoanEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            borrower: self.accounts.borrower.address().to_bytes(),
+            amount_x: amounts[0],
+            amount_y: amounts[1],
+            fee_x: fees[0],
+            fee_y: fees[1],
+            reserve_x: post_balances[0],
+            reserve_y: post_balances[1],
+            d: amm_config.curve(&post_balances)?.compute_d()?,
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+//! Read only price oracle. Returns the last spot price and the EMA price of token x
+//! quoted in token y through return data, so it can be read by CPI or simulation.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+    sysvars::{clock::Clock, Sysvar},
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::ProgramAccount;
+use crate::config::{Config, AmmState};
+
+pub struct GetOraclePriceAccounts<'info> {
+    // The config account for the AMM pool holding the oracle state.
+    pub config: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for GetOraclePriceAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        ProgramAccount::check(config)?;
+
+        Ok(Self { config })
+    }
+}
+
+// Layout of the return data. All values little endian.
+// [0..16] last spot price (u128, 1e18 scaled)
+// [16..32] EMA price decayed to the current timestamp (u128, 1e18 scaled)
+// [32..40] timestamp of the last oracle update (i64)
+pub struct OraclePrice {
+    pub spot_price: u128,
+    pub ema_price: u128,
+    pub timestamp: i64,
+}
+
+impl OraclePrice {
+    pub const LEN: usize = 16 + 16 + 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        data[0..16].copy_from_slice(&self.spot_price.to_le_bytes());
+        data[16..32].copy_from_slice(&self.ema_price.to_le_bytes());
+        data[32..40].copy_from_slice(&self.timestamp.to_le_bytes());
+        data
+    }
+}
+
+pub struct GetOraclePrice<'info> {
+    pub accounts: GetOraclePriceAccounts<'info>,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for GetOraclePrice<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        if !data.is_empty() {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let accounts = GetOraclePriceAccounts::try_from(accounts)?;
+
+        Ok(Self { accounts })
+    }
+}
+
+impl<'info> GetOraclePrice<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &5;
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        if amm_config.state() == u8::from(AmmState::Uninitialized) {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+        // No trade has priced the pool yet.
+        if amm_config.ema_price() == 0 {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let price = OraclePrice {
+            spot_price: amm_config.last_spot_price(),
+            ema_price: amm_config.current_ema_price(now)?,
+            timestamp: amm_config.oracle_timestamp(),
+        };
+        set_return_data(&price.to_bytes());
+        Ok(())
+    }
+}
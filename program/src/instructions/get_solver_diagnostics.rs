@@ -0,0 +1,128 @@
+//! Read only solver diagnostics. Runs the pool's Newton solves with its configured iteration
+//! limit and tolerance and reports iterations, final residual and convergence through return data,
+//! for the CLI and tests. Unlike trades, a solve that did not converge is reported, not rejected.
+//! Cryptoswap pools run their own D and y solves. Constant product and weighted pools have
+//! closed forms, reported as exact in zero iterations.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::PoolAccounts;
+use crate::helpers::math_procs::curve_ops::{
+    MegaAmmStableSwapCurve, MegaAmmConstantProductCurve, MegaAmmWeightedCurve, MegaAmmCryptoSwapCurve,
+};
+use crate::helpers::math_procs::curve::{CurveType, STABLESWAP_AMP};
+use crate::config::{Config, AmmState};
+
+pub struct GetSolverDiagnosticsAccounts<'info> {
+    // The config account for the AMM pool.
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for GetSolverDiagnosticsAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, mint_lp, vault_x, vault_y, token_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+
+        Ok(Self { config, mint_lp, vault_x, vault_y, token_program })
+    }
+}
+
+// Empty data diagnoses the D solve only, amount and direction add the swap's y solve.
+pub struct GetSolverDiagnosticsInstructionData {
+    pub amount: u64, // Amount to swap, 0 without a swap
+    pub is_x: u8, // Swap being performed from token X to Y, bool value (1 or 0)
+}
+
+impl<'info> TryFrom<&'info [u8]> for GetSolverDiagnosticsInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.is_empty() {
+            return Ok(Self { amount: 0, is_x: 1 });
+        }
+        if data.len() != (8+1) {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let is_x = data[8];
+
+        if is_x != 0 && is_x != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { amount, is_x })
+    }
+}
+
+pub struct GetSolverDiagnostics<'info> {
+    pub accounts: GetSolverDiagnosticsAccounts<'info>,
+    pub instruction_data: GetSolverDiagnosticsInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for GetSolverDiagnostics<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = GetSolverDiagnosticsAccounts::try_from(accounts)?;
+        let instruction_data = GetSolverDiagnosticsInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> GetSolverDiagnostics<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &11;
+    // Return data uses the `SolverDiagnostics` layout.
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        if amm_config.state() == u8::from(AmmState::Uninitialized) {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        let [vault_x_amount, vault_y_amount] = amm_config.reserves();
+
+        let balances = [vault_x_amount, vault_y_amount];
+        // Index of the token given and of the token received.
+        let (i, j) = if self.instruction_data.is_x == 1 { (0, 1) } else { (1, 0) };
+        let diagnostics = match amm_config.curve_type()? {
+            CurveType::StableSwap => MegaAmmStableSwapCurve {
+                balances: &balances,
+                target_token_idx: Some(j),
+                fee_bps: amm_config.fee() as u64,
+                solver: amm_config.solver_params(),
+            }.solver_diagnostics(STABLESWAP_AMP, self.instruction_data.amount, i)?,
+            CurveType::ConstantProduct => MegaAmmConstantProductCurve {
+                balances: &balances,
+                fee_bps: amm_config.fee() as u64,
+            }.solver_diagnostics(self.instruction_data.amount, i)?,
+            CurveType::Weighted => MegaAmmWeightedCurve {
+                balances: &balances,
+                weights: amm_config.weights(),
+                fee_bps: amm_config.fee() as u64,
+            }.solver_diagnostics(self.instruction_data.amount, i)?,
+            CurveType::CryptoSwap => MegaAmmCryptoSwapCurve {
+                balances: &balances,
+                price_scale: amm_config.price_scale(),
+                fee_bps: amm_config.fee() as u64,
+                solver: amm_config.solver_params(),
+            }.solver_diagnostics(self.instruction_data.amount, i)?,
+        };
+        set_return_data(&diagnostics.to_bytes());
+        Ok(())
+    }
+}
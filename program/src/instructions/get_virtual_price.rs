@@ -0,0 +1,74 @@
+//! Read only virtual price. Returns the value of one LP token in units of the invariant D,
+//! D * 1e18 / lp_supply, through return data so other programs can price LP tokens by CPI.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::PoolAccounts;
+use crate::helpers::math_procs::curve::Curve;
+use crate::config::{Config, AmmState};
+
+pub struct GetVirtualPriceAccounts<'info> {
+    // The config account for the AMM pool.
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for GetVirtualPriceAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, mint_lp, vault_x, vault_y, token_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+
+        Ok(Self { config, mint_lp, vault_x, vault_y, token_program })
+    }
+}
+
+pub struct GetVirtualPrice<'info> {
+    pub accounts: GetVirtualPriceAccounts<'info>,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for GetVirtualPrice<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        if !data.is_empty() {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let accounts = GetVirtualPriceAccounts::try_from(accounts)?;
+
+        Ok(Self { accounts })
+    }
+}
+
+impl<'info> GetVirtualPrice<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &7;
+    // Return data is the virtual price as a little endian u128, scaled by 1e18.
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        if amm_config.state() == u8::from(AmmState::Uninitialized) {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        // No liquidity, no LP token to value.
+        if lp_supply == 0 {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        }
+
+        let balances = [vault_x_amount, vault_y_amount];
+        let virtual_price = amm_config.curve(&balances)?.virtual_price(lp_supply)?;
+        set_return_data(&virtual_price.to_le_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Grows the pool's observation buffer so TWAPs can be queried over longer windows.
+//! Permissionless, the payer funds the rent of the new slots.
+use pinocchio::{
+    AccountView, Address, ProgramResult,
+    sysvars::{rent::Rent, Sysvar},
+};
+use pinocchio_system::instructions::Transfer;
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::{SignerAccount, ProgramAccount};
+use crate::config::Config;
+use crate::observations::{
+    Observations, ObservationsHeader, observations_len, MAX_OBSERVATIONS,
+};
+
+// Maximum account growth allowed by the runtime in a single instruction.
+const MAX_PERMITTED_DATA_INCREASE: usize = 10_240;
+
+pub struct GrowObservationsAccounts<'info> {
+    // Pays for the additional rent, must sign.(mutable)
+    pub payer: &'info AccountView,
+    // The config account of the AMM pool.
+    pub config: &'info AccountView,
+    // Observation buffer of the pool.(mutable)
+    pub observations: &'info AccountView,
+    // System program, used to transfer the rent.
+    pub system_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for GrowObservationsAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [payer, config, observations, system_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        SignerAccount::check(payer)?;
+        ProgramAccount::check(config)?;
+        if !observations.owned_by(&Address::new_from_array(crate::ID)) {
+            return Err(MegaAmmProgramError::InvalidOwner.into());
+        }
+        let expected_observations = Address::find_program_address(
+            &[b"observations", config.address().as_ref()],
+            &Address::new_from_array(crate::ID),
+        ).0;
+        if expected_observations.ne(observations.address()) {
+            return Err(MegaAmmProgramError::InvalidAddress.into());
+        }
+
+        Ok(Self { payer, config, observations, system_program })
+    }
+}
+
+pub struct GrowObservationsInstructionData {
+    // Number of observation slots the buffer should hold.
+    pub cardinality_next: u16,
+}
+
+impl<'info> TryFrom<&'info [u8]> for GrowObservationsInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() != 2 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let cardinality_next = u16::from_le_bytes(data[0..2].try_into().unwrap());
+        if cardinality_next > MAX_OBSERVATIONS {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { cardinality_next })
+    }
+}
+
+pub struct GrowObservations<'info> {
+    pub accounts: GrowObservationsAccounts<'info>,
+    pub instruction_data: GrowObservationsInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for GrowObservations<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = GrowObservationsAccounts::try_from(accounts)?;
+        let instruction_data = GrowObservationsInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> GrowObservations<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &6;
+    pub fn process(&mut self) -> ProgramResult {
+        Config::load(self.accounts.config)?.check_unlocked()?;
+        let current = {
+            let data = self.accounts.observations.try_borrow()?;
+            Observations::from_bytes(&data)?.header.cardinality_next()
+        };
+        let cardinality_next = self.instruction_data.cardinality_next;
+        if cardinality_next <= current {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let old_len = observations_len(current);
+        let new_len = observations_len(cardinality_next);
+        if new_len - old_len > MAX_PERMITTED_DATA_INCREASE {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        // Topping up the rent before growing the account.
+        let required = Rent::get()?.minimum_balance(new_len);
+        let lamports = self.accounts.observations.lamports();
+        if required > lamports {
+            Transfer {
+                from: self.accounts.payer,
+                to: self.accounts.observations,
+                lamports: required - lamports,
+            }.invoke()?;
+        }
+        // New slots are zeroed, so they read as uninitialized until the ring reaches them.
+        self.accounts.observations.resize(new_len)?;
+
+        let mut data = self.accounts.observations.try_borrow_mut()?;
+        let header = unsafe { &mut *(data.as_mut_ptr() as *mut ObservationsHeader) };
+        header.set_cardinality_next(cardinality_next);
+        Ok(())
+    }
+}
@@ -14,6 +14,10 @@ use crate::helpers::utils::{
     AssociatedTokenAccount,
 };
 use crate::config::Config;
+use crate::helpers::math_procs::curve::CurveType;
+use crate::helpers::token_extensions::{check_supported_extensions, transfer_hook_program};
+use crate::observations::{ObservationsHeader, observations_len};
+use crate::events::{emit, PoolInitialized};
 use pinocchio_log::log;
 use pinocchio_associated_token_account;
 use pinocchio_associated_token_account::{
@@ -40,6 +44,8 @@ pub struct InitializeAccounts<'a> {
     pub ata_token_program: &'a AccountView,
     pub system_program: &'a AccountView,
     pub token_program: &'a AccountView,
+    // Ring buffer of price observations for the pool. PDA to be created, must be mutable.
+    pub observations: &'a AccountView,
 }
 
 impl<'a> TryFrom<&'a [AccountView]> for InitializeAccounts<'a> {
@@ -52,7 +58,7 @@ impl<'a> TryFrom<&'a [AccountView]> for InitializeAccounts<'a> {
             mint_x, mint_y,
             mint_lp, config, ata_token_program,
             system_program,
-            token_program,
+            token_program, observations,
         ] = accounts else {
             return Err(MegaAmmProgramError::InvalidAccountData.into());
         };
@@ -67,7 +73,8 @@ impl<'a> TryFrom<&'a [AccountView]> for InitializeAccounts<'a> {
             vault_x_ata: vault_x_ata, vault_y_ata: vault_y_ata,
             mint_x: mint_x, mint_y: mint_y, mint_lp: mint_lp,
             config: config, ata_token_program: ata_token_program,
-            system_program: system_program, token_program: token_program
+            system_program: system_program, token_program: token_program,
+            observations: observations,
         })
     }
 }
@@ -82,27 +89,32 @@ pub struct InitializeInstructionData {
     pub lp_mint_decimals: u8, // Mint decimals.
     pub lp_bump: [u8; 1], // Bump seed used for deriving the lp_mint account PDA. Must be a u8.
     pub authority: [u8; 32], // Public key with admin auth over the AMM. Immutable pool if absent.
+    pub curve_type: u8, // Pool invariant, a `CurveType`. Stableswap if absent.
+    pub weight_x: u64, // Weight of token x for weighted pools, 1e18 scaled. Zero for other curves.
 }
 
 impl<'a> TryFrom<&'a [u8]> for InitializeInstructionData {
     type Error = MegaAmmProgramError;
     fn try_from(data: &'a [u8]) -> Result<Self, Self::Error> {
-        const INITIALIZE_DATA_LEN_WITH_AUTHORITY: usize = size_of::<InitializeInstructionData>();
+        const INITIALIZE_DATA_LEN_WITH_WEIGHT: usize = size_of::<InitializeInstructionData>();
+        const INITIALIZE_DATA_LEN_WITH_CURVE: usize = INITIALIZE_DATA_LEN_WITH_WEIGHT - size_of::<u64>();
+        const INITIALIZE_DATA_LEN_WITH_AUTHORITY: usize = INITIALIZE_DATA_LEN_WITH_CURVE - size_of::<u8>();
         const INITIALIZE_DATA_LEN: usize = INITIALIZE_DATA_LEN_WITH_AUTHORITY - size_of::<[u8; 32]>();
         match data.len() {
-            INITIALIZE_DATA_LEN_WITH_AUTHORITY => {
+            INITIALIZE_DATA_LEN_WITH_WEIGHT => {
                 Ok(unsafe { (data.as_ptr() as *const Self).read_unaligned() })
             }
-            INITIALIZE_DATA_LEN => {
-                // Authority is not present. We need to build buffer and add it at the end before
-                // transmitting buffer to the struct.
-                let mut raw: MaybeUninit<[u8; INITIALIZE_DATA_LEN_WITH_AUTHORITY]> = MaybeUninit::uninit();
+            INITIALIZE_DATA_LEN | INITIALIZE_DATA_LEN_WITH_AUTHORITY | INITIALIZE_DATA_LEN_WITH_CURVE => {
+                // Trailing fields are not present. We need to build buffer and zero them at the end
+                // before transmitting buffer to the struct. A zero authority leaves the pool
+                // immutable, a zero curve type is the stableswap, unweighted.
+                let mut raw: MaybeUninit<[u8; INITIALIZE_DATA_LEN_WITH_WEIGHT]> = MaybeUninit::uninit();
                 let raw_ptr = raw.as_mut_ptr() as *mut u8;
                 unsafe {
                     // Copy the provided data.
-                    core::ptr::copy_nonoverlapping(data.as_ptr(), raw_ptr, INITIALIZE_DATA_LEN);
-                    // Add the authority to the end of the buffer.
-                    core::ptr::write_bytes(raw_ptr.add(INITIALIZE_DATA_LEN), 0, 32);
+                    core::ptr::copy_nonoverlapping(data.as_ptr(), raw_ptr, data.len());
+                    // Zero the missing fields at the end of the buffer.
+                    core::ptr::write_bytes(raw_ptr.add(data.len()), 0, INITIALIZE_DATA_LEN_WITH_WEIGHT - data.len());
                     // Now transmute to the struct.
                     Ok((raw.as_ptr() as *const Self).read_unaligned())
                 }
@@ -121,6 +133,20 @@ impl<'a> TryFrom<(&'a [u8], &'a [AccountView])> for Initialize<'a> {
     fn try_from((data, accounts): (&'a [u8], &'a [AccountView])) -> Result<Self, Self::Error> {
         let accounts = InitializeAccounts::try_from(accounts)?;
         let instruction_data = InitializeInstructionData::try_from(data)?;
+        // Rejecting unknown curves and invalid weights before any account is created.
+        CurveType::try_from(instruction_data.curve_type)?.check_weight_x(instruction_data.weight_x)?;
+        // The vaults are created for the mint accounts, the config stores the mints from the data.
+        if accounts.mint_x.address().as_ref().ne(&instruction_data.mint_x)
+            || accounts.mint_y.address().as_ref().ne(&instruction_data.mint_y) {
+            return Err(MegaAmmProgramError::InvalidAddress);
+        }
+        // One token program derives both vaults and owns the LP mint, it must own both mints.
+        MintInterface::check(accounts.mint_x)?;
+        MintInterface::check(accounts.mint_y)?;
+        MintInterface::check_token_program(accounts.mint_x, accounts.token_program)?;
+        MintInterface::check_token_program(accounts.mint_y, accounts.token_program)?;
+        check_supported_extensions(accounts.mint_x)?;
+        check_supported_extensions(accounts.mint_y)?;
         Ok(Self {accounts, instruction_data})
     }
 }
@@ -155,12 +181,20 @@ impl<'a> Initialize<'a> {
         let config = Config::load_mut(self.accounts.config)?;
         config.set_inner(
             self.instruction_data.seed,
-            self.accounts.config.address().to_bytes(),
+            self.instruction_data.authority,
             self.instruction_data.mint_x,
             self.instruction_data.mint_y,
             self.instruction_data.fee,
             self.instruction_data.config_bump,
-        );
+            CurveType::try_from(self.instruction_data.curve_type)?,
+            self.instruction_data.weight_x,
+        )?;
+        // Transfer hooks the mints run today are allowlisted, the pool rejects any other
+        // program a hook authority points them to later.
+        config.set_transfer_hook_programs(&[
+            transfer_hook_program(self.accounts.mint_x)?,
+            transfer_hook_program(self.accounts.mint_y)?,
+        ])?;
 
         // Creating ata for the pool vaults.
         AssociatedTokenAccount::init(
@@ -199,7 +233,41 @@ impl<'a> Initialize<'a> {
             self.accounts.config.address(),
             &mint_signer,
             None, // LP tokens should not be freezable.
+            self.accounts.token_program.address(),
+        )?;
+
+        // Creating the observations ring buffer with a single slot. Anyone can grow it later.
+        let (expected_observations, observations_bump) = Address::find_program_address(
+            &[b"observations", self.accounts.config.address().as_ref()],
+            &crate::ID.into()
+        );
+        if expected_observations.ne(self.accounts.observations.address()) {
+            return Err(MegaAmmProgramError::InvalidAddress.into());
+        }
+        let observations_bump_binding = [observations_bump];
+        let observations_signer_seeds = [
+            Seed::from(b"observations"),
+            Seed::from(&mint_config_binding),
+            Seed::from(&observations_bump_binding),
+        ];
+        let observations_signer = [Signer::from(&observations_signer_seeds)];
+        ProgramAccount::init::<ObservationsHeader>(
+            self.accounts.initializer,
+            self.accounts.observations,
+            &observations_signer,
+            observations_len(1),
         )?;
+        let mut observations_data = self.accounts.observations.try_borrow_mut()?;
+        let header = unsafe { &mut *(observations_data.as_mut_ptr() as *mut ObservationsHeader) };
+        header.set_inner(self.accounts.config.address(), observations_bump_binding);
+        emit(&PoolInitialized {
+            pool: self.accounts.config.address().to_bytes(),
+            authority: self.instruction_data.authority,
+            mint_x: self.instruction_data.mint_x,
+            mint_y: self.instruction_data.mint_y,
+            mint_lp: self.accounts.mint_lp.address().to_bytes(),
+            fee_bps: self.instruction_data.fee,
+        }.to_bytes());
         Ok(())
     }
 }
@@ -1,10 +1,34 @@
 //! Exposes the instruction of the AMM.
 pub mod deposit;
+pub mod donate;
+pub mod flash_loan;
+pub mod get_oracle_price;
+pub mod get_solver_diagnostics;
+pub mod get_virtual_price;
+pub mod grow_observations;
 pub mod initialize;
+pub mod quote_deposit;
+pub mod quote_swap;
+pub mod quote_withdraw;
+pub mod skim;
 pub mod swap;
+pub mod sync;
+pub mod update_config;
 pub mod withdraw;
 
 use deposit::*;
+use donate::*;
+use flash_loan::*;
+use get_oracle_price::*;
+use get_solver_diagnostics::*;
+use get_virtual_price::*;
+use grow_observations::*;
 use initialize::*;
+use quote_deposit::*;
+use quote_swap::*;
+use quote_withdraw::*;
+use skim::*;
 use swap::*;
+use sync::*;
+use update_config::*;
 use withdraw::*;
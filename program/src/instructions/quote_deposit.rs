@@ -0,0 +1,97 @@
+//! Read only deposit quote. Returns the LP tokens a deposit would mint, as computed
+//! by `Deposit::quote`, without moving funds.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::PoolAccounts;
+use crate::config::{Config, AmmState};
+use crate::instructions::deposit::Deposit;
+
+pub struct QuoteDepositAccounts<'info> {
+    // The config account for the AMM pool.
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for QuoteDepositAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, mint_lp, vault_x, vault_y, token_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+
+        Ok(Self { config, mint_lp, vault_x, vault_y, token_program })
+    }
+}
+
+pub struct QuoteDepositInstructionData {
+    // Amount of token x to be deposited into the pool.
+    pub amount_x: u64,
+    // Amount of token y to be deposited into the pool.
+    pub amount_y: u64,
+}
+
+impl<'info> TryFrom<&'info [u8]> for QuoteDepositInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() != (8+8) {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let amount_x = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let amount_y = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+        if amount_x == 0 || amount_y == 0 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { amount_x, amount_y })
+    }
+}
+
+pub struct QuoteDeposit<'info> {
+    pub accounts: QuoteDepositAccounts<'info>,
+    pub instruction_data: QuoteDepositInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for QuoteDeposit<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = QuoteDepositAccounts::try_from(accounts)?;
+        let instruction_data = QuoteDepositInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> QuoteDeposit<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &9;
+    // Return data uses the `DepositQuote` layout.
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        if amm_config.state() != AmmState::Initialized.into() {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        let quote = Deposit::quote(
+            amm_config, vault_x_amount, vault_y_amount, lp_supply,
+            self.instruction_data.amount_x, self.instruction_data.amount_y,
+        )?;
+        set_return_data(&quote.to_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+//! Read only swap quote for aggregators. Output amount and fee are computed by
+//! `Swap::quote`, the same code the swap executes, and returned through return data.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::PoolAccounts;
+use crate::config::{Config, AmmState};
+use crate::instructions::swap::Swap;
+
+pub struct QuoteSwapAccounts<'info> {
+    // The config account for the AMM pool.
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for QuoteSwapAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, mint_lp, vault_x, vault_y, token_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+
+        Ok(Self { config, mint_lp, vault_x, vault_y, token_program })
+    }
+}
+
+pub struct QuoteSwapInstructionData {
+    pub amount: u64, // Amount to swap
+    pub is_x: u8, // Swap being performed from token X to Y, bool value (1 or 0)
+}
+
+impl<'info> TryFrom<&'info [u8]> for QuoteSwapInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() != (8+1) {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let amount = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let is_x = data[8];
+
+        if amount == 0 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        if is_x != 0 && is_x != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self { amount, is_x })
+    }
+}
+
+pub struct QuoteSwap<'info> {
+    pub accounts: QuoteSwapAccounts<'info>,
+    pub instruction_data: QuoteSwapInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for QuoteSwap<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = QuoteSwapAccounts::try_from(accounts)?;
+        let instruction_data = QuoteSwapInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> QuoteSwap<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &8;
+    // Return data uses the `SwapQuote` layout.
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        if amm_config.state() != AmmState::Initialized.into() {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        let quote = Swap::quote(
+            amm_config, vault_x_amount, vault_y_amount,
+            self.instruction_data.amount, self.instruction_data.is_x,
+        )?;
+        set_return_data(&quote.to_bytes());
+        Ok(())
+    }
+}
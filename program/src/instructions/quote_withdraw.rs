@@ -0,0 +1,98 @@
+//! Read only withdrawal quote for both balanced and single sided exits.
+//! Tokens out and LP burned come from `Withdraw::quote`.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::set_return_data,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::PoolAccounts;
+use crate::config::{Config, AmmState};
+use crate::instructions::withdraw::{Withdraw, WithdrawInstructionData};
+
+pub struct QuoteWithdrawAccounts<'info> {
+    // The config account for the AMM pool.
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for QuoteWithdrawAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [config, mint_lp, vault_x, vault_y, token_program, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+
+        Ok(Self { config, mint_lp, vault_x, vault_y, token_program })
+    }
+}
+
+// Same fields as the Withdraw instruction data, without the expiration.
+pub struct QuoteWithdrawInstructionData {
+    pub withdrawal: WithdrawInstructionData,
+}
+
+impl<'info> TryFrom<&'info [u8]> for QuoteWithdrawInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() != (8+8+8+1) {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let lp_to_burn = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let amount_of_x = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let amount_of_y = u64::from_le_bytes(data[16..24].try_into().unwrap());
+        let withdraw_mode = data[24];
+
+        if withdraw_mode != 0 && withdraw_mode != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        Ok(Self {
+            withdrawal: WithdrawInstructionData {
+                lp_to_burn, amount_of_x, amount_of_y, expiration: 0, withdraw_mode, native_sol: 0,
+            }
+        })
+    }
+}
+
+pub struct QuoteWithdraw<'info> {
+    pub accounts: QuoteWithdrawAccounts<'info>,
+    pub instruction_data: QuoteWithdrawInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for QuoteWithdraw<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = QuoteWithdrawAccounts::try_from(accounts)?;
+        let instruction_data = QuoteWithdrawInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> QuoteWithdraw<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &10;
+    // Return data uses the `WithdrawQuote` layout.
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load(self.accounts.config)?;
+        amm_config.check_unlocked()?;
+        amm_config.check_withdraw_allowed(self.instruction_data.withdrawal.withdraw_mode)?;
+
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        let quote = Withdraw::quote(
+            amm_config, vault_x_amount, vault_y_amount, lp_supply, &self.instruction_data.withdrawal,
+        )?;
+        set_return_data(&quote.to_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! Sends whatever the vaults hold above the pool's tracked reserves to a recipient.
+//! Permissionless, tokens sent straight to a vault are not priced by the pool and belong
+//! to whoever skims them.
+use pinocchio::{
+    AccountView, ProgramResult,
+    cpi::{Signer, Seed},
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::{
+    TokenInterface, TokenAccount, ProgramAccount, AssociatedTokenAccount, PoolAccounts,
+};
+use crate::config::Config;
+use crate::events::{emit, SkimEvent};
+
+pub struct SkimAccounts<'info> {
+    // The config account for the AMM pool, signs the transfers out of the vaults.(mutable)
+    pub config: &'info AccountView,
+    // Holds all token x deposited into the pool.(mutable)
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.(mutable)
+    pub vault_y: &'info AccountView,
+    // Receives the excess of token x.(mutable)
+    pub recipient_x: &'info AccountView,
+    // Receives the excess of token y.(mutable)
+    pub recipient_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // Accounts forwarded to the transfer hooks of the pool's mints: the hook programs, their
+    // `ExtraAccountMetaList` accounts and the extra accounts they list. Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for SkimAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [
+            config, vault_x, vault_y, recipient_x, recipient_y, token_program,
+            mint_x, mint_y, transfer_hook_accounts @ ..
+        ] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        ProgramAccount::check(config)?;
+        let config_state = Config::load(config)?;
+        AssociatedTokenAccount::check(vault_x, config, config_state.mint_x(), token_program)?;
+        AssociatedTokenAccount::check(vault_y, config, config_state.mint_y(), token_program)?;
+        TokenInterface::check(recipient_x)?;
+        TokenInterface::check(recipient_y)?;
+        PoolAccounts::check_mints(config_state, mint_x, mint_y, token_program)?;
+
+        Ok(Self {
+            config, vault_x, vault_y, recipient_x, recipient_y, token_program, mint_x, mint_y,
+            transfer_hook_accounts,
+        })
+    }
+}
+
+pub struct Skim<'info> {
+    pub accounts: SkimAccounts<'info>,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Skim<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        if !data.is_empty() {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let accounts = SkimAccounts::try_from(accounts)?;
+
+        Ok(Self { accounts })
+    }
+}
+
+impl<'info> Skim<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &12;
+
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+
+        let balances = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+        let reserves = amm_config.reserves();
+        let excess = [
+            balances[0].saturating_sub(reserves[0]),
+            balances[1].saturating_sub(reserves[1]),
+        ];
+
+        let conf_bump_binding = amm_config.config_bump();
+        let config_signer_seeds = [
+            Seed::from(b"config"),
+            Seed::from(&conf_bump_binding),
+        ];
+        let signer_seeds = [Signer::from(&config_signer_seeds)];
+        let transfers = [
+            (self.accounts.vault_x, self.accounts.mint_x, self.accounts.recipient_x, excess[0]),
+            (self.accounts.vault_y, self.accounts.mint_y, self.accounts.recipient_y, excess[1]),
+        ];
+        for (vault, mint, recipient, amount) in transfers {
+            if amount == 0 {
+                continue;
+            }
+            TokenAccount::transfer_spl_tokens(
+                vault,
+                mint,
+                recipient,
+                self.accounts.config,
+                amount,
+                Some(&signer_seeds),
+                self.accounts.transfer_hook_accounts,
+            )?;
+        }
+
+        emit(&SkimEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            amount_x: excess[0],
+            amount_y: excess[1],
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
+    }
+}
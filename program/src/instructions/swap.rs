@@ -1,16 +1,21 @@
 //! Swapping the tokens.
 use pinocchio::{
     AccountView, Address, error::ProgramError, ProgramResult,
-    cpi::{Signer, Seed}
+    cpi::{Signer, Seed, set_return_data}
 };
 use crate::helpers::utils::{
-    SignerAccount, MintInterface, TokenInterface,
-    MintAccount, TokenAccount, ProgramAccount, AssociatedTokenAccount,
+    SignerAccount, MintInterface,
+    MintAccount, TokenAccount, AssociatedTokenAccount,
+    PoolAccounts, NativeSol, Callback,
 };
 use crate::helpers::errors::MegaAmmProgramError;
-use pinocchio::sysvars::clock::Clock;
+use pinocchio::sysvars::{clock::Clock, Sysvar};
 use crate::config::{Config, AmmState};
-use crate::helpers::math_procs::curve_ops::MegaAmmStableSwapCurve;
+use crate::observations::update_price_oracles;
+use crate::guards::{net_outflow, settled_balances};
+use crate::helpers::token_extensions::transfer_fee;
+use crate::events::{emit, SwapEvent};
+use crate::helpers::math_procs::curve::Curve;
 use pinocchio_log::log;
 use solana_address;
 
@@ -30,6 +35,23 @@ pub struct SwapAccounts<'info> {
     pub mint_lp: &'info AccountView,
     // SPL token program account.
     pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // System program wrapping native SOL, first of the trailing accounts when SOL is paid in.
+    pub system_program: Option<&'info AccountView>,
+    // Program called back by a flash swap once the output is sent, it pays for the swap.
+    // Next trailing account, flash swaps only.
+    pub callback_program: Option<&'info AccountView>,
+    // Accounts forwarded to the flash swap callback, `callback_accounts_len` of them after
+    // the callback program. Empty for regular swaps.
+    pub callback_accounts: &'info [AccountView],
+    // Accounts forwarded to the transfer hooks of the pool's mints: the hook programs, their
+    // `ExtraAccountMetaList` accounts and the extra accounts they list. Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
 }
 
 impl<'info> TryFrom<&'info [AccountView]> for SwapAccounts<'info> {
@@ -37,39 +59,53 @@ impl<'info> TryFrom<&'info [AccountView]> for SwapAccounts<'info> {
     fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
         let [
             user,  vault_x, vault_y, user_x_ata, user_y_ata,
-            config, mint_lp, token_program, _rem_data @ ..
+            config, mint_lp, token_program, observations, mint_x, mint_y, rem_data @ ..
         ] = accounts else {
             return Err(MegaAmmProgramError::InvalidAccountData.into());
         };
         // Checking the accounts.
         SignerAccount::check(user)?;
-        MintInterface::check(mint_lp)?;
-        TokenInterface::check(vault_x)?;
-        TokenInterface::check(vault_y)?;
-        ProgramAccount::check(config)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
         let config_state = Config::load(config)?;
-        AssociatedTokenAccount::check(user_x_ata, user, config_state.mint_x(), token_program)?;
-        AssociatedTokenAccount::check(user_y_ata, user, config_state.mint_y(), token_program)?;
+        // A native leg may go through the user's temporary WSOL account instead of their ATA.
+        for (user_ata, mint) in [(user_x_ata, config_state.mint_x()), (user_y_ata, config_state.mint_y())] {
+            if !NativeSol::is_temporary(user_ata, user, mint) {
+                AssociatedTokenAccount::check(user_ata, user, mint, token_program)?;
+            }
+        }
+        PoolAccounts::check_mints(config_state, mint_x, mint_y, token_program)?;
+        let (system_program, transfer_hook_accounts) = NativeSol::split_system_program(rem_data);
 
         Ok(Self {
             user, vault_x, vault_y, user_x_ata, user_y_ata,
-            config, mint_lp, token_program,
+            config, mint_lp, token_program, observations, mint_x, mint_y, system_program,
+            callback_program: None, callback_accounts: &[], transfer_hook_accounts,
         })
     }
 }
 
-pub struct SwapInstructionData {
+pub struct SwapInstructionData<'info> {
     pub amount: u64, // Amount to swap
     pub min_out: u64, // Minimum slippage. Min to receive below which it's bad pricing
     // Time duration the transaction should take or should not wait beyond
     pub expiration: i64,
     pub is_x: u8, // Swap being performed from token X to Y, bool value (1 or 0)
+    // Legs in the native mint are settled in SOL, bool value (1 or 0). Optional, 0 if absent.
+    pub native_sol: u8,
+    // Flash swap, the output is sent first and a callback pays for it, bool value (1 or 0).
+    // Optional, 0 if absent. Follows `native_sol`.
+    pub flash: u8,
+    // Number of trailing accounts forwarded to the callback, the transfer hook accounts
+    // follow them. Flash swaps only.
+    pub callback_accounts_len: u8,
+    // Instruction data of the flash swap callback, forwarded unchanged. The rest of the data.
+    pub callback_data: &'info [u8],
 }
 
-impl<'info> TryFrom<&'info [u8]> for SwapInstructionData {
+impl<'info> TryFrom<&'info [u8]> for SwapInstructionData<'info> {
     type Error = MegaAmmProgramError;
     fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
-        if data.len() != (8*3+1) {
+        if data.len() < (8*3+1) {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
 
@@ -77,6 +113,10 @@ impl<'info> TryFrom<&'info [u8]> for SwapInstructionData {
         let min_out = u64::from_le_bytes(data[8..16].try_into().unwrap());
         let expiration = i64::from_le_bytes(data[16..24].try_into().unwrap());
         let is_x = data[24];
+        let native_sol = data.get(25).copied().unwrap_or(0);
+        let flash = data.get(26).copied().unwrap_or(0);
+        let callback_accounts_len = data.get(27).copied().unwrap_or(0);
+        let callback_data = data.get(28..).unwrap_or(&[]);
 
         if amount <= 0 || min_out <= 0 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
@@ -84,23 +124,62 @@ impl<'info> TryFrom<&'info [u8]> for SwapInstructionData {
         if is_x != 0 && is_x != 1 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
+        if native_sol != 0 && native_sol != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        // Only flash swaps carry callback accounts and data, and they always say how many
+        // accounts go to the callback.
+        let valid_len = match flash {
+            0 => data.len() <= 8*3+3,
+            1 => data.len() >= 8*3+4,
+            _ => false,
+        };
+        if !valid_len {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
 
         Ok(Self {
-            amount, min_out, expiration, is_x,
+            amount, min_out, expiration, is_x, native_sol, flash, callback_accounts_len, callback_data,
         })
     }
 }
 
 pub struct Swap<'info> {
     pub accounts: SwapAccounts<'info>,
-    pub instruction_data: SwapInstructionData,
+    pub instruction_data: SwapInstructionData<'info>,
 }
 
 impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Swap<'info> {
     type Error = MegaAmmProgramError;
     fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
-        let accounts = SwapAccounts::try_from(accounts)?;
+        let mut accounts = SwapAccounts::try_from(accounts)?;
         let instruction_data = SwapInstructionData::try_from(data)?;
+        if instruction_data.flash == 1 {
+            // The callback pays, there is nothing to wrap for it.
+            if instruction_data.native_sol == 1 {
+                return Err(MegaAmmProgramError::InvalidInstructionData);
+            }
+            let [callback_program, rem_data @ ..] = accounts.transfer_hook_accounts else {
+                return Err(MegaAmmProgramError::NotEnoughAccountKeys);
+            };
+            // The pool itself is never the callback, it would be trading against its own vaults.
+            if callback_program.address().eq(&Address::new_from_array(crate::ID)) {
+                return Err(MegaAmmProgramError::IncorrectProgramId);
+            }
+            let (callback_accounts, transfer_hook_accounts) =
+                Callback::split_accounts(rem_data, instruction_data.callback_accounts_len)?;
+            accounts.callback_program = Some(callback_program);
+            accounts.callback_accounts = callback_accounts;
+            accounts.transfer_hook_accounts = transfer_hook_accounts;
+        }
+        // Native SOL needs the native mint on one side, and the system program to create its WSOL account.
+        NativeSol::check_accounts(
+            instruction_data.native_sol == 1, accounts.user,
+            [(accounts.user_x_ata, accounts.mint_x), (accounts.user_y_ata, accounts.mint_y)],
+        )?;
+        if instruction_data.native_sol == 1 && accounts.system_program.is_none() {
+            return Err(MegaAmmProgramError::NotEnoughAccountKeys);
+        }
 
         Ok(Self {
             accounts, instruction_data
@@ -108,31 +187,70 @@ impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Swap<'info> {
     }
 }
 
+// Result of a swap against the pool's reserves.
+// Return data layout, all values little endian u64.
+// [0..8] amount in, [8..16] amount out, [16..24] fee kept by the pool in the output token.
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    // Pool reserves (x, y) once the swap settles.
+    pub post_balances: [u64; 2],
+}
+
+impl SwapQuote {
+    pub const LEN: usize = 8 * 3;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        data[0..8].copy_from_slice(&self.amount_in.to_le_bytes());
+        data[8..16].copy_from_slice(&self.amount_out.to_le_bytes());
+        data[16..24].copy_from_slice(&self.fee.to_le_bytes());
+        data
+    }
+}
+
 impl<'info> Swap<'info> {
     pub const DISCRIMINATOR: &'info u8 = &3;
+
+    // Swap calculations through the pool's curve. Shared by Swap and QuoteSwap
+    // so quotes follow the exact execution path.
+    pub fn quote(
+        amm_config: &Config, vault_x_amount: u64, vault_y_amount: u64, amount: u64, is_x: u8,
+    ) -> Result<SwapQuote, ProgramError> {
+        let balances = [vault_x_amount, vault_y_amount];
+        // Index of the token given and of the token received.
+        let (i, j) = if is_x == 1 { (0, 1) } else { (1, 0) };
+        let (amount_out, fee) = amm_config.curve(&balances)?.swap_out(amount, i, j)?;
+
+        let mut post_balances = balances;
+        post_balances[i] = post_balances[i].checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        post_balances[j] = post_balances[j].checked_sub(amount_out).ok_or(ProgramError::ArithmeticOverflow)?;
+
+        // Pool circuit breakers. The price impact needs two more curve evaluations, it is
+        // only measured when the pool caps it.
+        let guards = amm_config.trade_guards();
+        guards.check_trade_size(balances[j], amount_out)?;
+        guards.check_reserve_floor(post_balances[j])?;
+        if guards.max_price_impact_bps != 0 {
+            let spot_before = amm_config.curve(&balances)?.spot_price()?;
+            let spot_after = amm_config.curve(&post_balances)?.spot_price()?;
+            guards.check_price_impact(spot_before, spot_after)?;
+        }
+        Ok(SwapQuote { amount_in: amount, amount_out, fee, post_balances })
+    }
+
     pub fn process(&mut self) -> ProgramResult {
-        let amm_config = Config::load(self.accounts.config)?;
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
         if amm_config.state() != AmmState::Initialized.into() {
             return Err(MegaAmmProgramError::Unauthorized.into());
         }
 
         // Deserializing token accounts.
-        let (vault_x_amount, vault_y_amount, lp_supply) = {
-            let mint_data_ref = self.accounts.mint_lp.try_borrow()?;
-            let mint_lp = unsafe {
-                pinocchio_token::state::Mint::from_bytes_unchecked(&mint_data_ref)
-            };
-
-            let v_x_ref = self.accounts.vault_x.try_borrow()?;
-            let v_x = unsafe { pinocchio_token::state::TokenAccount::from_bytes_unchecked(&v_x_ref) };
-
-            let v_y_ref = self.accounts.vault_y.try_borrow()?;
-            let v_y = unsafe { pinocchio_token::state::TokenAccount::from_bytes_unchecked(&v_y_ref) };
-            (v_x.amount(), v_y.amount(), mint_lp.supply())
-        };
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
 
         // Seed derivations for txn signing
-        let seed_binding = amm_config.seed().to_le_bytes();
         let conf_bump_binding = amm_config.config_bump();
         let config_signer_seeds = [
             Seed::from(b"config"),
@@ -140,85 +258,126 @@ impl<'info> Swap<'info> {
         ];
         let signer_seeds = [Signer::from(&config_signer_seeds)];
 
-        // Swap calculations with newton solver stableswap
-        // Balances should be in order, with the last representing token which is being swapped for
-        let balances = [vault_y_amount, vault_x_amount];
-        if self.instruction_data.is_x == 1 {
-            // We're getting y from the pool after adding x.
-            let mut curve = MegaAmmStableSwapCurve {
-                balances: &balances,
-                target_token_idx: Some(1),
-                fee_bps: amm_config.fee().into()
-            };
-            // Getting the final amount of token x to send to the user for the swap.
-            let final_amount = curve.stableswap(
-                self.instruction_data.amount, 0, 100
-            ).map_err(|_| ProgramError::Custom(2))?;
+        // Token given by the user goes to its vault, the other one is paid out.
+        let (user_in, mint_in, vault_in, vault_out, mint_out, user_out) = if self.instruction_data.is_x == 1 {
+            (
+                self.accounts.user_x_ata, self.accounts.mint_x, self.accounts.vault_x,
+                self.accounts.vault_y, self.accounts.mint_y, self.accounts.user_y_ata,
+            )
+        } else {
+            (
+                self.accounts.user_y_ata, self.accounts.mint_y, self.accounts.vault_y,
+                self.accounts.vault_x, self.accounts.mint_x, self.accounts.user_x_ata,
+            )
+        };
 
-            // Pool balance quard.
-            // if final_amount > 
+        // Token-2022 transfer fees are withheld from what the vault receives, the pool prices
+        // on the net amount.
+        let clock = Clock::get()?;
+        let amount_in = transfer_fee(mint_in, clock.epoch)?.net(self.instruction_data.amount);
+        let quote = Self::quote(
+            amm_config, vault_x_amount, vault_y_amount,
+            amount_in, self.instruction_data.is_x,
+        )?;
 
-            // Slippage protection.
-            if final_amount < self.instruction_data.min_out {
-                return Err(MegaAmmProgramError::SlippageExceeded.into());
-            }
+        // Slippage protection, on what the user receives once the fee on the way out is withheld.
+        let received = transfer_fee(mint_out, clock.epoch)?.net(quote.amount_out);
+        if received < self.instruction_data.min_out {
+            return Err(MegaAmmProgramError::SlippageExceeded.into());
+        }
 
-            // Swapping x for y, X from user to the pool.
-            TokenAccount::transfer_spl_tokens(
-                self.accounts.user_x_ata,
-                self.accounts.vault_x,
-                self.accounts.user,
-                self.instruction_data.amount,
-                None,
-            )?;
-            
-            // Transfer token y from pool to user for x.
-            TokenAccount::transfer_spl_tokens(
-                self.accounts.vault_y,
-                self.accounts.user_y_ata,
-                self.accounts.config,
-                final_amount,
-                Some(&signer_seeds),
-            )?;
-            return Ok(());
-        } else {
-            // 0. Withdrawing y, hence it is the last element in the list
-            // We've added y we're getting x from the pool
-            // We're getting y from the pool.
-            let balances = [vault_x_amount, vault_y_amount];
-            let mut curve = MegaAmmStableSwapCurve {
-                balances: &balances,
-                target_token_idx: Some(0),
-                fee_bps: amm_config.fee().into()
-            };
-            // Getting final amount of token y to send to the user.
-            let final_amount = curve.stableswap(
-                self.instruction_data.amount, 1, 100
-            ).map_err(|_| ProgramError::Custom(2))?;
-
-            // Slippage protection.
-            if final_amount < self.instruction_data.min_out {
-                return Err(MegaAmmProgramError::SlippageExceeded.into());
-            }
+        // Rolling outflow limit, the token paid out counts net of the one received. A flash
+        // swap is only known to pay in once its callback returns, it is recorded then.
+        let reserves = [vault_x_amount, vault_y_amount];
+        if self.accounts.callback_program.is_none() {
+            let mut inflow = [0u64; 2];
+            let mut outflow = [0u64; 2];
+            let i = if self.instruction_data.is_x == 1 { 0 } else { 1 };
+            inflow[i] = quote.amount_in;
+            outflow[1 - i] = quote.amount_out;
+            amm_config.record_outflow(clock.unix_timestamp, net_outflow(inflow, outflow)?)?;
+        }
 
-            // Swapping y for x, transfer tokens Y from user to the pool.
+        // SOL paid in is wrapped into a temporary WSOL account first, SOL paid out is received
+        // in an empty one.
+        let native_sol = self.instruction_data.native_sol == 1;
+        let token_program = self.accounts.token_program.address();
+        let user_accounts = [(user_in, mint_in, self.instruction_data.amount), (user_out, mint_out, 0)];
+        if native_sol {
+            for (user_account, mint, amount) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::wrap(self.accounts.user, user_account, mint, amount, token_program)?;
+                }
+            }
+        }
+        // A flash swap pays out first and leaves the input to its callback.
+        let vaults_before = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+        if self.accounts.callback_program.is_none() {
             TokenAccount::transfer_spl_tokens(
-                self.accounts.user_y_ata,
-                self.accounts.vault_y,
+                user_in,
+                mint_in,
+                vault_in,
                 self.accounts.user,
                 self.instruction_data.amount,
                 None,
+                self.accounts.transfer_hook_accounts,
             )?;
-            // Transfer token from pool to user for y.
-            TokenAccount::transfer_spl_tokens(
-                self.accounts.vault_x,
-                self.accounts.user_x_ata,
-                self.accounts.config,
-                final_amount,
-                Some(&signer_seeds),
+        }
+        TokenAccount::transfer_spl_tokens(
+            vault_out,
+            mint_out,
+            user_out,
+            self.accounts.config,
+            quote.amount_out,
+            Some(&signer_seeds),
+            self.accounts.transfer_hook_accounts,
+        )?;
+        let mut post_balances = quote.post_balances;
+        if let Some(callback_program) = self.accounts.callback_program {
+            Callback::invoke(
+                callback_program, self.accounts.callback_accounts, self.instruction_data.callback_data,
             )?;
-            return Ok(());
+            // Whatever the callback paid, in either token, settles the swap as long as D is
+            // no lower than the priced swap leaves it, the pool's fee included.
+            let vaults_after = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+            post_balances = settled_balances(reserves, vaults_before, vaults_after)?;
+            if amm_config.curve(&post_balances)?.compute_d()? < amm_config.curve(&quote.post_balances)?.compute_d()? {
+                return Err(MegaAmmProgramError::InvariantDecreased.into());
+            }
+            // Whichever token the callback paid in is what flowed in.
+            amm_config.record_outflow(clock.unix_timestamp, net_outflow(post_balances, reserves)?)?;
+        }
+        // Closing the temporary WSOL account unwraps what the pool paid out, and returns its rent.
+        if native_sol {
+            for (user_account, mint, _) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::unwrap(self.accounts.user, user_account, token_program)?;
+                }
+            }
         }
+
+        // The pool prices off its own reserves, not the vault balances.
+        amm_config.set_reserves(&post_balances);
+
+        // Feeding the price oracles with the post trade reserves.
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations,
+            &post_balances, lp_supply,
+        )?;
+        // Composing programs read the settled amounts instead of diffing balances.
+        set_return_data(&quote.to_bytes());
+        emit(&SwapEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            user: self.accounts.user.address().to_bytes(),
+            is_x: self.instruction_data.is_x,
+            amount_in: quote.amount_in,
+            amount_out: quote.amount_out,
+            fee: quote.fee,
+            reserve_x: post_balances[0],
+            reserve_y: post_balances[1],
+            d: amm_config.curve(&post_balances)?.compute_d()?,
+        }.to_bytes());
+        amm_config.unlock();
         Ok(())
     }
 }
@@ -0,0 +1,99 @@
+//! Adopts the vault balances as the pool's reserves. Only the pool authority can call it,
+//! tokens sent straight to a vault then count towards the LPs and move the curve.
+use pinocchio::{
+    AccountView, ProgramResult,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::{SignerAccount, PoolAccounts};
+use crate::config::{Config, AmmState};
+use crate::observations::update_price_oracles;
+use crate::events::{emit, SyncEvent};
+use crate::helpers::math_procs::curve::Curve;
+
+pub struct SyncAccounts<'info> {
+    // Pool authority, must sign.
+    pub authority: &'info AccountView,
+    // The config account for the AMM pool.(mutable)
+    pub config: &'info AccountView,
+    // The mint for lp tokens.
+    pub mint_lp: &'info AccountView,
+    // Holds all token x deposited into the pool.
+    pub vault_x: &'info AccountView,
+    // Holds all token y deposited into the pool.
+    pub vault_y: &'info AccountView,
+    // Token program owning the vaults.
+    pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for SyncAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [
+            authority, config, mint_lp, vault_x, vault_y,
+            token_program, observations, _rem_data @ ..
+        ] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        SignerAccount::check(authority)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
+        match Config::load(config)?.has_authority() {
+            Some(pool_authority) if pool_authority.eq(authority.address()) => {},
+            _ => return Err(MegaAmmProgramError::Unauthorized.into()),
+        }
+
+        Ok(Self { authority, config, mint_lp, vault_x, vault_y, token_program, observations })
+    }
+}
+
+pub struct Sync<'info> {
+    pub accounts: SyncAccounts<'info>,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Sync<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        if !data.is_empty() {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+        let accounts = SyncAccounts::try_from(accounts)?;
+
+        Ok(Self { accounts })
+    }
+}
+
+impl<'info> Sync<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &13;
+
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+        if amm_config.state() == u8::from(AmmState::Uninitialized) {
+            return Err(MegaAmmProgramError::Unauthorized.into());
+        }
+
+        // Reserves without LP tokens would leave the first deposit nothing to mint against.
+        let (_, _, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+        if lp_supply == 0 {
+            return Err(MegaAmmProgramError::EmptyPool.into());
+        }
+        let balances = PoolAccounts::vault_balances(self.accounts.vault_x, self.accounts.vault_y)?;
+        amm_config.set_reserves(&balances);
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations, &balances, lp_supply,
+        )?;
+
+        let d = if balances.contains(&0) { 0 } else { amm_config.curve(&balances)?.compute_d()? };
+        emit(&SyncEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            authority: self.accounts.authority.address().to_bytes(),
+            reserve_x: balances[0],
+            reserve_y: balances[1],
+            d,
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Updates a single pool parameter. Only the pool authority can call it,
+//! pools initialized without an authority are immutable.
+use pinocchio::{
+    AccountView, Address, ProgramResult,
+};
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::utils::{SignerAccount, ProgramAccount};
+use crate::config::Config;
+use crate::events::{emit, ConfigChanged};
+
+pub struct UpdateConfigAccounts<'info> {
+    // Pool authority, must sign.
+    pub authority: &'info AccountView,
+    // The config account for the AMM pool being updated.(mutable)
+    pub config: &'info AccountView,
+}
+
+impl<'info> TryFrom<&'info [AccountView]> for UpdateConfigAccounts<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
+        let [authority, config, _rem_data @ ..] = accounts else {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        };
+        // Checking the accounts.
+        SignerAccount::check(authority)?;
+        ProgramAccount::check(config)?;
+        let config_state = Config::load(config)?;
+        match config_state.has_authority() {
+            Some(pool_authority) if pool_authority.eq(authority.address()) => {},
+            _ => return Err(MegaAmmProgramError::Unauthorized.into()),
+        }
+
+        Ok(Self { authority, config })
+    }
+}
+
+// Pool parameters that can be updated after initialization.
+#[repr(u8)]
+#[derive(Clone, Copy)]
+pub enum ConfigParam {
+    // EMA oracle half-life in seconds.
+    EmaHalfLife = 0u8,
+    // Newton solver iteration limit, 1 to 255.
+    SolverMaxIterations = 1u8,
+    // Newton solver convergence tolerance, 1 to 1000.
+    SolverTolerance = 2u8,
+    // Largest swap output in bps of the output reserve, 0 disables it.
+    MaxTradeBps = 3u8,
+    // Smallest output reserve a swap may leave, 0 disables it.
+    MinReserve = 4u8,
+    // Largest marginal price move of a swap in bps, 0 disables it.
+    MaxPriceImpactBps = 5u8,
+    // Seconds of the rolling outflow limit, 0 disables it.
+    OutflowWindow = 6u8,
+    // Largest net outflow of x over the window, 0 leaves x unlimited.
+    MaxOutflowX = 7u8,
+    // Largest net outflow of y over the window, 0 leaves y unlimited.
+    MaxOutflowY = 8u8,
+    // Pool state, 1 initialized, 2 disabled, 3 withdraw only.
+    State = 9u8,
+}
+
+impl TryFrom<u8> for ConfigParam {
+    type Error = MegaAmmProgramError;
+    fn try_from(param: u8) -> Result<Self, Self::Error> {
+        match param {
+            0 => Ok(ConfigParam::EmaHalfLife),
+            1 => Ok(ConfigParam::SolverMaxIterations),
+            2 => Ok(ConfigParam::SolverTolerance),
+            3 => Ok(ConfigParam::MaxTradeBps),
+            4 => Ok(ConfigParam::MinReserve),
+            5 => Ok(ConfigParam::MaxPriceImpactBps),
+            6 => Ok(ConfigParam::OutflowWindow),
+            7 => Ok(ConfigParam::MaxOutflowX),
+            8 => Ok(ConfigParam::MaxOutflowY),
+            9 => Ok(ConfigParam::State),
+            _ => Err(MegaAmmProgramError::InvalidInstructionData),
+        }
+    }
+}
+
+pub struct UpdateConfigInstructionData {
+    // Parameter being updated.
+    pub param: ConfigParam,
+    // The new value of the parameter.
+    pub value: u64,
+}
+
+impl<'info> TryFrom<&'info [u8]> for UpdateConfigInstructionData {
+    type Error = MegaAmmProgramError;
+    fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
+        if data.len() != (1+8) {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
+
+        let param = ConfigParam::try_from(data[0])?;
+        let value = u64::from_le_bytes(data[1..9].try_into().unwrap());
+
+        Ok(Self { param, value })
+    }
+}
+
+pub struct UpdateConfig<'info> {
+    pub accounts: UpdateConfigAccounts<'info>,
+    pub instruction_data: UpdateConfigInstructionData,
+}
+
+impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for UpdateConfig<'info> {
+    type Error = MegaAmmProgramError;
+    fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
+        let accounts = UpdateConfigAccounts::try_from(accounts)?;
+        let instruction_data = UpdateConfigInstructionData::try_from(data)?;
+
+        Ok(Self {
+            accounts, instruction_data
+        })
+    }
+}
+
+impl<'info> UpdateConfig<'info> {
+    pub const DISCRIMINATOR: &'info u8 = &4;
+    pub fn process(&mut self) -> ProgramResult {
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+        let param = self.instruction_data.param as u8;
+        let old_value = match self.instruction_data.param {
+            ConfigParam::EmaHalfLife => {
+                let old_value = amm_config.ema_half_life();
+                amm_config.set_ema_half_life(self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::SolverMaxIterations => {
+                let old_value = amm_config.solver_max_iterations() as u64;
+                let max_iterations = u16::try_from(self.instruction_data.value)
+                    .map_err(|_| MegaAmmProgramError::InvalidInstructionData)?;
+                amm_config.set_solver_max_iterations(max_iterations)?;
+                old_value
+            },
+            ConfigParam::SolverTolerance => {
+                let old_value = amm_config.solver_tolerance();
+                amm_config.set_solver_tolerance(self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::MaxTradeBps => {
+                let old_value = amm_config.trade_guards().max_trade_bps as u64;
+                let max_trade_bps = u16::try_from(self.instruction_data.value)
+                    .map_err(|_| MegaAmmProgramError::InvalidInstructionData)?;
+                amm_config.set_max_trade_bps(max_trade_bps)?;
+                old_value
+            },
+            ConfigParam::MinReserve => {
+                let old_value = amm_config.trade_guards().min_reserve;
+                amm_config.set_min_reserve(self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::MaxPriceImpactBps => {
+                let old_value = amm_config.trade_guards().max_price_impact_bps as u64;
+                let max_price_impact_bps = u16::try_from(self.instruction_data.value)
+                    .map_err(|_| MegaAmmProgramError::InvalidInstructionData)?;
+                amm_config.set_max_price_impact_bps(max_price_impact_bps)?;
+                old_value
+            },
+            ConfigParam::OutflowWindow => {
+                let old_value = amm_config.outflow_limiter().window;
+                amm_config.set_outflow_window(self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::MaxOutflowX => {
+                let old_value = amm_config.outflow_limiter().max_outflow[0];
+                amm_config.set_max_outflow(0, self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::MaxOutflowY => {
+                let old_value = amm_config.outflow_limiter().max_outflow[1];
+                amm_config.set_max_outflow(1, self.instruction_data.value)?;
+                old_value
+            },
+            ConfigParam::State => {
+                let old_value = amm_config.state() as u64;
+                // A pool can't go back to uninitialized.
+                let state = match self.instruction_data.value {
+                    1..=3 => self.instruction_data.value as u8,
+                    _ => return Err(MegaAmmProgramError::InvalidInstructionData.into()),
+                };
+                amm_config.set_state(state)?;
+                old_value
+            },
+        };
+        emit(&ConfigChanged {
+            pool: self.accounts.config.address().to_bytes(),
+            authority: self.accounts.authority.address().to_bytes(),
+            param,
+            old_value,
+            new_value: self.instruction_data.value,
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
+    }
+}
@@ -2,17 +2,23 @@
 use pinocchio::{
     AccountView, Address, ProgramResult,
     error::ProgramError,
-    cpi::{Signer, Seed},
+    cpi::{Signer, Seed, set_return_data},
+    sysvars::{clock::Clock, Sysvar},
 };
 use solana_address;
 use crate::helpers::errors::MegaAmmProgramError;
 use crate::helpers::utils::{
-    SignerAccount, MintInterface, TokenInterface, MintAccount,
-    TokenAccount, ProgramAccount, AssociatedTokenAccount,
+    SignerAccount, MintInterface, MintAccount,
+    TokenAccount, AssociatedTokenAccount, PoolAccounts, NativeSol,
 };
-use crate::helpers::math_procs::curve_ops::MegaAmmStableSwapCurve;
-use crate::helpers::math_procs::numerical_ops::get_d;
+use crate::helpers::math_procs::numerical_ops::{mul_div, withdraw_balanced};
+use crate::helpers::math_procs::curve::Curve;
+use crate::helpers::math_procs::MathError;
 use crate::config::{Config, AmmState};
+use crate::observations::update_price_oracles;
+use crate::guards::net_outflow;
+use crate::helpers::token_extensions::transfer_fee;
+use crate::events::{emit, WithdrawEvent};
 
 pub struct WithdrawAccounts<'info> {
     // User withdrawing funds
@@ -33,6 +39,18 @@ pub struct WithdrawAccounts<'info> {
     pub config: &'info AccountView,
     // The token program
     pub token_program: &'info AccountView,
+    // Ring buffer of the pool's price observations.(mutable)
+    pub observations: &'info AccountView,
+    // Mint of token x, checked transfers need its decimals.
+    pub mint_x: &'info AccountView,
+    // Mint of token y, checked transfers need its decimals.
+    pub mint_y: &'info AccountView,
+    // System program creating the WSOL account native SOL is received in, first of the trailing
+    // accounts when SOL is withdrawn.
+    pub system_program: Option<&'info AccountView>,
+    // Accounts forwarded to the transfer hooks of the pool's mints: the hook programs, their
+    // `ExtraAccountMetaList` accounts and the extra accounts they list. Empty without hooks.
+    pub transfer_hook_accounts: &'info [AccountView],
 }
 
 impl<'info> TryFrom<&'info [AccountView]> for WithdrawAccounts<'info> {
@@ -40,24 +58,30 @@ impl<'info> TryFrom<&'info [AccountView]> for WithdrawAccounts<'info> {
     fn try_from(accounts: &'info [AccountView]) -> Result<Self, Self::Error> {
         let [
             user, mint_lp, vault_x, vault_y, user_x_ata,
-            user_y_ata, user_lp_ata, config, token_program,
+            user_y_ata, user_lp_ata, config, token_program, observations,
+            mint_x, mint_y, rem_data @ ..
         ] = accounts else {
             return Err(MegaAmmProgramError::InvalidAccountData.into());
         };
         // Checking the accounts.
         SignerAccount::check(user)?;
-        MintInterface::check(mint_lp)?;
-        TokenInterface::check(vault_x)?;
-        TokenInterface::check(vault_y)?;
+        PoolAccounts::check(config, mint_lp, vault_x, vault_y, token_program)?;
         AssociatedTokenAccount::check(user_lp_ata, user, mint_lp.address(), token_program)?;
-        ProgramAccount::check(config)?;
         let config_state = Config::load(config)?;
-        AssociatedTokenAccount::check(user_x_ata, user, config_state.mint_x(), token_program)?;
-        AssociatedTokenAccount::check(user_y_ata, user, config_state.mint_y(), token_program)?;
+        // A native leg may go through the user's temporary WSOL account instead of their ATA.
+        for (user_ata, mint) in [(user_x_ata, config_state.mint_x()), (user_y_ata, config_state.mint_y())] {
+            if !NativeSol::is_temporary(user_ata, user, mint) {
+                AssociatedTokenAccount::check(user_ata, user, mint, token_program)?;
+            }
+        }
+        PoolAccounts::check_mints(config_state, mint_x, mint_y, token_program)?;
+        MintInterface::check_token_program(mint_lp, token_program)?;
+        let (system_program, transfer_hook_accounts) = NativeSol::split_system_program(rem_data);
 
         Ok(Self {
             user, mint_lp, vault_x, vault_y, user_x_ata,
-            user_y_ata, user_lp_ata, config, token_program,
+            user_y_ata, user_lp_ata, config, token_program, observations,
+            mint_x, mint_y, system_program, transfer_hook_accounts,
         })
     }
 }
@@ -74,12 +98,14 @@ pub struct WithdrawInstructionData {
     pub expiration: i64,
     // Withdraw mode, whether its balanced withdrawal or one sided.
     pub withdraw_mode: u8,
+    // The native mint side is paid out in SOL, bool value (1 or 0). Optional, 0 if absent.
+    pub native_sol: u8,
 }
 
 impl<'info> TryFrom<&'info [u8]> for WithdrawInstructionData {
     type Error = MegaAmmProgramError;
     fn try_from(data: &'info [u8]) -> Result<Self, Self::Error> {
-        if data.len() != (8+8+8+8+1) {
+        if data.len() != (8+8+8+8+1) && data.len() != (8+8+8+8+2) {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
 
@@ -88,10 +114,14 @@ impl<'info> TryFrom<&'info [u8]> for WithdrawInstructionData {
         let amount_of_y = u64::from_le_bytes(data[16..24].try_into().unwrap());
         let expiration = i64::from_le_bytes(data[24..32].try_into().unwrap());
         let withdraw_mode = data[32];
+        let native_sol = data.get(33).copied().unwrap_or(0);
 
         if withdraw_mode != 0 && withdraw_mode != 1 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
         }
+        if native_sol != 0 && native_sol != 1 {
+            return Err(MegaAmmProgramError::InvalidInstructionData.into());
+        }
 
         if lp_to_burn < 0 {
             return Err(MegaAmmProgramError::InvalidInstructionData.into());
@@ -102,7 +132,7 @@ impl<'info> TryFrom<&'info [u8]> for WithdrawInstructionData {
         }
 
         Ok(Self {
-            lp_to_burn, amount_of_x, amount_of_y, expiration, withdraw_mode
+            lp_to_burn, amount_of_x, amount_of_y, expiration, withdraw_mode, native_sol,
         })
     }
 }
@@ -117,6 +147,15 @@ impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Withdraw<'info> {
     fn try_from((data, accounts): (&'info [u8], &'info [AccountView])) -> Result<Self, Self::Error> {
         let accounts = WithdrawAccounts::try_from(accounts)?;
         let instruction_data = WithdrawInstructionData::try_from(data)?;
+        // Withdrawing SOL needs the native mint on one side and the system program to create
+        // the WSOL account receiving it.
+        NativeSol::check_accounts(
+            instruction_data.native_sol == 1, accounts.user,
+            [(accounts.user_x_ata, accounts.mint_x), (accounts.user_y_ata, accounts.mint_y)],
+        )?;
+        if instruction_data.native_sol == 1 && accounts.system_program.is_none() {
+            return Err(MegaAmmProgramError::NotEnoughAccountKeys);
+        }
         // Validated data.
         Ok(Self {
             accounts, instruction_data,
@@ -124,33 +163,85 @@ impl<'info> TryFrom<(&'info [u8], &'info [AccountView])> for Withdraw<'info> {
     }
 }
 
+// Tokens paid out and LP tokens burned by a withdrawal.
+// Return data layout, all values little endian u64.
+// [0..8] amount of x out, [8..16] amount of y out, [16..24] LP burned.
+pub struct WithdrawQuote {
+    pub amounts_out: [u64; 2],
+    pub lp_burned: u64,
+    // Fee kept by the pool on single sided exits, in the withdrawn token. Not part of the return data.
+    pub fee: u64,
+    // Pool reserves (x, y) once the withdrawal settles.
+    pub post_balances: [u64; 2],
+}
+
+impl WithdrawQuote {
+    pub const LEN: usize = 8 * 3;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        data[0..8].copy_from_slice(&self.amounts_out[0].to_le_bytes());
+        data[8..16].copy_from_slice(&self.amounts_out[1].to_le_bytes());
+        data[16..24].copy_from_slice(&self.lp_burned.to_le_bytes());
+        data
+    }
+}
+
 impl<'info> Withdraw<'info> {
     pub const DISCRIMINATOR: &'info u8 = &2;
-    pub fn process(&mut self) -> ProgramResult {
-        // Loading the config.
-        let amm_config = Config::load(self.accounts.config)?;
-        if amm_config.state() != AmmState::Initialized.into() {
-            return Err(MegaAmmProgramError::Unauthorized.into());
-        }
 
-        let (vault_x_amount, vault_y_amount, lp_supply) = {
-            let mint_data_ref = self.accounts.mint_lp.try_borrow()?;
-            let mint_lp = unsafe {
-                pinocchio_token::state::Mint::from_bytes_unchecked(&mint_data_ref)
-            };
-            let vault_x_data_ref = self.accounts.vault_x.try_borrow()?;
-            let vault_x = unsafe {
-                pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_x_data_ref)
-            };
-            let vault_y_data_ref = self.accounts.vault_y.try_borrow()?;
-            let vault_y = unsafe {
-                pinocchio_token::state::TokenAccount::from_bytes_unchecked(&vault_y_data_ref)
+    // Curve calculations for both withdrawal modes. Shared by Withdraw and QuoteWithdraw
+    // so quotes follow the exact execution path.
+    pub fn quote(
+        amm_config: &Config, vault_x_amount: u64, vault_y_amount: u64, lp_supply: u64,
+        instruction_data: &WithdrawInstructionData,
+    ) -> Result<WithdrawQuote, ProgramError> {
+        let balances = [vault_x_amount, vault_y_amount];
+        let curve = amm_config.curve(&balances)?;
+        let (amounts_out, lp_burned, fee) = if instruction_data.withdraw_mode == 0 {
+            // Balanced withdrawal. Specifying the lps to burn comes from the frontend.
+            // Amounts are arranged as the balances were supplied, x then y.
+            // Proportional to the reserves whatever the curve.
+            let amounts = withdraw_balanced(&balances, instruction_data.lp_to_burn, lp_supply)?;
+            ([amounts[0], amounts[1]], instruction_data.lp_to_burn, 0)
+        } else {
+            // Imbalanced withdrawal acts as a virtual swap. Only one side can be requested.
+            let (idx, amount) = match (instruction_data.amount_of_x, instruction_data.amount_of_y) {
+                (x, 0) if x > 0 => (0usize, x),
+                (0, y) if y > 0 => (1usize, y),
+                _ => return Err(MegaAmmProgramError::InvalidInstructionData.into()),
             };
-            (vault_x.amount(), vault_y.amount(), mint_lp.supply())
+            let d_current = curve.compute_d()?;
+            let mut reduced = balances;
+            reduced[idx] = reduced[idx].checked_sub(amount).ok_or(MathError::InsufficientLiquidity)?;
+            let d_new = amm_config.curve(&reduced)?.compute_d()?;
+            let spread = d_current.checked_sub(d_new).ok_or(MathError::Underflow)?;
+            // Specifying lps to burn is calculated by the smart contract.
+            let lp_to_burn = u64::try_from(mul_div(lp_supply as u128, spread, d_current)?)
+                .map_err(|_| MathError::Overflow)?;
+            // Fee has already been applied
+            let (amount_out, fee) = curve.withdraw_one(lp_to_burn, lp_supply, idx)?;
+            let mut amounts_out = [0u64; 2];
+            amounts_out[idx] = amount_out;
+            (amounts_out, lp_to_burn, fee)
         };
 
+        let post_balances = [
+            vault_x_amount.checked_sub(amounts_out[0]).ok_or(ProgramError::ArithmeticOverflow)?,
+            vault_y_amount.checked_sub(amounts_out[1]).ok_or(ProgramError::ArithmeticOverflow)?,
+        ];
+        Ok(WithdrawQuote { amounts_out, lp_burned, fee, post_balances })
+    }
+
+    pub fn process(&mut self) -> ProgramResult {
+        // Loading the config.
+        let amm_config = Config::load_mut(self.accounts.config)?;
+        amm_config.lock()?;
+        amm_config.check_withdraw_allowed(self.instruction_data.withdraw_mode)?;
+
+        let (vault_x_amount, vault_y_amount, lp_supply) = PoolAccounts::reserves(amm_config, self.accounts.mint_lp)?;
+
         // Used for pda signing during withdrawal.
-        let seed_binding = amm_config.seed().to_le_bytes();
         let conf_bump_binding = amm_config.config_bump();
         let config_signer_seeds = [
             Seed::from(b"config"),
@@ -158,110 +249,110 @@ impl<'info> Withdraw<'info> {
         ];
         let signer_seeds = [Signer::from(&config_signer_seeds)];
 
-        let balances = [vault_x_amount, vault_y_amount];
-        // Transfer token amounts returned, list of amounts of tokens to move.
-        if self.instruction_data.withdraw_mode == 0 {
-            // Balanced withdrawal. Specifying the lps to burn comes from the frontend.
-            // This is a slice, arranged as it was supplied to the curve
-            // i.e for above, x is at the 1st index then y, these are amounts to send
-            let mut curve = MegaAmmStableSwapCurve {
-                balances: &balances, target_token_idx: None, fee_bps: 0
-            };
-            let new_balances = curve.amm_balanced_withdrawal(self.instruction_data.lp_to_burn, lp_supply)
-                .map_err(|_| ProgramError::Custom(2))?;
+        // Token-2022 transfer fees are withheld from what the user receives. A single sided
+        // exit asks for an exact amount, the pool pays out enough for the user to receive it.
+        let clock = Clock::get()?;
+        let request = WithdrawInstructionData {
+            lp_to_burn: self.instruction_data.lp_to_burn,
+            amount_of_x: transfer_fee(self.accounts.mint_x, clock.epoch)?.gross(self.instruction_data.amount_of_x)?,
+            amount_of_y: transfer_fee(self.accounts.mint_y, clock.epoch)?.gross(self.instruction_data.amount_of_y)?,
+            expiration: self.instruction_data.expiration,
+            withdraw_mode: self.instruction_data.withdraw_mode,
+            native_sol: self.instruction_data.native_sol,
+        };
+        let quote = Self::quote(
+            amm_config, vault_x_amount, vault_y_amount, lp_supply, &request,
+        )?;
+
+        // Rolling outflow limit. Balanced exits from a pool in `WithdrawOnly` are exempt so
+        // LPs can always leave a pool that was wound down.
+        let winding_down = amm_config.state() == u8::from(AmmState::WithdrawOnly);
+        if !(winding_down && self.instruction_data.withdraw_mode == 0) {
+            amm_config.record_outflow(clock.unix_timestamp, net_outflow([0; 2], quote.amounts_out)?)?;
+        }
+
+        // SOL withdrawn is received in an empty temporary WSOL account.
+        let native_sol = self.instruction_data.native_sol == 1;
+        let token_program = self.accounts.token_program.address();
+        let user_accounts = [
+            (self.accounts.user_x_ata, self.accounts.mint_x),
+            (self.accounts.user_y_ata, self.accounts.mint_y),
+        ];
+        if native_sol {
+            for (user_account, mint) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::wrap(self.accounts.user, user_account, mint, 0, token_program)?;
+                }
+            }
+        }
 
-            // Transfer tokens x from the pool to the user.
+        // Transfer tokens x from the pool to the user.
+        if quote.amounts_out[0] > 0 {
             TokenAccount::transfer_spl_tokens(
                 self.accounts.vault_x,
+                self.accounts.mint_x,
                 self.accounts.user_x_ata,
                 self.accounts.config,
-                new_balances[0],
+                quote.amounts_out[0],
                 Some(&signer_seeds),
+                self.accounts.transfer_hook_accounts,
             )?;
-            // Transfer token y from the pool to the user.
+        }
+        // Transfer token y from the pool to the user.
+        if quote.amounts_out[1] > 0 {
             TokenAccount::transfer_spl_tokens(
                 self.accounts.vault_y,
+                self.accounts.mint_y,
                 self.accounts.user_y_ata,
                 self.accounts.config,
-                new_balances[1],
+                quote.amounts_out[1],
                 Some(&signer_seeds),
+                self.accounts.transfer_hook_accounts,
             )?;
+        }
 
-            // Burning the required tokens, for pool share ownership.
-            TokenAccount::burn_tokens(
-                self.accounts.mint_lp,
-                self.accounts.user_lp_ata,
-                self.accounts.user,
-                self.instruction_data.lp_to_burn,
-                None
-            )?;
-            Ok(())
-        } else {
-            // Imbalanced withdrawal acts as a virtual swap.
-            // Imbalanced withdrawal of x from the pool. x has changed
-            let d_current = get_d(100, &[vault_x_amount, vault_y_amount]).map_err(|_| ProgramError::Custom(1))?;
-            if self.instruction_data.amount_of_x > 0 && self.instruction_data.amount_of_y == 0 {
-                let mut curve = MegaAmmStableSwapCurve {
-                    balances: &balances, target_token_idx: Some(0), fee_bps: amm_config.fee().into()
-                };
-
-                let new_x_amount = vault_x_amount.checked_sub(self.instruction_data.amount_of_x).ok_or(ProgramError::Custom(5))?;
-                let d_new = get_d(100, &[new_x_amount, vault_y_amount]).map_err(|_| ProgramError::Custom(1))?;
-                let spread = d_current.checked_sub(d_new).ok_or(ProgramError::Custom(2))?;
-                let lp_to_burn = lp_supply.checked_mul(spread).ok_or(ProgramError::Custom(3))?.checked_div(d_current).ok_or(ProgramError::Custom(4))?;
-                // Specifying lps to burn is calculated by the smart contract.
-                // Fee has already been applied
-                let new_balance = curve.amm_imbalanced_withdrawal(lp_to_burn, lp_supply, 100)
-                    .map_err(|_| ProgramError::Custom(2))?;
-                TokenAccount::transfer_spl_tokens(
-                    self.accounts.vault_x,
-                    self.accounts.user_x_ata,
-                    self.accounts.config,
-                    new_balance,
-                    Some(&signer_seeds),
-                )?;
-                // Burning the required tokens, for pool share ownership after withdrawal.
-                TokenAccount::burn_tokens(
-                    self.accounts.mint_lp,
-                    self.accounts.user_lp_ata,
-                    self.accounts.user,
-                    lp_to_burn,
-                    None
-                )?;
-                return Ok(());
+        // Closing the temporary WSOL account unwraps what the pool paid out, and returns its rent.
+        if native_sol {
+            for (user_account, mint) in user_accounts {
+                if MintInterface::is_native(mint) {
+                    NativeSol::unwrap(self.accounts.user, user_account, token_program)?;
+                }
             }
+        }
 
-            // Imbalanced withdrawal of y from the pool. y has changed
-            if self.instruction_data.amount_of_y > 0 && self.instruction_data.amount_of_x == 0 {
-                let mut curve = MegaAmmStableSwapCurve {
-                    balances: &balances, target_token_idx: Some(1), fee_bps: amm_config.fee().into()
-                };
+        // Burning the required tokens, for pool share ownership after withdrawal.
+        TokenAccount::burn_tokens(
+            self.accounts.mint_lp,
+            self.accounts.user_lp_ata,
+            self.accounts.user,
+            quote.lp_burned,
+            None
+        )?;
 
-                let new_y_balance = vault_y_amount.checked_sub(self.instruction_data.amount_of_y).ok_or(ProgramError::Custom(5))?;
-                let d_new = get_d(100, &[vault_x_amount, new_y_balance]).map_err(|_| ProgramError::Custom(1))?;
-                let spread = d_current.checked_sub(d_new).ok_or(ProgramError::Custom(2))?;
-                let lp_to_burn = lp_supply.checked_mul(spread).ok_or(ProgramError::Custom(3))?.checked_div(d_current).ok_or(ProgramError::Custom(4))?;
-                // Specifying lps to burn is calculated by the smart contract.
-                let new_balance = curve.amm_imbalanced_withdrawal(lp_to_burn, lp_supply, 100)
-                    .map_err(|_| ProgramError::Custom(2))?;
-                TokenAccount::transfer_spl_tokens(
-                    self.accounts.vault_y,
-                    self.accounts.user_y_ata,
-                    self.accounts.config,
-                    new_balance,
-                    Some(&signer_seeds),
-                )?;
-                // Burning the required tokens, for pool share ownership after withdrawal.
-                TokenAccount::burn_tokens(
-                    self.accounts.mint_lp,
-                    self.accounts.user_lp_ata,
-                    self.accounts.user,
-                    lp_to_burn,
-                    None
-                )?;
-                return Ok(());
-            }
-            Ok(())
-        }
+        // The pool prices off its own reserves, not the vault balances.
+        amm_config.set_reserves(&quote.post_balances);
+
+        // Feeding the price oracles with the post withdrawal reserves.
+        let post_lp_supply = lp_supply.checked_sub(quote.lp_burned).ok_or(ProgramError::ArithmeticOverflow)?;
+        update_price_oracles(
+            amm_config, self.accounts.config, self.accounts.observations, &quote.post_balances,
+            post_lp_supply,
+        )?;
+        set_return_data(&quote.to_bytes());
+        emit(&WithdrawEvent {
+            pool: self.accounts.config.address().to_bytes(),
+            user: self.accounts.user.address().to_bytes(),
+            withdraw_mode: self.instruction_data.withdraw_mode,
+            amount_x: quote.amounts_out[0],
+            amount_y: quote.amounts_out[1],
+            lp_burned: quote.lp_burned,
+            fee: quote.fee,
+            reserve_x: quote.post_balances[0],
+            reserve_y: quote.post_balances[1],
+            lp_supply: post_lp_supply,
+            d: amm_config.curve(&quote.post_balances)?.compute_d()?,
+        }.to_bytes());
+        amm_config.unlock();
+        Ok(())
     }
 }
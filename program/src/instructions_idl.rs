@@ -1,6 +1,6 @@
 #![cfg(feature = "idl")]
 /// Nothing in this file should be evaulated during main production builds.
-use shank::{ShankAccount};
+use shank::{ShankAccount, ShankType};
 use borsh::{BorshSerialize, BorshDeserialize};
 use solana_program::pubkey::Pubkey;
 
@@ -18,6 +18,7 @@ pub enum InitializerInstruction {
     #[account(7, name="ata_token_program", desc="ATA program used in creating the ata")]
     #[account(8, name="system_program", desc="System program used in creating PDAs")]
     #[account(9, name="token_program", desc="Token program")]
+    #[account(10, writable, name="observations", desc="Price observations PDA account")]
     Initialize {
         seed: u64,
         fee: u16,
@@ -27,9 +28,16 @@ pub enum InitializerInstruction {
         lp_mint_decimals: u8,
         lp_bump: [u8; 1],
         authority: [u8; 32],
+        /// 0 stableswap, 1 constant product, 2 weighted, 3 cryptoswap.
+        curve_type: u8,
+        /// Weight of token x for weighted pools, 1e18 scaled, within [1e16, 99e16]. 0 otherwise.
+        weight_x: u64,
     },
 
-    /// Depositing to the protocol
+    /// Depositing to the protocol. Sets `DepositQuote` as return data.
+    /// With `native_sol` set, the temporary WSOL account PDA ["wsol", user, native mint] stands in for the user ATA of the native mint,
+    /// created from the user's lamports and closed back to them within the instruction.
+    /// Transfer hook accounts of the pool's mints follow the listed accounts.
     #[account(0, writable, signer, name="user", desc="User depositing token to provide liquidity")]
     #[account(1, writable, name="vault_x", desc="Token account that holds token x deposited")]
     #[account(2, writable, name="vault_y", desc="Token account that holds token y deposited")]
@@ -39,13 +47,27 @@ pub enum InitializerInstruction {
     #[account(6, writable, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
     #[account(7, name="user_lp_ata", desc="User ATA for LP tokens")]
     #[account(8, name="token_program", desc="Token program")]
+    #[account(9, writable, name="observations", desc="Price observations PDA account")]
+    #[account(10, name="mint_x", desc="Token mint for token x")]
+    #[account(11, name="mint_y", desc="Token mint for token y")]
+    #[account(12, optional, writable, name="locked_lp", desc="Pool ATA for LP tokens, locks the minimum liquidity on the first deposit. Only required by the first deposit")]
+    #[account(13, optional, name="system_program", desc="System program, creates the temporary WSOL account")]
     Deposit {
         amount_x: u64,
         amount_y: u64,
         expiration: i64,
+        /// 1 pays the native mint side from the user's lamports. Optional, 0 if left out.
+        native_sol: u8,
     },
 
-    /// Performing a token swap from the protocol.
+    /// Performing a token swap from the protocol. Sets `SwapQuote` as return data.
+    /// With `native_sol` set, the temporary WSOL account PDA ["wsol", user, native mint] stands in for the user ATA of the native mint,
+    /// funded from the user's lamports when paid in and closed back to them within the instruction.
+    /// Transfer hook accounts of the pool's mints follow the listed accounts.
+    /// With `flash` set, the output is sent first and `callback_program` is then called with
+    /// `callback_data` and the `callback_accounts_len` accounts after it, the transfer hook
+    /// accounts follow them. It pays the input to the vaults, the swap fails
+    /// unless the pool's D ends up no lower than a regular swap leaves it.
     #[account(0, writable, signer, name="user", desc="User who wants to perform the swap")]
     #[account(1, writable, name="vault_x", desc="Holds all token x deposited into the pool")]
     #[account(2, writable, name="vault_y", desc="Holds all token y deposited into the pool")]
@@ -54,14 +76,30 @@ pub enum InitializerInstruction {
     #[account(5, writable, name="config", desc="Protocol configuration account")]
     #[account(6, writable, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
     #[account(7, name="token_program", desc="Token program")]
+    #[account(8, writable, name="observations", desc="Price observations PDA account")]
+    #[account(9, name="mint_x", desc="Token mint for token x")]
+    #[account(10, name="mint_y", desc="Token mint for token y")]
+    #[account(11, optional, name="system_program", desc="System program, creates the temporary WSOL account")]
+    #[account(12, optional, name="callback_program", desc="Program paying for a flash swap once the output is sent")]
     Swap {
         amount: u64,
         min_out: u64,
         expiration: i64,
         is_x: u8,
+        /// 1 settles the native mint side in SOL, wrapping the input and unwrapping the output. Optional, 0 if left out.
+        native_sol: u8,
+        /// 1 sends the output first and lets `callback_program` pay the input. Optional, 0 if left out.
+        flash: u8,
+        /// Number of trailing accounts forwarded to the callback. Flash swaps only.
+        callback_accounts_len: u8,
+        /// Instruction data of the flash swap callback, forwarded unchanged.
+        callback_data: Vec<u8>,
     },
 
-    /// Withdrawing liquidity from the protocol
+    /// Withdrawing liquidity from the protocol. Sets `WithdrawQuote` as return data.
+    /// With `native_sol` set, the temporary WSOL account PDA ["wsol", user, native mint] stands in for the user ATA of the native mint,
+    /// created empty and closed back to the user's lamports within the instruction.
+    /// Transfer hook accounts of the pool's mints follow the listed accounts.
     #[account(0, writable, signer, name="user", desc="User depositing token to provide liquidity")]
     #[account(1, writable, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
     #[account(2, writable, name="vault_x", desc="Token account that holds token x deposited")]
@@ -71,11 +109,198 @@ pub enum InitializerInstruction {
     #[account(6, writable, name="user_lp_ata", desc="User ata that holds lp tokens")]
     #[account(7, writable, name="config", desc="Protocol config account")]
     #[account(8, name="token_program", desc="Token program")]
+    #[account(9, writable, name="observations", desc="Price observations PDA account")]
+    #[account(10, name="mint_x", desc="Token mint for token x")]
+    #[account(11, name="mint_y", desc="Token mint for token y")]
+    #[account(12, optional, name="system_program", desc="System program, creates the temporary WSOL account")]
     Withdraw {
         lp_to_burn: u64,
         amount_of_x: u64,
         amount_of_y: i64,
         expiration: i64,
         withdraw_mode: u8,
+        /// 1 unwraps the native mint side to the user's lamports. Optional, 0 if left out.
+        native_sol: u8,
     },
+
+    /// Updating a pool parameter. Authority only.
+    #[account(0, signer, name="authority", desc="Pool authority")]
+    #[account(1, writable, name="config", desc="Protocol config account")]
+    UpdateConfig {
+        param: u8,
+        value: u64,
+    },
+
+    /// Reading the pool's spot and EMA price through return data.
+    #[account(0, name="config", desc="Protocol config account holding the oracle state")]
+    GetOraclePrice,
+
+    /// Growing the price observations ring buffer. Permissionless.
+    #[account(0, writable, signer, name="payer", desc="Pays for the added observation slots")]
+    #[account(1, name="config", desc="Protocol config account")]
+    #[account(2, writable, name="observations", desc="Price observations PDA account")]
+    #[account(3, name="system_program", desc="System program")]
+    GrowObservations {
+        cardinality_next: u16,
+    },
+
+    /// Reading the value of one LP token, D * 1e18 / lp_supply, through return data.
+    #[account(0, name="config", desc="Protocol config account")]
+    #[account(1, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(2, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(3, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(4, name="token_program", desc="Token program")]
+    GetVirtualPrice,
+
+    /// Quoting a swap. Returns amount in, amount out and fee through return data.
+    #[account(0, name="config", desc="Protocol config account")]
+    #[account(1, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(2, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(3, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(4, name="token_program", desc="Token program")]
+    QuoteSwap {
+        amount: u64,
+        is_x: u8,
+    },
+
+    /// Quoting a deposit. Returns the LP tokens minted through return data.
+    #[account(0, name="config", desc="Protocol config account")]
+    #[account(1, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(2, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(3, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(4, name="token_program", desc="Token program")]
+    QuoteDeposit {
+        amount_x: u64,
+        amount_y: u64,
+    },
+
+    /// Quoting a withdrawal. Returns amounts of x and y out and LP burned through return data.
+    #[account(0, name="config", desc="Protocol config account")]
+    #[account(1, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(2, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(3, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(4, name="token_program", desc="Token program")]
+    QuoteWithdraw {
+        lp_to_burn: u64,
+        amount_of_x: u64,
+        amount_of_y: u64,
+        withdraw_mode: u8,
+    },
+
+    /// Reporting the Newton solver's iterations, residual and convergence for the pool's D and,
+    /// with a non zero amount, a swap's new balance. Empty data diagnoses D only.
+    #[account(0, name="config", desc="Protocol config account")]
+    #[account(1, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(2, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(3, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(4, name="token_program", desc="Token program")]
+    GetSolverDiagnostics {
+        amount: u64,
+        is_x: u8,
+    },
+
+    /// Sending the vault balances above the pool's tracked reserves to the recipients. Permissionless.
+    /// Transfer hook accounts of the pool's mints follow the listed accounts.
+    #[account(0, writable, name="config", desc="Protocol config account")]
+    #[account(1, writable, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(2, writable, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(3, writable, name="recipient_x", desc="Receives the excess of token x")]
+    #[account(4, writable, name="recipient_y", desc="Receives the excess of token y")]
+    #[account(5, name="token_program", desc="Token program")]
+    #[account(6, name="mint_x", desc="Token mint for token x")]
+    #[account(7, name="mint_y", desc="Token mint for token y")]
+    Skim,
+
+    /// Adopting the vault balances as the pool's reserves. Only the pool authority can call it.
+    #[account(0, signer, name="authority", desc="Pool authority")]
+    #[account(1, writable, name="config", desc="Protocol config account")]
+    #[account(2, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(3, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(4, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(5, name="token_program", desc="Token program")]
+    #[account(6, writable, name="observations", desc="Price observations PDA account")]
+    Sync,
+
+    /// Donating tokens to the LPs. Grows the reserves without minting LP, at the ratio of the reserves.
+    /// Transfer hook accounts of the pool's mints follow the listed accounts.
+    #[account(0, writable, signer, name="donor", desc="Account donating the tokens")]
+    #[account(1, writable, name="config", desc="Protocol config account")]
+    #[account(2, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(3, writable, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(4, writable, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(5, writable, name="donor_x_ata", desc="Donor ata for token x")]
+    #[account(6, writable, name="donor_y_ata", desc="Donor ata for token y")]
+    #[account(7, name="token_program", desc="Token program")]
+    #[account(8, writable, name="observations", desc="Price observations PDA account")]
+    #[account(9, name="mint_x", desc="Token mint for token x")]
+    #[account(10, name="mint_y", desc="Token mint for token y")]
+    Donate {
+        amount_x: u64,
+        amount_y: u64,
+    },
+
+    /// Lending vault reserves for one instruction. The borrower program is called back with the
+    /// `borrower_accounts_len` accounts after it and must repay the vaults with the pool's fee,
+    /// which joins the reserves. Transfer hook accounts of the pool's mints follow them.
+    #[account(0, writable, signer, name="borrower", desc="Account borrowing the tokens")]
+    #[account(1, writable, name="config", desc="Protocol config account")]
+    #[account(2, name="mint_lp", desc="Mint account for the pool liquidity tokens")]
+    #[account(3, writable, name="vault_x", desc="Token account that holds token x deposited")]
+    #[account(4, writable, name="vault_y", desc="Token account that holds token y deposited")]
+    #[account(5, writable, name="borrower_x_ata", desc="Borrower ata for token x, receives the x borrowed")]
+    #[account(6, writable, name="borrower_y_ata", desc="Borrower ata for token y, receives the y borrowed")]
+    #[account(7, name="token_program", desc="Token program")]
+    #[account(8, writable, name="observations", desc="Price observations PDA account")]
+    #[account(9, name="mint_x", desc="Token mint for token x")]
+    #[account(10, name="mint_y", desc="Token mint for token y")]
+    #[account(11, name="borrower_program", desc="Program called back to repay the loan")]
+    FlashLoan {
+        amount_x: u64,
+        amount_y: u64,
+        /// Number of trailing accounts forwarded to the borrower program.
+        borrower_accounts_len: u8,
+        /// Instruction data of the callback, the rest of the instruction without a length prefix.
+        callback_data: Vec<u8>,
+    },
+}
+
+/// Return data of Swap and QuoteSwap.
+#[derive(Debug, Clone, ShankType, BorshSerialize, BorshDeserialize)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Fee kept by the pool, in the output token.
+    pub fee: u64,
+}
+
+/// Return data of Deposit and QuoteDeposit.
+#[derive(Debug, Clone, ShankType, BorshSerialize, BorshDeserialize)]
+pub struct DepositQuote {
+    pub lp_minted: u64,
+}
+
+/// Return data of Withdraw and QuoteWithdraw.
+#[derive(Debug, Clone, ShankType, BorshSerialize, BorshDeserialize)]
+pub struct WithdrawQuote {
+    pub amount_x_out: u64,
+    pub amount_y_out: u64,
+    pub lp_burned: u64,
+}
+
+/// Outcome of one Newton solve.
+#[derive(Debug, Clone, ShankType, BorshSerialize, BorshDeserialize)]
+pub struct Convergence {
+    pub value: u128,
+    pub iterations: u16,
+    /// Last step between two iterates.
+    pub residual: u128,
+    pub converged: bool,
+}
+
+/// Return data of GetSolverDiagnostics. `y` is zeroed when no swap was solved.
+#[derive(Debug, Clone, ShankType, BorshSerialize, BorshDeserialize)]
+pub struct SolverDiagnostics {
+    pub d: Convergence,
+    pub has_y: bool,
+    pub y: Convergence,
 }
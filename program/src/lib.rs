@@ -12,6 +12,9 @@ use pinocchio_log::log;
 pub mod helpers;
 pub mod instructions;
 pub mod config;
+pub mod observations;
+pub mod guards;
+pub mod events;
 #[cfg(feature = "idl" )]
 pub mod instructions_idl;
 
@@ -21,6 +24,18 @@ use instructions::{
     deposit::Deposit,
     withdraw::Withdraw,
     swap::Swap,
+    update_config::UpdateConfig,
+    get_oracle_price::GetOraclePrice,
+    grow_observations::GrowObservations,
+    get_virtual_price::GetVirtualPrice,
+    quote_swap::QuoteSwap,
+    quote_deposit::QuoteDeposit,
+    quote_withdraw::QuoteWithdraw,
+    get_solver_diagnostics::GetSolverDiagnostics,
+    skim::Skim,
+    sync::Sync,
+    donate::Donate,
+    flash_loan::FlashLoan,
 };
 use config::*;
 
@@ -50,6 +65,18 @@ fn process_instructions(
         Some((Deposit::DISCRIMINATOR, data)) => Deposit::try_from((data, accounts))?.process(),
         Some((Withdraw::DISCRIMINATOR, data)) => Withdraw::try_from((data, accounts))?.process(),
         Some((Swap::DISCRIMINATOR, data)) => Swap::try_from((data, accounts))?.process(),
+        Some((UpdateConfig::DISCRIMINATOR, data)) => UpdateConfig::try_from((data, accounts))?.process(),
+        Some((GetOraclePrice::DISCRIMINATOR, data)) => GetOraclePrice::try_from((data, accounts))?.process(),
+        Some((GrowObservations::DISCRIMINATOR, data)) => GrowObservations::try_from((data, accounts))?.process(),
+        Some((GetVirtualPrice::DISCRIMINATOR, data)) => GetVirtualPrice::try_from((data, accounts))?.process(),
+        Some((QuoteSwap::DISCRIMINATOR, data)) => QuoteSwap::try_from((data, accounts))?.process(),
+        Some((QuoteDeposit::DISCRIMINATOR, data)) => QuoteDeposit::try_from((data, accounts))?.process(),
+        Some((QuoteWithdraw::DISCRIMINATOR, data)) => QuoteWithdraw::try_from((data, accounts))?.process(),
+        Some((GetSolverDiagnostics::DISCRIMINATOR, data)) => GetSolverDiagnostics::try_from((data, accounts))?.process(),
+        Some((Skim::DISCRIMINATOR, data)) => Skim::try_from((data, accounts))?.process(),
+        Some((Sync::DISCRIMINATOR, data)) => Sync::try_from((data, accounts))?.process(),
+        Some((Donate::DISCRIMINATOR, data)) => Donate::try_from((data, accounts))?.process(),
+        Some((FlashLoan::DISCRIMINATOR, data)) => FlashLoan::try_from((data, accounts))?.process(),
         _ => Err(ProgramError::InvalidInstructionData),
     }
 }
@@ -0,0 +1,329 @@
+//! Per pool observation account. Fixed size ring buffer of cumulative price and
+//! cumulative D per LP readings used to compute TWAPs over arbitrary windows.
+//! Written at most once per slot by the state changing instructions.
+use pinocchio::{
+    AccountView, Address, ProgramResult,
+    sysvars::{clock::Clock, Sysvar},
+};
+
+use crate::helpers::errors::MegaAmmProgramError;
+use crate::helpers::math_procs::curve::Curve;
+use crate::helpers::math_procs::MathError;
+use crate::helpers::math_procs::oracle_ops::{
+    accumulate, interpolate_cumulative, twap_from_cumulatives,
+};
+use crate::config::Config;
+
+// Upper bound of the buffer's cardinality.
+pub const MAX_OBSERVATIONS: u16 = 4_096;
+
+#[repr(C)]
+pub struct ObservationsHeader {
+    config: Address, // Pool the observations belong to.
+    bump: [u8; 1], // PDA observations account derivation bump seed.
+    index: [u8; 2], // Position of the most recent observation.
+    cardinality: [u8; 2], // Number of slots in use by the ring.
+    cardinality_next: [u8; 2], // Number of slots allocated. The ring grows into them when it wraps.
+    last_slot: [u8; 8], // Slot of the most recent observation.
+    prevailing_price: [u8; 16], // Spot price since the last trade. 1e18 scaled.
+    prevailing_d_per_lp: [u8; 16], // D per LP since the last trade. 1e18 scaled.
+}
+
+#[repr(C)]
+pub struct Observation {
+    initialized: u8, // Whether the slot has ever been written.
+    timestamp: [u8; 8], // Unix timestamp of the observation.
+    cumulative_price: [u8; 16], // Sum of spot price * seconds.
+    cumulative_d_per_lp: [u8; 16], // Sum of D per LP * seconds.
+}
+
+impl Observation {
+    pub const LEN: usize = size_of::<Observation>();
+
+    #[inline(always)]
+    pub fn initialized(&self) -> bool { self.initialized == 1 }
+    #[inline(always)]
+    pub fn timestamp(&self) -> i64 { i64::from_le_bytes(self.timestamp) }
+    #[inline(always)]
+    pub fn cumulative_price(&self) -> u128 { u128::from_le_bytes(self.cumulative_price) }
+    #[inline(always)]
+    pub fn cumulative_d_per_lp(&self) -> u128 { u128::from_le_bytes(self.cumulative_d_per_lp) }
+
+    #[inline(always)]
+    fn set(&mut self, timestamp: i64, cumulative_price: u128, cumulative_d_per_lp: u128) {
+        self.initialized = 1;
+        self.timestamp = timestamp.to_le_bytes();
+        self.cumulative_price = cumulative_price.to_le_bytes();
+        self.cumulative_d_per_lp = cumulative_d_per_lp.to_le_bytes();
+    }
+}
+
+impl ObservationsHeader {
+    pub const LEN: usize = size_of::<ObservationsHeader>();
+
+    #[inline(always)]
+    pub fn config(&self) -> &Address { &self.config }
+    #[inline(always)]
+    pub fn bump(&self) -> [u8; 1] { self.bump }
+    #[inline(always)]
+    pub fn index(&self) -> u16 { u16::from_le_bytes(self.index) }
+    #[inline(always)]
+    pub fn cardinality(&self) -> u16 { u16::from_le_bytes(self.cardinality) }
+    #[inline(always)]
+    pub fn cardinality_next(&self) -> u16 { u16::from_le_bytes(self.cardinality_next) }
+    #[inline(always)]
+    pub fn last_slot(&self) -> u64 { u64::from_le_bytes(self.last_slot) }
+    #[inline(always)]
+    pub fn prevailing_price(&self) -> u128 { u128::from_le_bytes(self.prevailing_price) }
+    #[inline(always)]
+    pub fn prevailing_d_per_lp(&self) -> u128 { u128::from_le_bytes(self.prevailing_d_per_lp) }
+
+    #[inline(always)]
+    pub fn set_inner(&mut self, config: &Address, bump: [u8; 1]) {
+        self.config = config.clone();
+        self.bump = bump;
+        self.index = 0u16.to_le_bytes();
+        self.cardinality = 0u16.to_le_bytes();
+        self.cardinality_next = 1u16.to_le_bytes();
+    }
+
+    #[inline(always)]
+    pub fn set_cardinality_next(&mut self, cardinality_next: u16) {
+        self.cardinality_next = cardinality_next.to_le_bytes();
+    }
+}
+
+pub struct Observations<'a> {
+    pub header: &'a ObservationsHeader,
+    pub entries: &'a [Observation],
+}
+
+pub struct ObservationsMut<'a> {
+    pub header: &'a mut ObservationsHeader,
+    pub entries: &'a mut [Observation],
+}
+
+// Account size for a buffer holding `cardinality` observations.
+#[inline(always)]
+pub const fn observations_len(cardinality: u16) -> usize {
+    ObservationsHeader::LEN + Observation::LEN * cardinality as usize
+}
+
+impl<'a> Observations<'a> {
+    // Reads the buffer from raw account data. Usable off chain for TWAP queries.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self, MegaAmmProgramError> {
+        if data.len() < observations_len(1) {
+            return Err(MegaAmmProgramError::InvalidAccountData);
+        }
+        let header = unsafe { &*(data.as_ptr() as *const ObservationsHeader) };
+        if data.len() != observations_len(header.cardinality_next()) {
+            return Err(MegaAmmProgramError::InvalidAccountData);
+        }
+        let entries = unsafe {
+            core::slice::from_raw_parts(
+                data.as_ptr().add(ObservationsHeader::LEN) as *const Observation,
+                header.cardinality_next() as usize,
+            )
+        };
+        Ok(Self { header, entries })
+    }
+
+    // Cumulative (price, D per LP) at `target`. Interpolates between observations and
+    // extrapolates past the newest one with the prevailing values.
+    pub fn observe(&self, target: i64) -> Result<(u128, u128), MathError> {
+        let cardinality = self.header.cardinality() as usize;
+        if cardinality == 0 {
+            return Err(MathError::ObservationOutOfRange);
+        }
+        let index = self.header.index() as usize;
+        let newest = &self.entries[index];
+        if target >= newest.timestamp() {
+            let elapsed = (target - newest.timestamp()) as u64;
+            return Ok((
+                accumulate(newest.cumulative_price(), self.header.prevailing_price(), elapsed),
+                accumulate(newest.cumulative_d_per_lp(), self.header.prevailing_d_per_lp(), elapsed),
+            ));
+        }
+
+        // Oldest entry is the one after the newest, unless the ring has not wrapped yet.
+        let mut oldest_idx = (index + 1) % cardinality;
+        if !self.entries[oldest_idx].initialized() {
+            oldest_idx = 0;
+        }
+        if target < self.entries[oldest_idx].timestamp() {
+            return Err(MathError::ObservationOutOfRange);
+        }
+
+        // Binary search over the ring ordered from oldest to newest.
+        let len = (index + cardinality - oldest_idx) % cardinality + 1;
+        let (mut low, mut high) = (0usize, len - 1);
+        while high - low > 1 {
+            let mid = (low + high) >> 1;
+            if self.entries[(oldest_idx + mid) % cardinality].timestamp() <= target {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        let before = &self.entries[(oldest_idx + low) % cardinality];
+        let after = &self.entries[(oldest_idx + high) % cardinality];
+        Ok((
+            interpolate_cumulative(
+                before.timestamp(), before.cumulative_price(),
+                after.timestamp(), after.cumulative_price(), target,
+            )?,
+            interpolate_cumulative(
+                before.timestamp(), before.cumulative_d_per_lp(),
+                after.timestamp(), after.cumulative_d_per_lp(), target,
+            )?,
+        ))
+    }
+
+    // Time weighted average (price, D per LP) between two timestamps.
+    pub fn twap(&self, start: i64, end: i64) -> Result<(u128, u128), MathError> {
+        let (start_price, start_d_per_lp) = self.observe(start)?;
+        let (end_price, end_d_per_lp) = self.observe(end)?;
+        Ok((
+            twap_from_cumulatives(start, start_price, end, end_price)?,
+            twap_from_cumulatives(start, start_d_per_lp, end, end_d_per_lp)?,
+        ))
+    }
+}
+
+impl<'a> ObservationsMut<'a> {
+    pub fn from_bytes_mut(data: &'a mut [u8]) -> Result<Self, MegaAmmProgramError> {
+        // Validates the layout before handing out mutable views.
+        Observations::from_bytes(data)?;
+        let entries_len = (data.len() - ObservationsHeader::LEN) / Observation::LEN;
+        let header = unsafe { &mut *(data.as_mut_ptr() as *mut ObservationsHeader) };
+        let entries = unsafe {
+            core::slice::from_raw_parts_mut(
+                data.as_mut_ptr().add(ObservationsHeader::LEN) as *mut Observation,
+                entries_len,
+            )
+        };
+        Ok(Self { header, entries })
+    }
+
+    // Loads the buffer of the given pool.
+    pub fn load_mut(account_info: &'a AccountView, config: &AccountView) -> Result<Self, MegaAmmProgramError> {
+        if !account_info.owned_by(&Address::new_from_array(crate::ID)) {
+            return Err(MegaAmmProgramError::InvalidOwner.into());
+        }
+        let mut data = account_info.try_borrow_mut()?;
+        // Converting RefMut<[u8]> to &mut [u8]
+        let unsafe_data = unsafe {
+            core::slice::from_raw_parts_mut(data.as_mut_ptr(), data.len())
+        };
+        let buffer = Self::from_bytes_mut(unsafe_data)?;
+        if buffer.header.config().ne(config.address()) {
+            return Err(MegaAmmProgramError::InvalidAccountData.into());
+        }
+        Ok(buffer)
+    }
+
+    pub fn as_ref(&self) -> Observations<'_> {
+        Observations { header: self.header, entries: self.entries }
+    }
+
+    // Records the post trade values. A new observation is written at most once per slot,
+    // accumulating the values that prevailed since the previous one.
+    pub fn write(&mut self, timestamp: i64, slot: u64, price: u128, d_per_lp: u128) -> Result<(), MathError> {
+        let cardinality = self.header.cardinality();
+        if cardinality == 0 {
+            self.entries[0].set(timestamp, 0, 0);
+            self.header.cardinality = 1u16.to_le_bytes();
+        } else if slot != self.header.last_slot() {
+            let index = self.header.index() as usize;
+            let last = &self.entries[index];
+            let elapsed = timestamp.saturating_sub(last.timestamp()).max(0) as u64;
+            let cumulative_price = accumulate(last.cumulative_price(), self.header.prevailing_price(), elapsed);
+            let cumulative_d_per_lp = accumulate(last.cumulative_d_per_lp(), self.header.prevailing_d_per_lp(), elapsed);
+
+            // The ring only grows into newly allocated slots once it reaches its end.
+            let mut cardinality = cardinality;
+            if index == (cardinality - 1) as usize && self.header.cardinality_next() > cardinality {
+                cardinality = self.header.cardinality_next();
+                self.header.cardinality = cardinality.to_le_bytes();
+            }
+            let next = (index + 1) % cardinality as usize;
+            self.entries[next].set(timestamp, cumulative_price, cumulative_d_per_lp);
+            self.header.index = (next as u16).to_le_bytes();
+        }
+        self.header.last_slot = slot.to_le_bytes();
+        self.header.prevailing_price = price.to_le_bytes();
+        self.header.prevailing_d_per_lp = d_per_lp.to_le_bytes();
+        Ok(())
+    }
+}
+
+// Feeds the EMA oracle and the observation buffer with the post trade pool state.
+// An emptied pool keeps its last recorded values.
+pub fn update_price_oracles(
+    config: &mut Config, config_account: &AccountView, observations: &AccountView,
+    balances: &[u64], lp_supply: u64,
+) -> ProgramResult {
+    let clock = Clock::get()?;
+    config.update_oracle(balances, clock.unix_timestamp)?;
+    config.update_price_scale(balances, lp_supply)?;
+    if lp_supply == 0 || balances.iter().any(|&balance| balance == 0) {
+        return Ok(());
+    }
+
+    let value_per_lp = config.curve(balances)?.virtual_price(lp_supply)?;
+    let mut buffer = ObservationsMut::load_mut(observations, config_account)?;
+    buffer.write(clock.unix_timestamp, clock.slot, config.last_spot_price(), value_per_lp)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICE: u128 = 1_000_000_000_000_000_000;
+
+    fn buffer_bytes(cardinality_next: u16) -> [u8; observations_len(4)] {
+        let mut data = [0u8; observations_len(4)];
+        let header = unsafe { &mut *(data.as_mut_ptr() as *mut ObservationsHeader) };
+        header.set_inner(&Address::new_from_array([7u8; 32]), [255]);
+        header.set_cardinality_next(cardinality_next);
+        data
+    }
+
+    #[test]
+    fn test_write_once_per_slot_and_twap() {
+        let mut data = buffer_bytes(4);
+        let mut buffer = ObservationsMut::from_bytes_mut(&mut data).ok().unwrap();
+        buffer.write(100, 1, PRICE, PRICE).unwrap();
+        // Same slot only updates the prevailing values.
+        buffer.write(100, 1, 2 * PRICE, PRICE).unwrap();
+        assert_eq!(buffer.header.cardinality(), 1);
+        buffer.write(200, 2, 2 * PRICE, PRICE).unwrap();
+        assert_eq!(buffer.header.cardinality(), 4);
+        assert_eq!(buffer.header.index(), 1);
+
+        let (price, d_per_lp) = buffer.as_ref().twap(100, 300).unwrap();
+        assert_eq!(price, 2 * PRICE);
+        assert_eq!(d_per_lp, PRICE);
+        assert!(buffer.as_ref().twap(50, 300).is_err());
+    }
+
+    #[test]
+    fn test_ring_wraps_and_drops_oldest() {
+        let mut data = buffer_bytes(4);
+        let mut buffer = ObservationsMut::from_bytes_mut(&mut data).ok().unwrap();
+        for step in 0..6u64 {
+            let price = (step as u128 + 1) * PRICE;
+            buffer.write(100 * step as i64, step, price, PRICE).unwrap();
+        }
+        // Newest observation at t=500 overwrote slot 1, oldest kept is t=200.
+        assert_eq!(buffer.header.index(), 1);
+        assert!(buffer.as_ref().observe(150).is_err());
+        // Between t=200 and t=300 the price was 3.
+        let (price, _) = buffer.as_ref().twap(200, 300).unwrap();
+        assert_eq!(price, 3 * PRICE);
+        // Interpolated window between t=250 and t=450 averages 3.5 and 4.5 halves.
+        let (price, _) = buffer.as_ref().twap(250, 450).unwrap();
+        assert_eq!(price, 4 * PRICE);
+    }
+}
@@ -2,12 +2,33 @@
 //! This is the full AMM flow test.
 #![allow(warnings)]
 use proptest::prelude::*;
+use solana_sdk::{sysvar::clock::Clock, signature::{Keypair, Signer}, pubkey::Pubkey};
 mod common;
+use common::litesvm_setup::{get_token_balance, mint_tokens, create_ata, wrap_sol};
+use common::litesvm_quote_tests::{quote_swap, quote_swap_error, quote_deposit, quote_deposit_error, quote_withdraw, solver_diagnostics,
+};
 use common::litesvm_deposit_tests::deposit_liquidity;
-use common::litesvm_withdraw_tests::withdraw_liquidity;
-use common::litesvm_setup::setup_initialized_amm;
+use common::litesvm_withdraw_tests::{withdraw_liquidity, withdraw_lp, withdraw_exact_x, withdraw_lp_native_sol};
+use common::litesvm_event_tests::swap_events;
+use common::litesvm_reserve_tests::{skim, sync, donate};
+use common::litesvm_flash_tests::{flash_loan, flash_loan_calling_the_pool, flash_swap, flash_swap_calling_the_pool};
+use megaswap_protocol::events::MegaAmmEvent;
+use megaswap_protocol::config::Config;
+use megaswap_protocol::helpers::errors::MegaAmmProgramError;
+use megaswap_protocol::helpers::math_procs::curve::MINIMUM_LIQUIDITY;
+use spl_associated_token_account::get_associated_token_address;
+use common::context::AmmTestContext;
+use common::litesvm_setup::{
+    setup_initialized_amm, setup_initialized_amm_with_curve, setup_initialized_weighted_amm,
+    setup_initialized_token_2022_amm, setup_initialized_token_2022_amm_with, MintExtension,
+    TOKEN_2022_PROGRAM_ID, TRANSFER_HOOK_PROGRAM_ID, transfer_hook_count,
+    setup_initialized_native_sol_amm,
+};
 use common::litesvm_swap_tests::{
-    normal_swap, zero_amount_swap, slippage_protected_swap,
+    normal_swap, zero_amount_swap, slippage_protected_swap, native_sol_swap,
+};
+use common::litesvm_oracle_tests::{
+    read_oracle_price, advance_clock, grow_observations, read_twap, read_virtual_price,
 };
 
 #[test]
@@ -39,6 +60,33 @@ fn test_withdrawing_liquidity() {
     withdraw_liquidity(&mut ctx_1, &deposit_ctx);
 }
 
+#[test]
+fn test_oracle_tracks_marginal_price() {
+    let mut ctx = setup_initialized_amm();
+    // No trade has priced the pool yet.
+    assert!(read_oracle_price(&mut ctx).is_none());
+
+    // Balanced deposit prices x at 1:1 and seeds the EMA.
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let seeded = read_oracle_price(&mut ctx).unwrap();
+    assert_eq!(seeded.spot_price, 1_000_000_000_000_000_000);
+    assert_eq!(seeded.ema_price, seeded.spot_price);
+
+    // Selling x makes it cheaper. The EMA only follows once time passes.
+    advance_clock(&mut ctx.svm, 1);
+    normal_swap(&mut ctx, 200_000, 1, 1);
+    let after_swap = read_oracle_price(&mut ctx).unwrap();
+    assert!(after_swap.spot_price < seeded.spot_price);
+    assert!(after_swap.ema_price <= seeded.ema_price);
+
+    // After one default half-life the EMA covers half the distance to the spot.
+    advance_clock(&mut ctx.svm, 600);
+    let decayed = read_oracle_price(&mut ctx).unwrap();
+    let midpoint = (seeded.spot_price + after_swap.spot_price) / 2;
+    let diff = if decayed.ema_price > midpoint { decayed.ema_price - midpoint } else { midpoint - decayed.ema_price };
+    assert!(diff < 1_000_000_000_000);
+}
+
 // ================ PROPERTY TESTS ===============================
 proptest! {
     #[test]
@@ -155,3 +203,693 @@ proptest! {
         normal_swap(&mut ctx, swap_amount, 9_800, if swap_x { 1 } else { 0 });
     }
 }
+
+#[test]
+fn test_observations_twap() {
+    let mut ctx = setup_initialized_amm();
+    assert!(grow_observations(&mut ctx, 8));
+    // Shrinking or keeping the size is rejected.
+    assert!(!grow_observations(&mut ctx, 8));
+
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let start: Clock = ctx.svm.get_sysvar();
+    advance_clock(&mut ctx.svm, 100);
+    normal_swap(&mut ctx, 200_000, 1, 1);
+    let after_swap = read_oracle_price(&mut ctx).unwrap();
+    advance_clock(&mut ctx.svm, 100);
+
+    // Balanced price for the first 100 seconds, post swap price for the next 100.
+    let (twap, d_per_lp) = read_twap(&ctx, start.unix_timestamp, start.unix_timestamp + 200).unwrap();
+    let expected = (1_000_000_000_000_000_000 + after_swap.spot_price) / 2;
+    let diff = if twap > expected { twap - expected } else { expected - twap };
+    assert!(diff < 1_000_000_000_000);
+    // Fees only ever grow the value backing an LP token.
+    assert!(d_per_lp >= 1_000_000_000_000_000_000);
+    // Nothing was observed before the pool was funded.
+    assert!(read_twap(&ctx, start.unix_timestamp - 10, start.unix_timestamp).is_none());
+}
+
+#[test]
+fn test_virtual_price_grows_with_fees() {
+    let mut ctx = setup_initialized_amm();
+    // Nothing to value before the first deposit.
+    assert!(read_virtual_price(&mut ctx).is_none());
+
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let genesis = read_virtual_price(&mut ctx).unwrap();
+    assert_eq!(genesis, 1_000_000_000_000_000_000);
+
+    // Swap fees stay in the pool and raise the value of every LP token.
+    normal_swap(&mut ctx, 200_000, 1, 1);
+    let after_swap = read_virtual_price(&mut ctx).unwrap();
+    assert!(after_swap > genesis);
+}
+
+#[test]
+fn test_quotes_match_execution() {
+    let mut ctx = setup_initialized_amm();
+    // Nothing to quote against before the first deposit.
+    assert!(quote_swap(&mut ctx, 10_000, 1).is_none());
+
+    // Imbalanced pool, so quoting the wrong direction would show.
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 3_000_000);
+
+    let lp_quote = quote_deposit(&mut ctx, 100_000, 300_000).unwrap();
+    let deposit = deposit_liquidity(&mut ctx, 100_000, 300_000);
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_lp_ata), lp_quote);
+
+    for is_x in [1u8, 0u8] {
+        let (amount_in, amount_out, fee) = quote_swap(&mut ctx, 50_000, is_x).unwrap();
+        assert_eq!(amount_in, 50_000);
+        assert!(fee > 0);
+        let out_vault = if is_x == 1 { ctx.vault_y_ata } else { ctx.vault_x_ata };
+        let before = get_token_balance(&ctx.svm, &out_vault);
+        normal_swap(&mut ctx, 50_000, 1, is_x);
+        assert_eq!(before - get_token_balance(&ctx.svm, &out_vault), amount_out);
+    }
+
+    // Withdraw helper burns 20_000 LP in balanced mode.
+    let (x_out, y_out, lp_burned) = quote_withdraw(&mut ctx, 20_000, 0, 0, 0).unwrap();
+    assert_eq!(lp_burned, 20_000);
+    let x_before = get_token_balance(&ctx.svm, &ctx.vault_x_ata);
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    withdraw_liquidity(&mut ctx, &deposit);
+    assert_eq!(x_before - get_token_balance(&ctx.svm, &ctx.vault_x_ata), x_out);
+    assert_eq!(y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata), y_out);
+
+    // Single sided exits must name exactly one token.
+    assert!(quote_withdraw(&mut ctx, 0, 10_000, 0, 1).unwrap().0 > 0);
+    assert!(quote_withdraw(&mut ctx, 0, 10_000, 10_000, 1).is_none());
+}
+
+#[test]
+fn test_solver_diagnostics_and_non_convergence() {
+    let mut ctx = setup_initialized_amm();
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 3_000_000);
+
+    let (d, y) = solver_diagnostics(&mut ctx, 0, 1).unwrap();
+    assert!(d.3 && d.1 > 0 && d.2 <= 1);
+    assert!(y.is_none());
+    let (_, y) = solver_diagnostics(&mut ctx, 50_000, 1).unwrap();
+    assert!(y.unwrap().3);
+
+    // Capping the pool's solver at one iteration, the solver fields are the iteration
+    // limit (u16) and the tolerance (u64).
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    let offset = SOLVER_OFFSET;
+    config.data[offset..offset + 2].copy_from_slice(&1u16.to_le_bytes());
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+
+    // Diagnostics still report the failed solve, trades are rejected.
+    let (d, _) = solver_diagnostics(&mut ctx, 0, 1).unwrap();
+    assert!(!d.3);
+    assert_eq!(d.1, 1);
+    assert!(quote_swap(&mut ctx, 50_000, 1).is_none());
+    assert!(normal_swap(&mut ctx, 50_000, 1, 1).is_empty());
+}
+
+// Config layout from its end: the transfer hook allowlist (two addresses), the solver
+// fields (10 bytes), and ahead of them the trade guards, then the outflow limiter (window
+// u64, max outflow of x and y u64, window start i64, current and previous net outflows of
+// x and y i64).
+const TRANSFER_HOOK_PROGRAMS_OFFSET: usize = Config::LEN - 64;
+const SOLVER_OFFSET: usize = TRANSFER_HOOK_PROGRAMS_OFFSET - 10;
+const OUTFLOW_LIMITER_OFFSET: usize = SOLVER_OFFSET - 64;
+const TRADE_GUARDS_OFFSET: usize = OUTFLOW_LIMITER_OFFSET - 12;
+
+// Writes the trade guards straight into the config account: max trade (u16 bps),
+// min reserve (u64), max price impact (u16 bps).
+fn set_trade_guards(ctx: &mut AmmTestContext, max_trade_bps: u16, min_reserve: u64, max_price_impact_bps: u16) {
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    let offset = TRADE_GUARDS_OFFSET;
+    config.data[offset..offset + 2].copy_from_slice(&max_trade_bps.to_le_bytes());
+    config.data[offset + 2..offset + 10].copy_from_slice(&min_reserve.to_le_bytes());
+    config.data[offset + 10..offset + 12].copy_from_slice(&max_price_impact_bps.to_le_bytes());
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+}
+
+#[test]
+fn test_trade_guards() {
+    let mut ctx = setup_initialized_amm();
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+
+    // At most 10% of the output reserve per swap.
+    set_trade_guards(&mut ctx, 1_000, 0, 0);
+    assert!(quote_swap(&mut ctx, 50_000, 1).is_some());
+    assert_eq!(quote_swap_error(&mut ctx, 200_000, 1), Some(MegaAmmProgramError::TradeTooLarge as u32));
+    assert!(normal_swap(&mut ctx, 200_000, 1, 1).is_empty());
+
+    // The output reserve can't drop below 960_000.
+    set_trade_guards(&mut ctx, 0, 960_000, 0);
+    assert!(quote_swap(&mut ctx, 10_000, 1).is_some());
+    assert_eq!(quote_swap_error(&mut ctx, 50_000, 1), Some(MegaAmmProgramError::ReserveBelowFloor as u32));
+
+    // The marginal price can't move by more than 0.1%.
+    set_trade_guards(&mut ctx, 0, 0, 10);
+    assert!(quote_swap(&mut ctx, 1_000, 1).is_some());
+    assert_eq!(quote_swap_error(&mut ctx, 300_000, 0), Some(MegaAmmProgramError::PriceImpactTooHigh as u32));
+    assert!(normal_swap(&mut ctx, 300_000, 1, 0).is_empty());
+
+    // Disabled guards let the same trades through.
+    set_trade_guards(&mut ctx, 0, 0, 0);
+    assert!(!normal_swap(&mut ctx, 200_000, 1, 1).is_empty());
+}
+
+// Writes the outflow limiter's window and limits straight into the config account.
+fn set_outflow_limit(ctx: &mut AmmTestContext, window: u64, max_outflow_x: u64, max_outflow_y: u64) {
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    let offset = OUTFLOW_LIMITER_OFFSET;
+    config.data[offset..offset + 8].copy_from_slice(&window.to_le_bytes());
+    config.data[offset + 8..offset + 16].copy_from_slice(&max_outflow_x.to_le_bytes());
+    config.data[offset + 16..offset + 24].copy_from_slice(&max_outflow_y.to_le_bytes());
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+}
+
+#[test]
+fn test_outflow_limiter() {
+    let mut ctx = setup_initialized_amm();
+    let deposit_ctx = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+
+    // At most 100_000 y out of the pool per hour, net of what comes in.
+    set_outflow_limit(&mut ctx, 3_600, 0, 100_000);
+    assert!(!normal_swap(&mut ctx, 60_000, 1, 1).is_empty());
+    assert!(normal_swap(&mut ctx, 60_000, 1, 1).is_empty());
+    // Swapping y in makes room again.
+    assert!(!normal_swap(&mut ctx, 50_000, 1, 0).is_empty());
+    assert!(!normal_swap(&mut ctx, 60_000, 1, 1).is_empty());
+    // The window rolls on.
+    advance_clock(&mut ctx.svm, 2 * 3_600);
+    assert!(!normal_swap(&mut ctx, 90_000, 1, 1).is_empty());
+
+    // Withdrawals count too, unless the pool is winding down and LPs exit balanced.
+    set_outflow_limit(&mut ctx, 3_600, 1_000, 1_000);
+    assert!(withdraw_liquidity(&mut ctx, &deposit_ctx).is_empty());
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    config.data[0] = 3; // WithdrawOnly.
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+    assert!(!withdraw_liquidity(&mut ctx, &deposit_ctx).is_empty());
+    assert!(normal_swap(&mut ctx, 1_000, 1, 1).is_empty());
+}
+
+#[test]
+fn test_locked_pool_rejects_every_instruction() {
+    let mut ctx = setup_initialized_amm();
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    // The lock is released once the instruction settles.
+    assert!(!normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert_eq!(ctx.svm.get_account(&ctx.config_pda).unwrap().data[1], 0);
+
+    // Pool as a re-entering CPI would find it mid-trade, the lock byte follows the state.
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    config.data[1] = 1;
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+    assert_eq!(quote_swap_error(&mut ctx, 10_000, 1), Some(MegaAmmProgramError::PoolLocked as u32));
+    assert!(read_oracle_price(&mut ctx).is_none());
+    assert!(read_virtual_price(&mut ctx).is_none());
+    assert!(normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert!(!grow_observations(&mut ctx, 8));
+
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    config.data[1] = 0;
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+    assert!(quote_swap(&mut ctx, 10_000, 1).is_some());
+    assert!(!normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+}
+
+#[test]
+fn test_first_deposit_locks_minimum_liquidity() {
+    let mut ctx = setup_initialized_amm();
+    // A dust first deposit can't set the value of an LP token.
+    assert!(deposit_liquidity(&mut ctx, 10_000, 10_000).return_data.is_empty());
+    assert_eq!(
+        quote_deposit_error(&mut ctx, 10_000, 10_000),
+        Some(MegaAmmProgramError::InitialLiquidityTooLow as u32),
+    );
+
+    // The first depositor gets D minus the locked liquidity, held by the pool's own LP account.
+    let lp_quote = quote_deposit(&mut ctx, 1_000_000, 1_000_000).unwrap();
+    let first = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let locked_lp_ata = get_associated_token_address(&ctx.config_pda, &ctx.lp_mint_pda);
+    assert_eq!(get_token_balance(&ctx.svm, &first.user_lp_ata), lp_quote);
+    assert_eq!(get_token_balance(&ctx.svm, &locked_lp_ata), MINIMUM_LIQUIDITY);
+
+    // Later deposits lock nothing, and the pool can't be emptied of its locked share.
+    let second_quote = quote_deposit(&mut ctx, 1_000_000, 1_000_000).unwrap();
+    assert!(second_quote > lp_quote);
+    let second = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert_eq!(get_token_balance(&ctx.svm, &second.user_lp_ata), second_quote);
+    assert_eq!(get_token_balance(&ctx.svm, &locked_lp_ata), MINIMUM_LIQUIDITY);
+    assert!(!withdraw_lp(&mut ctx, &first, lp_quote).is_empty());
+    assert!(!withdraw_lp(&mut ctx, &second, second_quote).is_empty());
+    assert!(get_token_balance(&ctx.svm, &ctx.vault_x_ata) > 0);
+    assert!(get_token_balance(&ctx.svm, &ctx.vault_y_ata) > 0);
+}
+
+#[test]
+fn test_donation_inflation_attack_is_unprofitable() {
+    let read_u64 = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let mut ctx = setup_initialized_amm();
+
+    // The attacker opens the pool at the smallest size allowed and keeps a single LP token.
+    let attacker = deposit_liquidity(&mut ctx, 60_000, 60_000);
+    let attacker_lp = get_token_balance(&ctx.svm, &attacker.user_lp_ata);
+    let exit = withdraw_lp(&mut ctx, &attacker, attacker_lp - 1);
+    let mut attacker_out = read_u64(&exit, 0) + read_u64(&exit, 8);
+
+    // Then donates straight into the vaults to inflate the value of an LP token, hoping the
+    // next deposit rounds down to nothing.
+    let donation = 10_000_000;
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &ctx.vault_x_ata, donation);
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_y, &ctx.vault_y_ata, donation);
+
+    // The victim still gets LP tokens worth their deposit, give or take one LP of rounding.
+    let victim = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let victim_lp = get_token_balance(&ctx.svm, &victim.user_lp_ata);
+    assert!(victim_lp > 0);
+    let victim_exit = withdraw_lp(&mut ctx, &victim, victim_lp);
+    let victim_out = read_u64(&victim_exit, 0) + read_u64(&victim_exit, 8);
+    assert!(victim_out >= 2_000_000 * 99 / 100);
+
+    // The donation was never priced, and whatever the pool would have priced went to the
+    // locked liquidity. The attacker loses it.
+    let exit = withdraw_lp(&mut ctx, &attacker, 1);
+    attacker_out += read_u64(&exit, 0) + read_u64(&exit, 8);
+    let attacker_in = 2 * 60_000 + 2 * donation;
+    assert!(attacker_out < attacker_in / 100);
+}
+
+#[test]
+fn test_pool_prices_off_tracked_reserves() {
+    let mut ctx = setup_initialized_amm();
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let quote_before = quote_swap(&mut ctx, 10_000, 1).unwrap();
+    let virtual_price_before = read_virtual_price(&mut ctx).unwrap();
+
+    // Tokens sent straight to a vault don't move the curve.
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &ctx.vault_x_ata, 500_000);
+    assert_eq!(quote_swap(&mut ctx, 10_000, 1).unwrap(), quote_before);
+    assert_eq!(read_virtual_price(&mut ctx).unwrap(), virtual_price_before);
+
+    // Anyone can skim them.
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_x_ata), 500_000);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_000_000);
+
+    // Only the authority can adopt them as reserves instead, the LPs then own them.
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_y, &ctx.vault_y_ata, 100_000);
+    assert!(!sync(&mut ctx, &Keypair::new()));
+    let authority = ctx.initializer.insecure_clone();
+    assert!(sync(&mut ctx, &authority));
+    assert!(read_virtual_price(&mut ctx).unwrap() > virtual_price_before);
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_y_ata), 0);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 1_100_000);
+}
+
+#[test]
+fn test_donations_accrue_to_lps() {
+    let mut ctx = setup_initialized_amm();
+    // Nobody to donate to before the first deposit.
+    assert!(!donate(&mut ctx, 1_000, 1_000));
+
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 3_000_000);
+    let lp_supply = get_token_balance(&ctx.svm, &deposit.user_lp_ata) + MINIMUM_LIQUIDITY;
+    let virtual_price_before = read_virtual_price(&mut ctx).unwrap();
+    let spot_before = read_oracle_price(&mut ctx).unwrap().spot_price;
+
+    // Donations off the reserve ratio would move the price.
+    assert!(!donate(&mut ctx, 100_000, 100_000));
+    assert!(!donate(&mut ctx, 100_000, 0));
+
+    // A balanced donation grows the value of every LP token, not the supply nor the price.
+    assert!(donate(&mut ctx, 100_000, 300_000));
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_100_000);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 3_300_000);
+    assert!(read_virtual_price(&mut ctx).unwrap() > virtual_price_before);
+    let spot_after = read_oracle_price(&mut ctx).unwrap().spot_price;
+    assert!(spot_after.abs_diff(spot_before) <= spot_before / 100_000);
+    let (x_out, y_out, _) = quote_withdraw(&mut ctx, lp_supply - MINIMUM_LIQUIDITY, 0, 0, 0).unwrap();
+    assert!(x_out > 1_000_000 && y_out > 3_000_000);
+}
+
+#[test]
+fn test_flash_loans_pay_a_fee_to_lps() {
+    let mut ctx = setup_initialized_amm();
+    // Nothing to lend before the first deposit.
+    assert!(flash_loan(&mut ctx, 1_000, 0, 1_001, 0).is_err());
+
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let virtual_price_before = read_virtual_price(&mut ctx).unwrap();
+    assert_eq!(
+        flash_loan(&mut ctx, 1_000_001, 0, 1_000_201, 0),
+        Err(Some(MegaAmmProgramError::InsufficientLiquidity as u32)),
+    );
+
+    // The pool's 2 bps on each amount, 20 on 100_000 and 10 on 50_000. One unit short on
+    // either side and the whole loan reverts.
+    let not_repaid = Err(Some(MegaAmmProgramError::FlashLoanNotRepaid as u32));
+    assert_eq!(flash_loan(&mut ctx, 100_000, 50_000, 100_019, 50_010), not_repaid);
+    assert_eq!(flash_loan(&mut ctx, 100_000, 50_000, 100_020, 50_009), not_repaid);
+    assert_eq!(flash_loan(&mut ctx, 100_000, 0, 0, 0), not_repaid);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_000_000);
+
+    // Repaid with the fees, which join the reserves: nothing to skim and every LP token is worth more.
+    assert_eq!(flash_loan(&mut ctx, 100_000, 50_000, 100_020, 50_010), Ok(()));
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_000_020);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 1_000_010);
+    assert!(read_virtual_price(&mut ctx).unwrap() > virtual_price_before);
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_x_ata), 0);
+
+    // Anything repaid above the fee is left in the vaults for Skim or Sync.
+    assert_eq!(flash_loan(&mut ctx, 100_000, 0, 100_025, 0), Ok(()));
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_x_ata), 5);
+
+    // The pool can't be its own borrower.
+    assert_eq!(flash_loan_calling_the_pool(&mut ctx, 100_000), Err(Some(MegaAmmProgramError::IncorrectProgramId as u32)));
+    assert_eq!(ctx.svm.get_account(&ctx.config_pda).unwrap().data[1], 0);
+
+    // Only an initialized pool lends.
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    config.data[0] = 3; // WithdrawOnly.
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+    assert_eq!(
+        flash_loan(&mut ctx, 100_000, 0, 100_020, 0),
+        Err(Some(MegaAmmProgramError::Unauthorized as u32)),
+    );
+}
+
+#[test]
+fn test_flash_swaps_pay_the_input_in_the_callback() {
+    let mut ctx = setup_initialized_amm();
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let (_, amount_out, _) = quote_swap(&mut ctx, 10_000, 1).unwrap();
+
+    // The output is sent before the callback, underpaying the input lowers D and reverts it all.
+    let invariant_decreased = Err(Some(MegaAmmProgramError::InvariantDecreased as u32));
+    assert_eq!(flash_swap(&mut ctx, 10_000, 1, 0), invariant_decreased);
+    assert_eq!(flash_swap(&mut ctx, 10_000, 1, 9_000), invariant_decreased);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 1_000_000);
+
+    // Paid in full, the trader receives what a regular swap would have paid.
+    assert_eq!(flash_swap(&mut ctx, 10_000, 1, 10_000), Ok(amount_out));
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_010_000);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 1_000_000 - amount_out);
+    // The reserves follow the vaults, nothing is left to skim.
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_x_ata), 0);
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_y_ata), 0);
+
+    // Either direction works.
+    let (_, amount_out, _) = quote_swap(&mut ctx, 5_000, 0).unwrap();
+    assert_eq!(flash_swap(&mut ctx, 5_000, 0, 5_000), Ok(amount_out));
+
+    // The pool can't be its own callback.
+    assert_eq!(flash_swap_calling_the_pool(&mut ctx, 10_000), Err(Some(MegaAmmProgramError::IncorrectProgramId as u32)));
+    assert_eq!(ctx.svm.get_account(&ctx.config_pda).unwrap().data[1], 0);
+
+    // On transfer hook mints the pool's transfers get the hook accounts, the callback its own.
+    let mut ctx = setup_initialized_token_2022_amm_with(&[
+        MintExtension::TransferHook { program_id: TRANSFER_HOOK_PROGRAM_ID },
+    ]);
+    deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    let hooks_run = transfer_hook_count(&ctx);
+    let (_, amount_out, _) = quote_swap(&mut ctx, 10_000, 1).unwrap();
+    assert_eq!(flash_swap(&mut ctx, 10_000, 1, 10_000), Ok(amount_out));
+    assert_eq!(transfer_hook_count(&ctx), hooks_run + 2);
+}
+
+#[test]
+fn test_token_2022_pool() {
+    let mut ctx = setup_initialized_token_2022_amm();
+    // Vaults and LP mint belong to token-2022, like the pool's mints.
+    for account in [ctx.vault_x_ata, ctx.vault_y_ata, ctx.lp_mint_pda] {
+        assert_eq!(ctx.svm.get_account(&account).unwrap().owner, TOKEN_2022_PROGRAM_ID);
+    }
+
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert!(!deposit.return_data.is_empty());
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_000_000);
+    assert!(donate(&mut ctx, 10_000, 10_000));
+
+    let (_, amount_out, _) = quote_swap(&mut ctx, 10_000, 1).unwrap();
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    assert!(!normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert_eq!(y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata), amount_out);
+
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &ctx.vault_x_ata, 5_000);
+    assert!(skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+    assert_eq!(get_token_balance(&ctx.svm, &deposit.user_x_ata), 5_000);
+    assert!(!withdraw_liquidity(&mut ctx, &deposit).is_empty());
+
+    // The token program passed must be the one owning the mints.
+    ctx.token_program = spl_token::ID;
+    assert!(normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert!(!skim(&mut ctx, &deposit.user_x_ata, &deposit.user_y_ata));
+}
+
+#[test]
+fn test_transfer_fee_mints_price_on_net_amounts() {
+    let read_u64 = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    // Mints the pool can't work with are refused, the pool is never created.
+    let ctx = setup_initialized_token_2022_amm_with(&[MintExtension::NonTransferable]);
+    assert!(ctx.svm.get_account(&ctx.config_pda).is_none());
+
+    // 1% transfer fee on both tokens.
+    let mut ctx = setup_initialized_token_2022_amm_with(&[
+        MintExtension::TransferFee { basis_points: 100, maximum_fee: u64::MAX },
+    ]);
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 990_000);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_y_ata), 990_000);
+    // LP tokens were minted for what reached the vaults, a fee free pool of 990_000 each.
+    let mut fee_free = setup_initialized_token_2022_amm();
+    let fee_free_deposit = deposit_liquidity(&mut fee_free, 990_000, 990_000);
+    assert_eq!(
+        get_token_balance(&ctx.svm, &deposit.user_lp_ata),
+        get_token_balance(&fee_free.svm, &fee_free_deposit.user_lp_ata),
+    );
+
+    // Swaps price the 9_900 x reaching the vault.
+    let (_, amount_out, _) = quote_swap(&mut ctx, 9_900, 1).unwrap();
+    let x_before = get_token_balance(&ctx.svm, &ctx.vault_x_ata);
+    let swap = normal_swap(&mut ctx, 10_000, 1, 1);
+    assert_eq!(read_u64(&swap, 0), 9_900);
+    assert_eq!(read_u64(&swap, 8), amount_out);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata) - x_before, 9_900);
+
+    // The trader's minimum is held against what they receive once the fee on y is withheld.
+    let (_, amount_out, _) = quote_swap(&mut ctx, 9_900, 1).unwrap();
+    let received = amount_out - (amount_out * 100).div_ceil(10_000);
+    assert!(normal_swap(&mut ctx, 10_000, received + 1, 1).is_empty());
+    assert!(!normal_swap(&mut ctx, 10_000, received, 1).is_empty());
+
+    // Asking for an exact amount out grosses it up, the user receives it less the pool's 2 bps fee.
+    let user_x_before = get_token_balance(&ctx.svm, &deposit.user_x_ata);
+    let withdraw = withdraw_exact_x(&mut ctx, &deposit, 10_000);
+    let amount_out = read_u64(&withdraw, 0);
+    let received = get_token_balance(&ctx.svm, &deposit.user_x_ata) - user_x_before;
+    assert!(amount_out > 10_000);
+    assert_eq!(received, amount_out - (amount_out * 100).div_ceil(10_000));
+    assert!((9_997..=10_000).contains(&received));
+}
+
+#[test]
+fn test_transfer_hook_mints_run_the_allowlisted_hook() {
+    let mut ctx = setup_initialized_token_2022_amm_with(&[
+        MintExtension::TransferHook { program_id: TRANSFER_HOOK_PROGRAM_ID },
+    ]);
+    // The hook the mints run when the pool is created is allowlisted.
+    let config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    let allowlist = &config.data[TRANSFER_HOOK_PROGRAMS_OFFSET..];
+    assert_eq!(&allowlist[..32], TRANSFER_HOOK_PROGRAM_ID.as_ref());
+    assert_eq!(&allowlist[32..], TRANSFER_HOOK_PROGRAM_ID.as_ref());
+
+    // Every transfer in or out of the vaults runs the hook with the accounts the pool forwards.
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert!(!deposit.return_data.is_empty());
+    assert_eq!(transfer_hook_count(&ctx), 2);
+    assert!(!normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert_eq!(transfer_hook_count(&ctx), 4);
+    assert!(!withdraw_lp(&mut ctx, &deposit, 1_000).is_empty());
+    assert_eq!(transfer_hook_count(&ctx), 6);
+    // Later deposits leave out the pool's LP account, the hook accounts come first.
+    assert!(!deposit_liquidity(&mut ctx, 1_000_000, 1_000_000).return_data.is_empty());
+    assert_eq!(transfer_hook_count(&ctx), 8);
+
+    // Token-2022 can't run the hook without its accounts, the transfer fails.
+    let transfer_hook_accounts = std::mem::take(&mut ctx.transfer_hook_accounts);
+    assert!(normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    ctx.transfer_hook_accounts = transfer_hook_accounts;
+
+    // A hook program the pool didn't allowlist is never invoked.
+    let mut config = ctx.svm.get_account(&ctx.config_pda).unwrap();
+    config.data[TRANSFER_HOOK_PROGRAMS_OFFSET..].fill(0);
+    ctx.svm.set_account(ctx.config_pda, config).unwrap();
+    assert!(normal_swap(&mut ctx, 10_000, 1, 1).is_empty());
+    assert!(withdraw_lp(&mut ctx, &deposit, 1_000).is_empty());
+    assert_eq!(transfer_hook_count(&ctx), 8);
+}
+
+#[test]
+fn test_native_sol_is_wrapped_and_unwrapped() {
+    let read_u64 = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let sol = |ctx: &AmmTestContext, address: &Pubkey| ctx.svm.get_account(address).map_or(0, |account| account.lamports);
+    // Signature fee of every transaction.
+    let tx_fee = 5_000;
+    let mut ctx = setup_initialized_native_sol_amm();
+
+    // The x side is deposited from the user's SOL, the temporary WSOL account is closed afterwards.
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert!(!deposit.return_data.is_empty());
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata), 1_000_000);
+    assert_eq!(sol(&ctx, &deposit.user_x_ata), 0);
+
+    // SOL in: the trader pays exactly the amount swapped.
+    let x_before = get_token_balance(&ctx.svm, &ctx.vault_x_ata);
+    let (sol_change, swap) = native_sol_swap(&mut ctx, 10_000, 1);
+    assert_eq!(read_u64(&swap, 0), 10_000);
+    assert_eq!(sol_change, -10_000 - tx_fee);
+    assert_eq!(get_token_balance(&ctx.svm, &ctx.vault_x_ata) - x_before, 10_000);
+
+    // SOL out: the trader receives the amount out as lamports.
+    let (sol_change, swap) = native_sol_swap(&mut ctx, 10_000, 0);
+    assert_eq!(sol_change, read_u64(&swap, 8) as i64 - tx_fee);
+
+    // Withdrawing pays the x side out in SOL, a WSOL account the user already holds is left alone.
+    let user = deposit.user.pubkey();
+    let user_wsol = create_ata(&mut ctx.svm, &deposit.user, &ctx.mint_x, &user);
+    wrap_sol(&mut ctx.svm, &deposit.user, &user_wsol, 50_000);
+    let sol_before = sol(&ctx, &user);
+    let withdraw = withdraw_lp_native_sol(&mut ctx, &deposit, 1_000);
+    assert!(!withdraw.is_empty());
+    assert_eq!(sol(&ctx, &deposit.user_x_ata), 0);
+    // The temporary WSOL account the pool paid into was created and closed within the withdrawal.
+    assert_eq!(sol(&ctx, &user), sol_before + read_u64(&withdraw, 0) - tx_fee as u64);
+    assert_eq!(get_token_balance(&ctx.svm, &user_wsol), 50_000);
+
+    // Native SOL settlement needs the native mint on one side of the pool.
+    let mut ctx = setup_initialized_amm();
+    deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert!(native_sol_swap(&mut ctx, 10_000, 1).1.is_empty());
+}
+
+#[test]
+fn test_constant_product_pool() {
+    // Unknown curves are rejected, the pool is never created.
+    let ctx = setup_initialized_amm_with_curve(7);
+    assert!(ctx.svm.get_account(&ctx.config_pda).is_none());
+
+    let mut ctx = setup_initialized_amm_with_curve(1);
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 4_000_000);
+
+    // x * y = k, not the stableswap's near 1:1 quote: 250_000 x buys 800_000 y before the fee.
+    let (_, amount_out, fee) = quote_swap(&mut ctx, 250_000, 1).unwrap();
+    assert_eq!(amount_out + fee, 800_000);
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    normal_swap(&mut ctx, 250_000, 1, 1);
+    assert_eq!(y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata), amount_out);
+
+    // Same vaults, LP mint and withdrawal paths as the stableswap pools.
+    let (x_out, y_out, lp_burned) = quote_withdraw(&mut ctx, 20_000, 0, 0, 0).unwrap();
+    assert_eq!(lp_burned, 20_000);
+    assert!(x_out > 0 && y_out > 0);
+    assert!(quote_withdraw(&mut ctx, 0, 10_000, 0, 1).unwrap().0 > 0);
+    let (d, _) = solver_diagnostics(&mut ctx, 0, 1).unwrap();
+    assert!(d.3 && d.1 == 0);
+}
+
+#[test]
+fn test_weighted_pool() {
+    // Weights outside [1%, 99%] are rejected, the pool is never created.
+    let ctx = setup_initialized_weighted_amm(0);
+    assert!(ctx.svm.get_account(&ctx.config_pda).is_none());
+    let ctx = setup_initialized_weighted_amm(995_000_000_000_000_000);
+    assert!(ctx.svm.get_account(&ctx.config_pda).is_none());
+
+    // 80/20 pool, priced 1:1 at 4x for 1y.
+    let mut ctx = setup_initialized_weighted_amm(800_000_000_000_000_000);
+    let _ = deposit_liquidity(&mut ctx, 4_000_000, 1_000_000);
+
+    // y' = 1e6 * (4 / 5)^4 = 409_600, rounded towards the pool.
+    let (_, amount_out, fee) = quote_swap(&mut ctx, 1_000_000, 1).unwrap();
+    assert_eq!(amount_out + fee, 590_399);
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    normal_swap(&mut ctx, 1_000_000, 1, 1);
+    assert_eq!(y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata), amount_out);
+
+    let (x_out, y_out, lp_burned) = quote_withdraw(&mut ctx, 20_000, 0, 0, 0).unwrap();
+    assert_eq!(lp_burned, 20_000);
+    assert!(x_out > 0 && y_out > 0);
+    assert!(quote_withdraw(&mut ctx, 0, 10_000, 0, 1).unwrap().0 > 0);
+    let (d, y) = solver_diagnostics(&mut ctx, 10_000, 1).unwrap();
+    assert!(d.3 && d.1 == 0);
+    assert!(y.unwrap().3);
+}
+
+#[test]
+fn test_cryptoswap_pool() {
+    // Pool at 2 y for 1 x, the first deposit sets the price scale.
+    let mut ctx = setup_initialized_amm_with_curve(3);
+    let _ = deposit_liquidity(&mut ctx, 1_000_000, 2_000_000);
+
+    // Near the price scale the curve is flat, 10_000 x buys just under 20_000 y before the fee.
+    let (_, amount_out, fee) = quote_swap(&mut ctx, 10_000, 1).unwrap();
+    assert!(amount_out + fee < 20_000 && amount_out + fee > 19_980);
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    normal_swap(&mut ctx, 10_000, 1, 1);
+    assert_eq!(y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata), amount_out);
+
+    let (x_out, y_out, lp_burned) = quote_withdraw(&mut ctx, 20_000, 0, 0, 0).unwrap();
+    assert_eq!(lp_burned, 20_000);
+    assert!(x_out > 0 && y_out > 0);
+    assert!(quote_withdraw(&mut ctx, 0, 10_000, 0, 1).unwrap().0 > 0);
+    let (d, y) = solver_diagnostics(&mut ctx, 10_000, 1).unwrap();
+    assert!(d.3);
+    assert!(y.unwrap().3);
+}
+
+#[test]
+fn test_return_data_reports_settled_amounts() {
+    let read_u64 = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let mut ctx = setup_initialized_amm();
+
+    // Deposit returns the LP minted.
+    let deposit = deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+    assert_eq!(deposit.return_data.len(), 8);
+    assert_eq!(read_u64(&deposit.return_data, 0), get_token_balance(&ctx.svm, &deposit.user_lp_ata));
+
+    // Swap returns amount in, amount out and fee.
+    let y_before = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
+    let swap = normal_swap(&mut ctx, 10_000, 1, 1);
+    assert_eq!(swap.len(), 24);
+    assert_eq!(read_u64(&swap, 0), 10_000);
+    assert_eq!(read_u64(&swap, 8), y_before - get_token_balance(&ctx.svm, &ctx.vault_y_ata));
+    assert!(read_u64(&swap, 16) > 0);
+
+    // Withdraw returns amounts out and LP burned.
+    let x_before = get_token_balance(&ctx.svm, &ctx.vault_x_ata);
+    let withdraw = withdraw_liquidity(&mut ctx, &deposit);
+    assert_eq!(withdraw.len(), 24);
+    assert_eq!(read_u64(&withdraw, 0), x_before - get_token_balance(&ctx.svm, &ctx.vault_x_ata));
+    assert_eq!(read_u64(&withdraw, 16), 20_000);
+}
+
+#[test]
+fn test_swap_emits_event() {
+    let mut ctx = setup_initialized_amm();
+    deposit_liquidity(&mut ctx, 1_000_000, 1_000_000);
+
+    let (events, trader) = swap_events(&mut ctx, 10_000);
+    assert_eq!(events.len(), 1);
+    let MegaAmmEvent::Swap(event) = &events[0] else { panic!("expected a swap event") };
+    assert_eq!(event.pool, ctx.config_pda.to_bytes());
+    assert_eq!(event.user, trader);
+    assert_eq!(event.amount_in, 10_000);
+    assert!(event.fee > 0);
+    // Post trade reserves match the vaults.
+    assert_eq!(event.reserve_x, get_token_balance(&ctx.svm, &ctx.vault_x_ata));
+    assert_eq!(event.reserve_y, get_token_balance(&ctx.svm, &ctx.vault_y_ata));
+    assert!(event.d > 0);
+}
@@ -2,7 +2,7 @@
 #![allow(warnings)]
 use litesvm::LiteSVM;
 use solana_sdk::{
-    account::Account, pubkey::Pubkey, signature::Keypair,
+    account::Account, instruction::AccountMeta, pubkey::Pubkey, signature::Keypair,
 };
 use solana_system_interface::program::ID as SYSTEM_PROGRAM_ID;
 
@@ -15,12 +15,17 @@ pub struct AmmTestContext {
     pub seed: u64,
     pub mint_x: Pubkey,
     pub mint_y: Pubkey,
+    // Token program owning both mints, the legacy one or token-2022.
+    pub token_program: Pubkey,
 
     pub vault_x_ata: Pubkey,
     pub vault_y_ata: Pubkey,
     pub config_pda: Pubkey,
     pub lp_mint_pda: Pubkey,
+    pub observations_pda: Pubkey,
     pub fee: u16,
+    // Appended to every instruction moving tokens, empty unless the mints have transfer hooks.
+    pub transfer_hook_accounts: Vec<AccountMeta>,
 }
 
 pub struct DepositTestContext {
@@ -28,4 +33,6 @@ pub struct DepositTestContext {
     pub user_x_ata: Pubkey,
     pub user_y_ata: Pubkey, 
     pub user_lp_ata: Pubkey,
+    // Return data of the deposit transaction, empty if it failed.
+    pub return_data: Vec<u8>,
 }
@@ -24,7 +24,7 @@ mod litesvm_setup;
 use litesvm_setup::{
     create_ata, build_deposit_ix_data,
     create_legacy_mint, create_pool, create_pda_mint, mint_tokens,
-    get_token_balance,
+    get_token_balance, temporary_wsol_account,
 };
 use crate::common::context::{DepositTestContext, AmmTestContext};
 
@@ -35,13 +35,24 @@ pub fn deposit_liquidity(ctx: &mut AmmTestContext, x_amount: u64, y_amount: u64)
 
     let user = Keypair::new();
     ctx.svm.airdrop(&user.pubkey(), 1_000_000_000).unwrap();
+    // A native x side is deposited straight from the user's SOL, wrapped by the pool.
+    let native_x = ctx.mint_x == spl_token::native_mint::ID;
+    if native_x {
+        ctx.svm.airdrop(&user.pubkey(), x_amount).unwrap();
+    }
 
-    // Create user ATAs on the client side.
+    // Create user ATAs on the client side, the native side goes through the pool's temporary
+    // WSOL account instead.
     // Creating the test mints for x and y minting test tokens into them.
-    let user_x_ata = create_ata(
-        &mut ctx.svm, &user, &ctx.mint_x, &user.pubkey()
-    );
-    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &user_x_ata, x_amount);
+    let user_x_ata = if native_x {
+        temporary_wsol_account(&ctx.program_id, &user.pubkey(), &ctx.mint_x)
+    } else {
+        let user_x_ata = create_ata(
+            &mut ctx.svm, &user, &ctx.mint_x, &user.pubkey()
+        );
+        mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &user_x_ata, x_amount);
+        user_x_ata
+    };
     //println!("The user x ata balance is {}", get_token_balance(&ctx.svm, &user_x_ata));
     let user_y_ata = create_ata(
         &mut ctx.svm, &user, &ctx.mint_y, &user.pubkey()
@@ -50,17 +61,31 @@ pub fn deposit_liquidity(ctx: &mut AmmTestContext, x_amount: u64, y_amount: u64)
     //println!("The user y ata balance is {}", get_token_balance(&ctx.svm, &user_y_ata));
 
     // Here the mint is already available. Tokens will be minted here, for liquidity providers
-    create_pda_mint(&mut ctx.svm, ctx.lp_mint_pda, ctx.lp_mint_pda, 6);
+    // Only created once, later deposits must keep the LP supply.
+    if ctx.svm.get_account(&ctx.lp_mint_pda).map_or(true, |mint| mint.data.is_empty()) {
+        create_pda_mint(&mut ctx.svm, ctx.lp_mint_pda, ctx.lp_mint_pda, 6);
+    }
     let user_lp_ata = create_ata(
         &mut ctx.svm, &user, &ctx.lp_mint_pda, &user.pubkey(),
     );
+    // The pool's own LP account, where the first deposit locks the minimum liquidity.
+    // Creating it again fails harmlessly once it exists.
+    let locked_lp_ata = create_ata(
+        &mut ctx.svm, &user, &ctx.lp_mint_pda, &ctx.config_pda,
+    );
+    // Only the first deposit passes it, later ones go straight to the other trailing accounts.
+    let lp_supply = ctx.svm.get_account(&ctx.lp_mint_pda)
+        .map_or(0, |mint| u64::from_le_bytes(mint.data[36..44].try_into().unwrap()));
 
     // Build deposit instruction.
-    let deposit_ix_data = build_deposit_ix_data(
+    let mut deposit_ix_data = build_deposit_ix_data(
         x_amount, y_amount, i64::MAX,
     );
+    if native_x {
+        deposit_ix_data.push(1); // native_sol.
+    }
 
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new(user.pubkey(), true),
         AccountMeta::new(ctx.config_pda, false),
         AccountMeta::new(ctx.lp_mint_pda, false),
@@ -69,8 +94,18 @@ pub fn deposit_liquidity(ctx: &mut AmmTestContext, x_amount: u64, y_amount: u64)
         AccountMeta::new(ctx.vault_y_ata, false),
         AccountMeta::new(user_x_ata, false),
         AccountMeta::new(user_y_ata, false),
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
     ];
+    if lp_supply == 0 {
+        accounts.push(AccountMeta::new(locked_lp_ata, false));
+    }
+    if native_x {
+        accounts.push(AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false));
+    }
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
 
     let ix = Instruction::new_with_bytes(
         ctx.program_id,
@@ -86,12 +121,13 @@ pub fn deposit_liquidity(ctx: &mut AmmTestContext, x_amount: u64, y_amount: u64)
 
     let res = ctx.svm.send_transaction(tx);
     //println!("The deposit result is {:#?}", res);
+    let return_data = res.map(|meta| meta.return_data.data).unwrap_or_default();
     
     let liquidity_pool = get_token_balance(&ctx.svm, &user_lp_ata);
     println!("The liquidity pool total token balance is {}", liquidity_pool);
     println!("=======================================================");
 
     DepositTestContext {
-        user, user_x_ata, user_y_ata, user_lp_ata,
+        user, user_x_ata, user_y_ata, user_lp_ata, return_data,
     }
 }
@@ -0,0 +1,61 @@
+//! Testing the events emitted through `sol_log_data`.
+#![allow(warnings)]
+use base64::{Engine, engine::general_purpose::STANDARD};
+use megaswap_protocol::events::MegaAmmEvent;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    message::Message, signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use crate::common::context::AmmTestContext;
+use crate::common::litesvm_setup::{create_ata, mint_tokens};
+
+/// Decodes every `Program data:` log line emitted by the protocol.
+pub fn decode_events(logs: &[String]) -> Vec<MegaAmmEvent> {
+    logs.iter()
+        .filter_map(|line| line.strip_prefix("Program data: "))
+        .filter_map(|payload| STANDARD.decode(payload).ok())
+        .filter_map(|data| MegaAmmEvent::decode(&data).ok())
+        .collect()
+}
+
+/// Swaps x for y from a fresh wallet and returns the events it emitted, empty if it failed.
+pub fn swap_events(ctx: &mut AmmTestContext, amount: u64) -> (Vec<MegaAmmEvent>, [u8; 32]) {
+    let trader = Keypair::new();
+    ctx.svm.airdrop(&trader.pubkey(), 1_000_000_000).unwrap();
+    let trader_x_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_x, &trader.pubkey());
+    let trader_y_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_y, &trader.pubkey());
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &trader_x_ata, amount);
+
+    let mut data = vec![3u8];
+    data.extend_from_slice(&amount.to_le_bytes()); // amount
+    data.extend_from_slice(&1u64.to_le_bytes()); // min_out
+    data.extend_from_slice(&i64::MAX.to_le_bytes()); // expiration
+    data.push(1u8); // x -> y
+
+    let mut accounts = vec![
+        AccountMeta::new(trader.pubkey(), true),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(trader_x_ata, false),
+        AccountMeta::new(trader_y_ata, false),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+    ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    let ix = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&trader],
+        Message::new(&[ix], Some(&trader.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+
+    let events = ctx.svm.send_transaction(tx)
+        .map(|meta| decode_events(&meta.logs))
+        .unwrap_or_default();
+    (events, trader.pubkey().to_bytes())
+}
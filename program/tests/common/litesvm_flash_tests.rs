@@ -0,0 +1,214 @@
+//! Testing flash loans and flash swaps against the local borrower program.
+#![allow(warnings)]
+use solana_sdk::{
+    pubkey::Pubkey, instruction::{AccountMeta, Instruction, InstructionError},
+    signature::{Keypair, Signer}, message::Message,
+    transaction::{Transaction, TransactionError},
+};
+use crate::common::context::AmmTestContext;
+use crate::common::litesvm_setup::{create_ata, mint_tokens, get_token_balance};
+
+/// The local borrower program, `test-flash-borrower`, paying the vaults what it is told to.
+pub const FLASH_BORROWER_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("FLashBorrowerXZy63pByGvukt5BaHWjETPQdgEpsyCU");
+
+/// Borrows from the vaults, the borrower program then pays `repay_x` and `repay_y` back.
+/// Returns the program's error code when the loan fails, None for any other failure.
+pub fn flash_loan(
+    ctx: &mut AmmTestContext, amount_x: u64, amount_y: u64, repay_x: u64, repay_y: u64,
+) -> Result<(), Option<u32>> {
+    add_flash_borrower(ctx);
+    let borrower = Keypair::new();
+    ctx.svm.airdrop(&borrower.pubkey(), 1_000_000_000).unwrap();
+    let borrower_x_ata = create_ata(&mut ctx.svm, &borrower, &ctx.mint_x, &borrower.pubkey());
+    let borrower_y_ata = create_ata(&mut ctx.svm, &borrower, &ctx.mint_y, &borrower.pubkey());
+    // What the borrower made with the loan, enough to pay the fees.
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &borrower_x_ata, 1_000_000);
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_y, &borrower_y_ata, 1_000_000);
+
+    // One payment per vault, signed by the borrower, running the mints' hooks.
+    let mut callback_data = repay_x.to_le_bytes().to_vec();
+    callback_data.extend_from_slice(&repay_y.to_le_bytes());
+    let mut borrower_accounts = vec![
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new_readonly(borrower.pubkey(), true),
+        AccountMeta::new(borrower_x_ata, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(borrower_y_ata, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+    ];
+    borrower_accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    send_flash_loan(
+        ctx, &borrower, amount_x, amount_y, (borrower_x_ata, borrower_y_ata),
+        FLASH_BORROWER_PROGRAM_ID, borrower_accounts, callback_data,
+    )
+}
+
+/// Flash loan naming the pool itself as the borrower program, with a Skim of the vaults.
+pub fn flash_loan_calling_the_pool(ctx: &mut AmmTestContext, amount_x: u64) -> Result<(), Option<u32>> {
+    let borrower = Keypair::new();
+    ctx.svm.airdrop(&borrower.pubkey(), 1_000_000_000).unwrap();
+    let borrower_x_ata = create_ata(&mut ctx.svm, &borrower, &ctx.mint_x, &borrower.pubkey());
+    let borrower_y_ata = create_ata(&mut ctx.svm, &borrower, &ctx.mint_y, &borrower.pubkey());
+
+    let skim_accounts = vec![
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(borrower_x_ata, false),
+        AccountMeta::new(borrower_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+    ];
+    let program_id = ctx.program_id;
+    send_flash_loan(
+        ctx, &borrower, amount_x, 0, (borrower_x_ata, borrower_y_ata),
+        program_id, skim_accounts, vec![12u8], // Skim discriminator.
+    )
+}
+
+/// Flash swaps `amount` of x in (`is_x` 1) or of y, the borrower program then pays `repay` of
+/// the input token from the trader's account, which starts out empty in the output token.
+/// Returns what the trader received, or the program's error code, None for any other failure.
+pub fn flash_swap(ctx: &mut AmmTestContext, amount: u64, is_x: u8, repay: u64) -> Result<u64, Option<u32>> {
+    add_flash_borrower(ctx);
+    let trader = Keypair::new();
+    ctx.svm.airdrop(&trader.pubkey(), 1_000_000_000).unwrap();
+    let trader_x_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_x, &trader.pubkey());
+    let trader_y_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_y, &trader.pubkey());
+    let (trader_in, mint_in, vault_in, trader_out) = if is_x == 1 {
+        (trader_x_ata, ctx.mint_x, ctx.vault_x_ata, trader_y_ata)
+    } else {
+        (trader_y_ata, ctx.mint_y, ctx.vault_y_ata, trader_x_ata)
+    };
+    if repay > 0 {
+        mint_tokens(&mut ctx.svm, &ctx.initializer, &mint_in, &trader_in, repay);
+    }
+
+    // A single payment of the input token, signed by the trader, running the mint's hook.
+    let mut callback_accounts = vec![
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new_readonly(trader.pubkey(), true),
+        AccountMeta::new(trader_in, false),
+        AccountMeta::new_readonly(mint_in, false),
+        AccountMeta::new(vault_in, false),
+    ];
+    callback_accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    send_flash_swap(
+        ctx, &trader, amount, is_x, (trader_x_ata, trader_y_ata),
+        FLASH_BORROWER_PROGRAM_ID, callback_accounts, repay.to_le_bytes().to_vec(),
+    )?;
+    Ok(get_token_balance(&ctx.svm, &trader_out))
+}
+
+/// Flash swap of x in naming the pool itself as the callback, with a Skim of the vaults.
+pub fn flash_swap_calling_the_pool(ctx: &mut AmmTestContext, amount: u64) -> Result<(), Option<u32>> {
+    let trader = Keypair::new();
+    ctx.svm.airdrop(&trader.pubkey(), 1_000_000_000).unwrap();
+    let trader_x_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_x, &trader.pubkey());
+    let trader_y_ata = create_ata(&mut ctx.svm, &trader, &ctx.mint_y, &trader.pubkey());
+
+    let skim_accounts = vec![
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(trader_x_ata, false),
+        AccountMeta::new(trader_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+    ];
+    let program_id = ctx.program_id;
+    send_flash_swap(
+        ctx, &trader, amount, 1, (trader_x_ata, trader_y_ata),
+        program_id, skim_accounts, vec![12u8], // Skim discriminator.
+    ).map(|_| ())
+}
+
+fn add_flash_borrower(ctx: &mut AmmTestContext) {
+    if ctx.svm.get_account(&FLASH_BORROWER_PROGRAM_ID).is_none() {
+        let bytes = include_bytes!("../../../target/deploy/megaswap_test_flash_borrower.so");
+        ctx.svm.add_program(FLASH_BORROWER_PROGRAM_ID, bytes);
+    }
+}
+
+fn send_flash_swap(
+    ctx: &mut AmmTestContext, trader: &Keypair, amount: u64, is_x: u8,
+    (trader_x_ata, trader_y_ata): (Pubkey, Pubkey),
+    callback_program: Pubkey, callback_accounts: Vec<AccountMeta>, callback_data: Vec<u8>,
+) -> Result<(), Option<u32>> {
+    let mut data = vec![3u8]; // Swap discriminator.
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&1u64.to_le_bytes()); // min_out
+    data.extend_from_slice(&1_800_000_000i64.to_le_bytes());
+    data.push(is_x);
+    data.push(0); // native_sol
+    data.push(1); // flash
+    data.push(callback_accounts.len() as u8);
+    data.extend_from_slice(&callback_data);
+    let mut accounts = vec![
+        AccountMeta::new(trader.pubkey(), true),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(trader_x_ata, false),
+        AccountMeta::new(trader_y_ata, false),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+        AccountMeta::new_readonly(callback_program, false),
+    ];
+    accounts.extend(callback_accounts);
+    // The pool's own transfers only get the hook accounts.
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    send(ctx, trader, Instruction::new_with_bytes(ctx.program_id, &data, accounts))
+}
+
+fn send_flash_loan(
+    ctx: &mut AmmTestContext, borrower: &Keypair, amount_x: u64, amount_y: u64,
+    (borrower_x_ata, borrower_y_ata): (Pubkey, Pubkey),
+    borrower_program: Pubkey, borrower_accounts: Vec<AccountMeta>, callback_data: Vec<u8>,
+) -> Result<(), Option<u32>> {
+    let mut data = vec![15u8]; // FlashLoan discriminator.
+    data.extend_from_slice(&amount_x.to_le_bytes());
+    data.extend_from_slice(&amount_y.to_le_bytes());
+    data.push(borrower_accounts.len() as u8);
+    data.extend_from_slice(&callback_data);
+    let mut accounts = vec![
+        AccountMeta::new(borrower.pubkey(), true),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new_readonly(ctx.lp_mint_pda, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(borrower_x_ata, false),
+        AccountMeta::new(borrower_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+        AccountMeta::new_readonly(borrower_program, false),
+    ];
+    accounts.extend(borrower_accounts);
+    // The pool's own transfers only get the hook accounts.
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    send(ctx, borrower, Instruction::new_with_bytes(ctx.program_id, &data, accounts))
+}
+
+fn send(ctx: &mut AmmTestContext, signer: &Keypair, instruction: Instruction) -> Result<(), Option<u32>> {
+    let tx = Transaction::new(
+        &[signer],
+        Message::new(&[instruction], Some(&signer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+    match ctx.svm.send_transaction(tx) {
+        Ok(_) => Ok(()),
+        Err(failed) => match failed.err {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => Err(Some(code)),
+            _ => Err(None),
+        },
+    }
+}
@@ -0,0 +1,109 @@
+//! Testing the pool's price oracle.
+#![allow(warnings)]
+use litesvm::LiteSVM;
+use solana_sdk::{
+    pubkey::Pubkey, instruction::{AccountMeta, Instruction},
+    sysvar::clock::Clock, signature::{Keypair, Signer}, message::Message,
+    transaction::Transaction,
+};
+use solana_system_interface::program::ID as SYSTEM_PROGRAM_ID;
+use megaswap_protocol::observations::Observations;
+use crate::common::context::AmmTestContext;
+
+/// Oracle state as returned by the GetOraclePrice instruction.
+pub struct OracleReading {
+    pub spot_price: u128,
+    pub ema_price: u128,
+    pub timestamp: i64,
+}
+
+/// Reads the pool's oracle through a simulated GetOraclePrice transaction.
+pub fn read_oracle_price(ctx: &mut AmmTestContext) -> Option<OracleReading> {
+    let payer = Keypair::new();
+    ctx.svm.airdrop(&payer.pubkey(), 1_000_000_000).unwrap();
+
+    let data = vec![5u8]; // GetOraclePrice discriminator.
+    let accounts = vec![
+        AccountMeta::new_readonly(ctx.config_pda, false),
+    ];
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&payer],
+        Message::new(&[instruction], Some(&payer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+
+    let result = ctx.svm.simulate_transaction(tx).ok()?;
+    let return_data = result.meta.return_data.data;
+    if return_data.len() != 40 {
+        return None;
+    }
+    Some(OracleReading {
+        spot_price: u128::from_le_bytes(return_data[0..16].try_into().unwrap()),
+        ema_price: u128::from_le_bytes(return_data[16..32].try_into().unwrap()),
+        timestamp: i64::from_le_bytes(return_data[32..40].try_into().unwrap()),
+    })
+}
+
+/// Moves the cluster clock forward so the EMA has time to decay.
+pub fn advance_clock(svm: &mut LiteSVM, seconds: i64) {
+    let mut clock: Clock = svm.get_sysvar();
+    clock.unix_timestamp += seconds;
+    clock.slot += 1;
+    svm.set_sysvar::<Clock>(&clock);
+    svm.expire_blockhash();
+}
+
+/// Grows the pool's observation buffer to `cardinality_next` slots.
+pub fn grow_observations(ctx: &mut AmmTestContext, cardinality_next: u16) -> bool {
+    let payer = Keypair::new();
+    ctx.svm.airdrop(&payer.pubkey(), 1_000_000_000).unwrap();
+
+    let mut data = vec![6u8]; // GrowObservations discriminator.
+    data.extend_from_slice(&cardinality_next.to_le_bytes());
+    let accounts = vec![
+        AccountMeta::new(payer.pubkey(), true),
+        AccountMeta::new_readonly(ctx.config_pda, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+    ];
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&payer],
+        Message::new(&[instruction], Some(&payer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+    ctx.svm.send_transaction(tx).is_ok()
+}
+
+/// Time weighted (price, D per LP) between two timestamps, read off chain from the buffer.
+pub fn read_twap(ctx: &AmmTestContext, start: i64, end: i64) -> Option<(u128, u128)> {
+    let account = ctx.svm.get_account(&ctx.observations_pda)?;
+    let observations = Observations::from_bytes(&account.data).ok()?;
+    observations.twap(start, end).ok()
+}
+
+/// Reads the value of one LP token through a simulated GetVirtualPrice transaction.
+pub fn read_virtual_price(ctx: &mut AmmTestContext) -> Option<u128> {
+    let payer = Keypair::new();
+    ctx.svm.airdrop(&payer.pubkey(), 1_000_000_000).unwrap();
+
+    let data = vec![7u8]; // GetVirtualPrice discriminator.
+    let accounts = vec![
+        AccountMeta::new_readonly(ctx.config_pda, false),
+        AccountMeta::new_readonly(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.vault_x_ata, false),
+        AccountMeta::new_readonly(ctx.vault_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+    ];
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&payer],
+        Message::new(&[instruction], Some(&payer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+
+    let result = ctx.svm.simulate_transaction(tx).ok()?;
+    let return_data = result.meta.return_data.data;
+    Some(u128::from_le_bytes(return_data.as_slice().try_into().ok()?))
+}
@@ -0,0 +1,120 @@
+//! Testing the read only quote instructions.
+#![allow(warnings)]
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    signature::{Keypair, Signer}, message::Message,
+    transaction::{Transaction, TransactionError},
+};
+use crate::common::context::AmmTestContext;
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Simulates a quote instruction and returns its raw return data.
+fn simulate_quote(ctx: &mut AmmTestContext, data: Vec<u8>) -> Option<Vec<u8>> {
+    let tx = quote_transaction(ctx, data);
+    let result = ctx.svm.simulate_transaction(tx).ok()?;
+    Some(result.meta.return_data.data)
+}
+
+/// Simulates a quote instruction expected to fail and returns the program's error code.
+fn simulate_quote_error(ctx: &mut AmmTestContext, data: Vec<u8>) -> Option<u32> {
+    let tx = quote_transaction(ctx, data);
+    match ctx.svm.simulate_transaction(tx).err()?.err {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(code),
+        _ => None,
+    }
+}
+
+fn quote_transaction(ctx: &mut AmmTestContext, data: Vec<u8>) -> Transaction {
+    let payer = Keypair::new();
+    ctx.svm.airdrop(&payer.pubkey(), 1_000_000_000).unwrap();
+
+    let accounts = vec![
+        AccountMeta::new_readonly(ctx.config_pda, false),
+        AccountMeta::new_readonly(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.vault_x_ata, false),
+        AccountMeta::new_readonly(ctx.vault_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+    ];
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    Transaction::new(
+        &[&payer],
+        Message::new(&[instruction], Some(&payer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    )
+}
+
+/// QuoteSwap. Returns (amount in, amount out, fee).
+pub fn quote_swap(ctx: &mut AmmTestContext, amount: u64, is_x: u8) -> Option<(u64, u64, u64)> {
+    let mut data = vec![8u8]; // QuoteSwap discriminator.
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(is_x);
+    let quote = simulate_quote(ctx, data)?;
+    Some((read_u64(&quote, 0), read_u64(&quote, 8), read_u64(&quote, 16)))
+}
+
+/// QuoteSwap expected to fail. Returns the program's error code, None if it succeeded.
+pub fn quote_swap_error(ctx: &mut AmmTestContext, amount: u64, is_x: u8) -> Option<u32> {
+    let mut data = vec![8u8]; // QuoteSwap discriminator.
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(is_x);
+    simulate_quote_error(ctx, data)
+}
+
+/// QuoteDeposit. Returns the LP tokens that would be minted.
+pub fn quote_deposit(ctx: &mut AmmTestContext, amount_x: u64, amount_y: u64) -> Option<u64> {
+    let mut data = vec![9u8]; // QuoteDeposit discriminator.
+    data.extend_from_slice(&amount_x.to_le_bytes());
+    data.extend_from_slice(&amount_y.to_le_bytes());
+    let quote = simulate_quote(ctx, data)?;
+    Some(read_u64(&quote, 0))
+}
+
+/// QuoteDeposit expected to fail. Returns the program's error code, None if it succeeded.
+pub fn quote_deposit_error(ctx: &mut AmmTestContext, amount_x: u64, amount_y: u64) -> Option<u32> {
+    let mut data = vec![9u8]; // QuoteDeposit discriminator.
+    data.extend_from_slice(&amount_x.to_le_bytes());
+    data.extend_from_slice(&amount_y.to_le_bytes());
+    simulate_quote_error(ctx, data)
+}
+
+/// QuoteWithdraw. Returns (amount of x out, amount of y out, LP burned).
+pub fn quote_withdraw(
+    ctx: &mut AmmTestContext, lp_to_burn: u64, amount_of_x: u64, amount_of_y: u64, withdraw_mode: u8,
+) -> Option<(u64, u64, u64)> {
+    let mut data = vec![10u8]; // QuoteWithdraw discriminator.
+    data.extend_from_slice(&lp_to_burn.to_le_bytes());
+    data.extend_from_slice(&amount_of_x.to_le_bytes());
+    data.extend_from_slice(&amount_of_y.to_le_bytes());
+    data.push(withdraw_mode);
+    let quote = simulate_quote(ctx, data)?;
+    Some((read_u64(&quote, 0), read_u64(&quote, 8), read_u64(&quote, 16)))
+}
+
+/// One solve of GetSolverDiagnostics, (value, iterations, residual, converged).
+pub type SolveReport = (u128, u16, u128, bool);
+
+fn read_solve(data: &[u8]) -> SolveReport {
+    (
+        u128::from_le_bytes(data[0..16].try_into().unwrap()),
+        u16::from_le_bytes(data[16..18].try_into().unwrap()),
+        u128::from_le_bytes(data[18..34].try_into().unwrap()),
+        data[34] == 1,
+    )
+}
+
+/// GetSolverDiagnostics. Returns the D solve and, for a non zero amount, the swap's y solve.
+pub fn solver_diagnostics(
+    ctx: &mut AmmTestContext, amount: u64, is_x: u8,
+) -> Option<(SolveReport, Option<SolveReport>)> {
+    let mut data = vec![11u8]; // GetSolverDiagnostics discriminator.
+    if amount > 0 {
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.push(is_x);
+    }
+    let report = simulate_quote(ctx, data)?;
+    let y = if report[35] == 1 { Some(read_solve(&report[36..71])) } else { None };
+    Some((read_solve(&report[0..35]), y))
+}
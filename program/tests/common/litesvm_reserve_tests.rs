@@ -0,0 +1,93 @@
+//! Testing the pool's reserve tracking, Skim, Sync and Donate.
+#![allow(warnings)]
+use solana_sdk::{
+    pubkey::Pubkey, instruction::{AccountMeta, Instruction},
+    signature::{Keypair, Signer}, message::Message,
+    transaction::Transaction,
+};
+use crate::common::context::AmmTestContext;
+use crate::common::litesvm_setup::{create_ata, mint_tokens};
+
+/// Sends the vaults' excess over the tracked reserves to the recipients. Permissionless.
+pub fn skim(ctx: &mut AmmTestContext, recipient_x: &Pubkey, recipient_y: &Pubkey) -> bool {
+    let payer = Keypair::new();
+    ctx.svm.airdrop(&payer.pubkey(), 1_000_000_000).unwrap();
+
+    let data = vec![12u8]; // Skim discriminator.
+    let mut accounts = vec![
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(*recipient_x, false),
+        AccountMeta::new(*recipient_y, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+    ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&payer],
+        Message::new(&[instruction], Some(&payer.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+    ctx.svm.send_transaction(tx).is_ok()
+}
+
+/// Adopts the vault balances as reserves, signed by `authority`.
+pub fn sync(ctx: &mut AmmTestContext, authority: &Keypair) -> bool {
+    ctx.svm.airdrop(&authority.pubkey(), 1_000_000_000).unwrap();
+
+    let data = vec![13u8]; // Sync discriminator.
+    let accounts = vec![
+        AccountMeta::new_readonly(authority.pubkey(), true),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new_readonly(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.vault_x_ata, false),
+        AccountMeta::new_readonly(ctx.vault_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+    ];
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[authority],
+        Message::new(&[instruction], Some(&authority.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+    ctx.svm.send_transaction(tx).is_ok()
+}
+
+/// Donates `amount_x` and `amount_y` to the pool's LPs from a fresh donor.
+pub fn donate(ctx: &mut AmmTestContext, amount_x: u64, amount_y: u64) -> bool {
+    let donor = Keypair::new();
+    ctx.svm.airdrop(&donor.pubkey(), 1_000_000_000).unwrap();
+    let donor_x_ata = create_ata(&mut ctx.svm, &donor, &ctx.mint_x, &donor.pubkey());
+    let donor_y_ata = create_ata(&mut ctx.svm, &donor, &ctx.mint_y, &donor.pubkey());
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_x, &donor_x_ata, amount_x);
+    mint_tokens(&mut ctx.svm, &ctx.initializer, &ctx.mint_y, &donor_y_ata, amount_y);
+
+    let mut data = vec![14u8]; // Donate discriminator.
+    data.extend_from_slice(&amount_x.to_le_bytes());
+    data.extend_from_slice(&amount_y.to_le_bytes());
+    let mut accounts = vec![
+        AccountMeta::new(donor.pubkey(), true),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new_readonly(ctx.lp_mint_pda, false),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(donor_x_ata, false),
+        AccountMeta::new(donor_y_ata, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+    ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&donor],
+        Message::new(&[instruction], Some(&donor.pubkey())),
+        ctx.svm.latest_blockhash(),
+    );
+    ctx.svm.send_transaction(tx).is_ok()
+}
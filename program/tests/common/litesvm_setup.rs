@@ -18,7 +18,7 @@ use solana_system_interface::instruction::create_account;
 use spl_associated_token_account::ID as ATA_PROGRAM_ID;
 use solana_program::program_pack::Pack; //Trait to enable Mint::LEN
 use spl_associated_token_account::{
-    get_associated_token_address,
+    get_associated_token_address_with_program_id,
     instruction::create_associated_token_account,
 };
 use spl_token::instruction as token_ix;
@@ -29,7 +29,97 @@ use spl_token::solana_program::program_option::COption;
 
 use crate::common::context::{AmmTestContext};
 
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+/// The local transfer hook program, `test-transfer-hook`, counting the transfers it runs for.
+pub const TRANSFER_HOOK_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("HooKq5sVYVXgAaV8Ma2AbRYWpu1ZbsD2Vq7Qaxd6Hc2Q");
+// `Execute` discriminator of the transfer hook interface.
+const EXECUTE_DISCRIMINATOR: [u8; 8] = [105, 37, 101, 197, 75, 251, 102, 26];
+
+/// Token-2022 extensions initialized on both pool mints.
+#[derive(Debug, Clone, Copy)]
+pub enum MintExtension {
+    TransferFee { basis_points: u16, maximum_fee: u64 },
+    NonTransferable,
+    TransferHook { program_id: Pubkey },
+}
+
+impl MintExtension {
+    // Length of the extension's value in the mint account.
+    fn len(&self) -> usize {
+        match self {
+            MintExtension::TransferFee { .. } => 108,
+            MintExtension::NonTransferable => 0,
+            MintExtension::TransferHook { .. } => 64,
+        }
+    }
+
+    // Initializes the extension, it must run before the mint itself is initialized.
+    fn init_ix(&self, mint: &Pubkey, authority: &Pubkey) -> Instruction {
+        let data = match self {
+            MintExtension::TransferFee { basis_points, maximum_fee } => {
+                // TransferFeeExtension, InitializeTransferFeeConfig.
+                let mut data = vec![26u8, 0];
+                // Transfer fee config and withheld withdrawal authorities.
+                for _ in 0..2 {
+                    data.push(1);
+                    data.extend_from_slice(authority.as_ref());
+                }
+                data.extend_from_slice(&basis_points.to_le_bytes());
+                data.extend_from_slice(&maximum_fee.to_le_bytes());
+                data
+            }
+            MintExtension::NonTransferable => vec![32u8], // InitializeNonTransferableMint.
+            MintExtension::TransferHook { program_id } => {
+                // TransferHookExtension, Initialize with the hook authority and program.
+                let mut data = vec![36u8, 0];
+                data.extend_from_slice(authority.as_ref());
+                data.extend_from_slice(program_id.as_ref());
+                data
+            }
+        };
+        Instruction::new_with_bytes(TOKEN_2022_PROGRAM_ID, &data, vec![AccountMeta::new(*mint, false)])
+    }
+}
+
 pub fn setup_initialized_amm() -> AmmTestContext {
+    setup_amm(None, None, TOKEN_PROGRAM_ID, &[])
+}
+
+/// Initializes a pool with the given curve type, 0 stableswap, 1 constant product, 3 cryptoswap.
+pub fn setup_initialized_amm_with_curve(curve_type: u8) -> AmmTestContext {
+    setup_amm(Some(curve_type), None, TOKEN_PROGRAM_ID, &[])
+}
+
+/// Initializes a weighted pool (curve type 2) with the given weight of x, 1e18 scaled.
+pub fn setup_initialized_weighted_amm(weight_x: u64) -> AmmTestContext {
+    setup_amm(Some(2), Some(weight_x), TOKEN_PROGRAM_ID, &[])
+}
+
+/// Initializes a stableswap pool whose mints, vaults and LP mint belong to token-2022.
+pub fn setup_initialized_token_2022_amm() -> AmmTestContext {
+    setup_amm(None, None, TOKEN_2022_PROGRAM_ID, &[])
+}
+
+/// Initializes a token-2022 stableswap pool whose mints carry the given extensions.
+pub fn setup_initialized_token_2022_amm_with(extensions: &[MintExtension]) -> AmmTestContext {
+    setup_amm(None, None, TOKEN_2022_PROGRAM_ID, extensions)
+}
+
+/// Initializes a stableswap pool of the native mint, as x, against a legacy token y.
+pub fn setup_initialized_native_sol_amm() -> AmmTestContext {
+    setup_amm_with(None, None, TOKEN_PROGRAM_ID, &[], true)
+}
+
+fn setup_amm(
+    curve_type: Option<u8>, weight_x: Option<u64>, token_program: Pubkey, extensions: &[MintExtension],
+) -> AmmTestContext {
+    setup_amm_with(curve_type, weight_x, token_program, extensions, false)
+}
+
+fn setup_amm_with(
+    curve_type: Option<u8>, weight_x: Option<u64>, token_program: Pubkey, extensions: &[MintExtension],
+    native_x: bool,
+) -> AmmTestContext {
     let program_id = solana_sdk::pubkey!("HBfwxFs8KL5993jCpzY95A9EYJkHMsYy2YMDVP1Jq3Zy");
     let bytes = include_bytes!("../../../target/deploy/megaswap_protocol.so");
 
@@ -42,22 +132,34 @@ pub fn setup_initialized_amm() -> AmmTestContext {
 
     // ---- PDAs ----
     let seed: u64 = 42;
-    let mint_x = create_test_mint(&mut svm, &initializer, &initializer.pubkey(), 6); 
-    let mint_y = create_test_mint(&mut svm, &initializer, &initializer.pubkey(), 6); 
+    let mint_x = if native_x {
+        // The native mint has no authority, its supply only grows by wrapping SOL.
+        create_native_mint(&mut svm);
+        spl_token::native_mint::ID
+    } else {
+        create_test_mint(&mut svm, &initializer, &initializer.pubkey(), 6, &token_program, extensions)
+    };
+    let mint_y = create_test_mint(&mut svm, &initializer, &initializer.pubkey(), 6, &token_program, extensions);
+    let transfer_hook_accounts = setup_transfer_hook(&mut svm, &[mint_x, mint_y], extensions);
 
     let (config_pda, config_bump) = Pubkey::find_program_address(
         &[b"config"],
         &program_id,
     );
-    let vault_x_ata = get_associated_token_address(&config_pda, &mint_x);
-    let vault_y_ata = get_associated_token_address(&config_pda, &mint_y);
+    let vault_x_ata = get_associated_token_address_with_program_id(&config_pda, &mint_x, &token_program);
+    let vault_y_ata = get_associated_token_address_with_program_id(&config_pda, &mint_y, &token_program);
 
     let (lp_mint_pda, lp_bump) = Pubkey::find_program_address(
         &[b"lp_mint", config_pda.as_ref()],
         &program_id,
     );
+    let (observations_pda, _) = Pubkey::find_program_address(
+        &[b"observations", config_pda.as_ref()],
+        &program_id,
+    );
 
-    let authority = Pubkey::new_unique();
+    // The initializer administers the test pools.
+    let authority = initializer.pubkey();
     // Stable swaps fee are between 1 bps and 4bps
     let fee = 2u16;
     let mut instruction_data = vec![0u8];
@@ -77,6 +179,14 @@ pub fn setup_initialized_amm() -> AmmTestContext {
     instruction_data.push(lp_bump);
     // AMM admin authority
     instruction_data.extend_from_slice(authority.as_ref());
+    // Curve type, stableswap when left out.
+    if let Some(curve_type) = curve_type {
+        instruction_data.push(curve_type);
+    }
+    // Weight of x, weighted pools only.
+    if let Some(weight_x) = weight_x {
+        instruction_data.extend_from_slice(&weight_x.to_le_bytes());
+    }
 
     let accounts = vec![
         AccountMeta::new(initializer.pubkey(), true),
@@ -92,7 +202,8 @@ pub fn setup_initialized_amm() -> AmmTestContext {
 
         AccountMeta::new(ATA_PROGRAM_ID, false),
         AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(token_program, false),
+        AccountMeta::new(observations_pda, false),
         //AccountMeta::new_readonly(solana_sdk::sysvar::rent::ID, false),
     ];
 
@@ -118,12 +229,72 @@ pub fn setup_initialized_amm() -> AmmTestContext {
         seed,
         mint_x,
         mint_y,
+        token_program,
         vault_x_ata,
         vault_y_ata,
         config_pda,
         lp_mint_pda,
-        fee
+        observations_pda,
+        fee,
+        transfer_hook_accounts,
+    }
+}
+
+/// Counter of the transfers the local transfer hook ran for.
+pub fn transfer_hook_counter() -> Pubkey {
+    Pubkey::find_program_address(&[b"counter"], &TRANSFER_HOOK_PROGRAM_ID).0
+}
+
+/// Transfers the local hook ran for so far.
+pub fn transfer_hook_count(ctx: &AmmTestContext) -> u64 {
+    let counter = ctx.svm.get_account(&transfer_hook_counter()).unwrap();
+    u64::from_le_bytes(counter.data[..8].try_into().unwrap())
+}
+
+// Loads the transfer hook program the mints point to, with an `ExtraAccountMetaList` per mint
+// listing the counter as the one writable extra account. Returns the accounts token-2022
+// needs to invoke the hook: the program, the lists and the counter.
+fn setup_transfer_hook(svm: &mut LiteSVM, mints: &[Pubkey], extensions: &[MintExtension]) -> Vec<AccountMeta> {
+    let Some(program_id) = extensions.iter().find_map(|extension| match extension {
+        MintExtension::TransferHook { program_id } => Some(*program_id),
+        _ => None,
+    }) else {
+        return vec![];
+    };
+    if program_id == TRANSFER_HOOK_PROGRAM_ID {
+        let bytes = include_bytes!("../../../target/deploy/megaswap_test_transfer_hook.so");
+        svm.add_program(program_id, bytes);
+    }
+
+    let counter = transfer_hook_counter();
+    let owned_by_hook = |data: Vec<u8>| Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    svm.set_account(counter, owned_by_hook(vec![0u8; 8])).unwrap();
+
+    // TLV entry for `Execute` holding a single `ExtraAccountMeta`: a fixed address, not a
+    // signer, writable.
+    let mut extra_account_metas = EXECUTE_DISCRIMINATOR.to_vec();
+    extra_account_metas.extend_from_slice(&(4u32 + 35).to_le_bytes());
+    extra_account_metas.extend_from_slice(&1u32.to_le_bytes());
+    extra_account_metas.push(0);
+    extra_account_metas.extend_from_slice(counter.as_ref());
+    extra_account_metas.extend_from_slice(&[0, 1]);
+
+    let mut accounts = vec![AccountMeta::new_readonly(program_id, false)];
+    for mint in mints {
+        let (address, _) = Pubkey::find_program_address(
+            &[b"extra-account-metas", mint.as_ref()], &program_id,
+        );
+        svm.set_account(address, owned_by_hook(extra_account_metas.clone())).unwrap();
+        accounts.push(AccountMeta::new_readonly(address, false));
     }
+    accounts.push(AccountMeta::new(counter, false));
+    accounts
 }
 
 pub fn create_ata(
@@ -132,13 +303,15 @@ pub fn create_ata(
     mint: &Pubkey,
     owner: &Pubkey,
 ) -> Pubkey {
-    let ata = get_associated_token_address(owner, mint);
+    // The ATA belongs to the token program owning the mint.
+    let token_program = svm.get_account(mint).unwrap().owner;
+    let ata = get_associated_token_address_with_program_id(owner, mint, &token_program);
 
     let ix = create_associated_token_account(
         &payer.pubkey(),
         owner,
         mint,
-        &spl_token::ID.into(),
+        &token_program,
     );
 
     let tx = Transaction::new(
@@ -216,11 +389,18 @@ pub fn create_test_mint(
     payer: &Keypair,
     mint_authority: &Pubkey,
     decimals: u8,
+    token_program: &Pubkey,
+    extensions: &[MintExtension],
 ) -> Pubkey {
     let mint = Keypair::new();
 
     let rent = solana_sdk::sysvar::rent::Rent::default();
-    let mint_space = Mint::LEN;
+    // Token-2022 extensions follow the account type, after the mint padded to a token account's length.
+    let mint_space = if extensions.is_empty() {
+        Mint::LEN
+    } else {
+        TokenAccount::LEN + 1 + extensions.iter().map(|extension| 4 + extension.len()).sum::<usize>()
+    };
     let lamports = rent.minimum_balance(mint_space);
 
     // Create mint account
@@ -229,20 +409,24 @@ pub fn create_test_mint(
         &mint.pubkey(),
         lamports,
         mint_space as u64,
-        &spl_token::ID,
+        token_program,
     );
 
+    let mut instructions = vec![create_account_ix];
+    instructions.extend(extensions.iter().map(|extension| extension.init_ix(&mint.pubkey(), mint_authority)));
+
     // Initialize mint
-    let init_mint_ix = token_ix::initialize_mint(
+    let init_mint_ix = for_token_program(token_ix::initialize_mint(
         &spl_token::ID,
         &mint.pubkey(),
         mint_authority,
         None,
         decimals,
-    ).unwrap();
+    ).unwrap(), token_program);
 
+    instructions.push(init_mint_ix);
     let tx = Transaction::new_signed_with_payer(
-        &[create_account_ix, init_mint_ix],
+        &instructions,
         Some(&payer.pubkey()),
         &[payer, &mint],
         svm.latest_blockhash(),
@@ -281,14 +465,55 @@ pub fn create_pda_mint(
     );
 }
 
+/// Sets the legacy token program's native mint, wrapped SOL with 9 decimals.
+pub fn create_native_mint(svm: &mut LiteSVM) {
+    let mint_state = Mint {
+        mint_authority: COption::None,
+        supply: 0,
+        decimals: spl_token::native_mint::DECIMALS,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+
+    let mut data = vec![0u8; Mint::LEN];
+    Mint::pack(mint_state, &mut data).unwrap();
+
+    svm.set_account(
+        spl_token::native_mint::ID,
+        Account {
+            lamports: Rent::default().minimum_balance(Mint::LEN), data, owner: TOKEN_PROGRAM_ID,
+            executable: false, rent_epoch: 0,
+        },
+    ).unwrap();
+}
+
+/// The pool's temporary WSOL account for `owner`, created and closed within each native SOL
+/// instruction in place of the owner's token account for the native mint.
+pub fn temporary_wsol_account(program_id: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"wsol", owner.as_ref(), mint.as_ref()], program_id).0
+}
+
+/// Wraps `lamports` of the owner's SOL into their native token account.
+pub fn wrap_sol(svm: &mut LiteSVM, owner: &Keypair, account: &Pubkey, lamports: u64) {
+    let ixs = [
+        system_instruction::transfer(&owner.pubkey(), account, lamports),
+        token_ix::sync_native(&TOKEN_PROGRAM_ID, account).unwrap(),
+    ];
+    let tx = Transaction::new_signed_with_payer(
+        &ixs, Some(&owner.pubkey()), &[owner], svm.latest_blockhash(),
+    );
+    svm.send_transaction(tx).unwrap();
+}
+
 pub fn mint_tokens(
     svm: &mut LiteSVM, authority: &Keypair, mint: &Pubkey,
     destination_ata: &Pubkey, amount: u64,
 ) {
-    let ix = token_ix::mint_to(
+    let token_program = svm.get_account(mint).unwrap().owner;
+    let ix = for_token_program(token_ix::mint_to(
         &spl_token::ID, mint, destination_ata,
         &authority.pubkey(), &[], amount,
-    ).unwrap();
+    ).unwrap(), &token_program);
     let tx = Transaction::new_signed_with_payer(
         &[ix], Some(&authority.pubkey()),
         &[authority], // Both sign if different
@@ -300,6 +525,14 @@ pub fn mint_tokens(
 
 pub fn get_token_balance(svm: &LiteSVM, token_account: &Pubkey) -> u64 {
     let acc = svm.get_account(token_account).unwrap();
-    let token_acc = TokenAccount::unpack(&acc.data).unwrap();
+    // Token-2022 accounts carry their extensions after the base account.
+    let token_acc = TokenAccount::unpack(&acc.data[..TokenAccount::LEN]).unwrap();
     token_acc.amount
 }
+
+/// Token-2022 shares the legacy program's instruction layout for the base instructions,
+/// only the program the instruction is sent to changes.
+pub fn for_token_program(mut ix: Instruction, token_program: &Pubkey) -> Instruction {
+    ix.program_id = *token_program;
+    ix
+}
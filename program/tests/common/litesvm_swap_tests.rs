@@ -15,12 +15,13 @@ use solana_system_interface::{
 };
 use crate::common::context::{DepositTestContext, AmmTestContext};
 use crate::common::litesvm_setup::{
-    create_ata, create_pda_mint, mint_tokens, get_token_balance
+    create_ata, create_pda_mint, mint_tokens, get_token_balance, temporary_wsol_account,
 };
 
 use spl_token::ID as TOKEN_PROGRAM_ID;
 
-pub fn normal_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u64, target_token: u8) {
+/// Returns the swap transaction's return data, empty if it failed.
+pub fn normal_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u64, target_token: u8) -> Vec<u8> {
     let svm = &mut ctx.svm;
 
     println!("================ NORMAL SWAP TEST ================");
@@ -82,7 +83,7 @@ pub fn normal_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u64, ta
     println!("----------------------------------------------");
 
     // Build Instruction
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new(target_wallet.pubkey(), true),
 
         AccountMeta::new(ctx.vault_x_ata, false),
@@ -94,8 +95,12 @@ pub fn normal_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u64, ta
         AccountMeta::new(ctx.config_pda, false),
         AccountMeta::new(ctx.lp_mint_pda, false),
 
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
     ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
 
     let instruction = Instruction::new_with_bytes(
         program_id, &data, accounts,
@@ -134,6 +139,7 @@ pub fn normal_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u64, ta
     // We should have less x in the wallet than before and more x in the pool than before
     //assert!(x_after < 100_000);
     //assert!(y_after > 0);
+    result.map(|meta| meta.return_data.data).unwrap_or_default()
 }
 
 /// This swap should not trigger fees or mutate the pool state.
@@ -203,7 +209,7 @@ pub fn zero_amount_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u6
     println!("================================================");
 
     // Building the Instruction
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new(target_wallet.pubkey(), true),
 
         AccountMeta::new(ctx.vault_x_ata, false),
@@ -215,8 +221,12 @@ pub fn zero_amount_swap(ctx: &mut AmmTestContext, swap_amount: u64, slippage: u6
         AccountMeta::new(ctx.config_pda, false),
         AccountMeta::new(ctx.lp_mint_pda, false),
 
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
     ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
 
     let instruction = Instruction::new_with_bytes(
         program_id, &data, accounts,
@@ -320,7 +330,7 @@ pub fn slippage_protected_swap(ctx: &mut AmmTestContext, swap_amount: u64, slipp
     println!("================================================");
 
     // Building the Instruction
-    let accounts = vec![
+    let mut accounts = vec![
         AccountMeta::new(target_wallet.pubkey(), true),
 
         AccountMeta::new(ctx.vault_x_ata, false),
@@ -332,8 +342,12 @@ pub fn slippage_protected_swap(ctx: &mut AmmTestContext, swap_amount: u64, slipp
         AccountMeta::new(ctx.config_pda, false),
         AccountMeta::new(ctx.lp_mint_pda, false),
 
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
     ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
 
     let instruction = Instruction::new_with_bytes(
         program_id, &data, accounts,
@@ -369,3 +383,61 @@ pub fn slippage_protected_swap(ctx: &mut AmmTestContext, swap_amount: u64, slipp
     // Assert failure
     assert!(result.is_err());
 }
+
+/// Swaps with the native mint side settled in SOL: paid from the trader's lamports when swapping
+/// x in, received as lamports when swapping y in. Returns the change in the trader's SOL, their
+/// WSOL account included, and the transaction's return data, empty if it failed.
+pub fn native_sol_swap(ctx: &mut AmmTestContext, swap_amount: u64, target_token: u8) -> (i64, Vec<u8>) {
+    let svm = &mut ctx.svm;
+    let trader = Keypair::new();
+    svm.airdrop(&trader.pubkey(), 10_000_000_000).unwrap();
+
+    // The native side goes through the pool's temporary WSOL account, which only lives for the swap.
+    let wallet_x_ata = if ctx.mint_x == spl_token::native_mint::ID {
+        temporary_wsol_account(&ctx.program_id, &trader.pubkey(), &ctx.mint_x)
+    } else {
+        create_ata(svm, &trader, &ctx.mint_x, &trader.pubkey())
+    };
+    let wallet_y_ata = create_ata(svm, &trader, &ctx.mint_y, &trader.pubkey());
+    mint_tokens(svm, &ctx.initializer, &ctx.mint_y, &wallet_y_ata, 1000_000_000);
+
+    let mut data = Vec::with_capacity(27);
+    data.push(3u8); // Swap discriminator
+    data.extend_from_slice(&swap_amount.to_le_bytes());
+    data.extend_from_slice(&1u64.to_le_bytes()); // min_out
+    data.extend_from_slice(&1_800_000_000i64.to_le_bytes());
+    data.push(target_token);
+    data.push(1); // native_sol
+
+    let mut accounts = vec![
+        AccountMeta::new(trader.pubkey(), true),
+        AccountMeta::new(ctx.vault_x_ata, false),
+        AccountMeta::new(ctx.vault_y_ata, false),
+        AccountMeta::new(wallet_x_ata, false),
+        AccountMeta::new(wallet_y_ata, false),
+        AccountMeta::new(ctx.config_pda, false),
+        AccountMeta::new(ctx.lp_mint_pda, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+    ];
+    accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
+
+    let instruction = Instruction::new_with_bytes(ctx.program_id, &data, accounts);
+    let tx = Transaction::new(
+        &[&trader],
+        Message::new(&[instruction], Some(&trader.pubkey())),
+        svm.latest_blockhash(),
+    );
+
+    let sol = |svm: &LiteSVM| {
+        [trader.pubkey(), wallet_x_ata].iter()
+            .map(|address| svm.get_account(address).map_or(0, |account| account.lamports as i64))
+            .sum::<i64>()
+    };
+    let sol_before = sol(svm);
+    let result = svm.send_transaction(tx);
+    (sol(svm) - sol_before, result.map(|meta| meta.return_data.data).unwrap_or_default())
+}
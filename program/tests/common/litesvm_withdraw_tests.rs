@@ -22,29 +22,72 @@ use crate::common::context::{DepositTestContext, AmmTestContext};
 use crate::common::litesvm_deposit_tests::deposit_liquidity;
 #[path="./litesvm_setup.rs"]
 mod litesvm_setup;
-use litesvm_setup::{get_token_balance};
+use litesvm_setup::{get_token_balance, create_ata, temporary_wsol_account};
 
 
-pub fn withdraw_liquidity(ctx: &mut AmmTestContext, deposit: &DepositTestContext) {
+/// Returns the withdraw transaction's return data, empty if it failed.
+pub fn withdraw_liquidity(ctx: &mut AmmTestContext, deposit: &DepositTestContext) -> Vec<u8> {
+    withdraw_lp(ctx, deposit, 20_000)
+}
+
+/// Burns `lp_to_burn` of the depositor's LP tokens for balanced amounts of x and y.
+pub fn withdraw_lp(ctx: &mut AmmTestContext, deposit: &DepositTestContext, lp_to_burn: u64) -> Vec<u8> {
+    send_withdraw(ctx, deposit, lp_to_burn, 100_000, 0, 0, 0)
+}
+
+/// Balanced withdrawal paying the native mint side out in SOL, through the pool's temporary WSOL account.
+pub fn withdraw_lp_native_sol(ctx: &mut AmmTestContext, deposit: &DepositTestContext, lp_to_burn: u64) -> Vec<u8> {
+    send_withdraw(ctx, deposit, lp_to_burn, 100_000, 0, 0, 1)
+}
+
+/// Single sided withdrawal of exactly `amount_of_x` to the depositor, the pool works out the LP to burn.
+pub fn withdraw_exact_x(ctx: &mut AmmTestContext, deposit: &DepositTestContext, amount_of_x: u64) -> Vec<u8> {
+    send_withdraw(ctx, deposit, 0, amount_of_x, 0, 1, 0)
+}
+
+fn send_withdraw(
+    ctx: &mut AmmTestContext, deposit: &DepositTestContext,
+    lp_to_burn: u64, amount_of_x: u64, amount_of_y: u64, withdraw_mode: u8, native_sol: u8,
+) -> Vec<u8> {
+    // SOL is received in the pool's temporary WSOL account, other tokens in the depositor's ATAs.
+    // A native side deposited in SOL left the depositor without an ATA for it.
+    let mut user_accounts = [deposit.user_x_ata, deposit.user_y_ata];
+    for (user_account, mint) in user_accounts.iter_mut().zip([ctx.mint_x, ctx.mint_y]) {
+        if native_sol == 1 && mint == spl_token::native_mint::ID {
+            *user_account = temporary_wsol_account(&ctx.program_id, &deposit.user.pubkey(), &mint);
+        } else if *user_account == temporary_wsol_account(&ctx.program_id, &deposit.user.pubkey(), &mint) {
+            *user_account = create_ata(&mut ctx.svm, &deposit.user, &mint, &deposit.user.pubkey());
+        }
+    }
 
     let mut withdraw_ix_data = vec![2u8];
-    withdraw_ix_data.extend_from_slice(&20_000u64.to_le_bytes()); // lp_to_burn u64
-    withdraw_ix_data.extend_from_slice(&100_000u64.to_le_bytes()); // amount_of_x u64
-    withdraw_ix_data.extend_from_slice(&0u64.to_le_bytes()); // amount_of_y u64
+    withdraw_ix_data.extend_from_slice(&lp_to_burn.to_le_bytes()); // lp_to_burn u64
+    withdraw_ix_data.extend_from_slice(&amount_of_x.to_le_bytes()); // amount_of_x u64
+    withdraw_ix_data.extend_from_slice(&amount_of_y.to_le_bytes()); // amount_of_y u64
     withdraw_ix_data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // expiration.
-    withdraw_ix_data.push(0u8);
+    withdraw_ix_data.push(withdraw_mode);
+    if native_sol == 1 {
+        withdraw_ix_data.push(native_sol);
+    }
 
-    let withdraw_accounts = vec![
+    let mut withdraw_accounts = vec![
         AccountMeta::new(deposit.user.pubkey(), true),
         AccountMeta::new(ctx.lp_mint_pda, false),
         AccountMeta::new(ctx.vault_x_ata, false),
         AccountMeta::new(ctx.vault_y_ata, false),
-        AccountMeta::new(deposit.user_x_ata, false),
-        AccountMeta::new(deposit.user_y_ata, false),
+        AccountMeta::new(user_accounts[0], false),
+        AccountMeta::new(user_accounts[1], false),
         AccountMeta::new(deposit.user_lp_ata, false),
         AccountMeta::new(ctx.config_pda, false),
-        AccountMeta::new_readonly(pinocchio_token::ID, false),
+        AccountMeta::new_readonly(ctx.token_program, false),
+        AccountMeta::new(ctx.observations_pda, false),
+        AccountMeta::new_readonly(ctx.mint_x, false),
+        AccountMeta::new_readonly(ctx.mint_y, false),
     ];
+    if native_sol == 1 {
+        withdraw_accounts.push(AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false));
+    }
+    withdraw_accounts.extend(ctx.transfer_hook_accounts.iter().cloned());
 
     let withdraw_ix = Instruction::new_with_bytes(
         ctx.program_id,
@@ -65,4 +108,5 @@ pub fn withdraw_liquidity(ctx: &mut AmmTestContext, deposit: &DepositTestContext
     let vault_y_balance = get_token_balance(&ctx.svm, &ctx.vault_y_ata);
     //println!("The vault x balance after pool withdrawal: {}", vault_x_balance);
     //println!("The vault y balance after pool withdrawal: {}", vault_y_balance);
+    withdraw_res.map(|meta| meta.return_data.data).unwrap_or_default()
 }
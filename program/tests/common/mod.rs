@@ -3,3 +3,8 @@ pub mod context;
 pub mod litesvm_setup;
 pub mod litesvm_withdraw_tests;
 pub mod litesvm_swap_tests;
+pub mod litesvm_oracle_tests;
+pub mod litesvm_quote_tests;
+pub mod litesvm_event_tests;
+pub mod litesvm_reserve_tests;
+pub mod litesvm_flash_tests;
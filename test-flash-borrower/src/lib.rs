@@ -0,0 +1,89 @@
+//! Flash loan and flash swap callee used by the program's LiteSVM tests. The pool calls it back
+//! once the tokens are out, it pays the vaults the amounts in its instruction data with the
+//! authority's signature, so tests choose whether a loan is repaid in full, short or not at all.
+//! Accounts after the payments are forwarded to every transfer, for mints with transfer hooks.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use pinocchio::{
+    AccountView, Address, ProgramResult, error::ProgramError,
+    cpi::invoke_with_bounds,
+    instruction::{InstructionAccount, InstructionView},
+};
+
+// `TransferChecked` discriminator of the token programs.
+const TRANSFER_CHECKED: u8 = 12;
+// Offset of the decimals in a mint account.
+const MINT_DECIMALS_OFFSET: usize = 44;
+// Largest number of transfer hook accounts forwarded to a payment.
+const MAX_HOOK_ACCOUNTS: usize = 16;
+
+#[cfg(all(not(feature = "std"), not(feature = "no-entrypoint")))]
+mod entrypoint {
+    use pinocchio::{default_allocator, nostd_panic_handler, program_entrypoint};
+
+    default_allocator!();
+    nostd_panic_handler!();
+    program_entrypoint!(super::process_instruction);
+}
+
+pub fn process_instruction(
+    _program_id: &Address,
+    accounts: &[AccountView],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    // The token program and the authority signing the payments, then a source, mint and
+    // destination for each payment and the transfer hook accounts. The instruction data holds
+    // one u64 amount per payment.
+    let [token_program, authority, rest @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if !instruction_data.len().is_multiple_of(8) {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (payments, hook_accounts) = rest
+        .split_at_checked(instruction_data.len() / 8 * 3)
+        .ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if hook_accounts.len() > MAX_HOOK_ACCOUNTS {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    for (payment, amount) in payments.chunks_exact(3).zip(instruction_data.chunks_exact(8)) {
+        let [source, mint, destination] = payment else { unreachable!() };
+        let amount = u64::from_le_bytes(amount.try_into().unwrap());
+        if amount == 0 {
+            continue;
+        }
+        let decimals = *mint.try_borrow()?.get(MINT_DECIMALS_OFFSET).ok_or(ProgramError::InvalidAccountData)?;
+
+        let mut data = [0u8; 10];
+        data[0] = TRANSFER_CHECKED;
+        data[1..9].copy_from_slice(&amount.to_le_bytes());
+        data[9] = decimals;
+        const MAX_ACCOUNTS: usize = 4 + MAX_HOOK_ACCOUNTS;
+        let account_count = 4 + hook_accounts.len();
+        let account_views: [&AccountView; MAX_ACCOUNTS] = core::array::from_fn(|index| match index {
+            0 => source,
+            1 => mint,
+            2 => destination,
+            3 => authority,
+            _ => hook_accounts.get(index - 4).unwrap_or(mint),
+        });
+        let instruction_accounts: [InstructionAccount; MAX_ACCOUNTS] = core::array::from_fn(|index| match index {
+            0 => InstructionAccount::writable(source.address()),
+            1 => InstructionAccount::readonly(mint.address()),
+            2 => InstructionAccount::writable(destination.address()),
+            3 => InstructionAccount::readonly_signer(authority.address()),
+            _ => {
+                let account = account_views[index];
+                InstructionAccount::new(account.address(), account.is_writable(), account.is_signer())
+            },
+        });
+        let transfer_ix = InstructionView {
+            program_id: token_program.address(),
+            accounts: &instruction_accounts[..account_count],
+            data: &data,
+        };
+        invoke_with_bounds::<MAX_ACCOUNTS>(&transfer_ix, &account_views[..account_count])?;
+    }
+    Ok(())
+}
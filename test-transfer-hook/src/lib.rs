@@ -0,0 +1,41 @@
+//! Transfer hook used by the program's LiteSVM tests. Token-2022 invokes it on every transfer
+//! of a mint pointing to it, it counts the transfers in the one account its
+//! `ExtraAccountMetaList` lists, so tests can tell the pool forwarded the hook accounts.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use pinocchio::{AccountView, Address, ProgramResult, error::ProgramError};
+
+// First 8 bytes of sha256("spl-transfer-hook-interface:execute"). Also the discriminator
+// of the `ExtraAccountMetaList` entry listing the accounts `Execute` needs.
+pub const EXECUTE_DISCRIMINATOR: [u8; 8] = [105, 37, 101, 197, 75, 251, 102, 26];
+
+#[cfg(all(not(feature = "std"), not(feature = "no-entrypoint")))]
+mod entrypoint {
+    use pinocchio::{default_allocator, nostd_panic_handler, program_entrypoint};
+
+    default_allocator!();
+    nostd_panic_handler!();
+    program_entrypoint!(super::process_instruction);
+}
+
+pub fn process_instruction(
+    program_id: &Address,
+    accounts: &[AccountView],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    // `Execute` carries the amount transferred after the discriminator.
+    if instruction_data.len() != 16 || instruction_data[..8] != EXECUTE_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    // Source, mint, destination and authority, the `ExtraAccountMetaList`, then the counter.
+    let [_source, _mint, _destination, _authority, _extra_account_metas, counter, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    if !counter.owned_by(program_id) || !counter.is_writable() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mut data = counter.try_borrow_mut()?;
+    let count = u64::from_le_bytes(data[..8].try_into().unwrap()) + 1;
+    data[..8].copy_from_slice(&count.to_le_bytes());
+    Ok(())
+}